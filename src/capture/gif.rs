@@ -0,0 +1,429 @@
+//! Animated GIF encoder. The frames are streamed to the output as
+//! they come so long clips don't have to be kept in memory.
+//!
+//! Game Boy frames only use a handful of colors and change little
+//! from one frame to the next so we only encode the rectangle that
+//! changed since the previous frame and merge identical frames into
+//! a single longer one. Each rectangle comes with its own color
+//! table since the LCD ghosting and the palette changes can add new
+//! colors at any time.
+
+use std::io::{Write, Result};
+
+use gpu::{SCREEN_WIDTH, SCREEN_HEIGHT, CYCLES_PER_FRAME};
+use ui::palette::Rgb;
+
+pub struct GifWriter<W: Write> {
+    out:        W,
+    /// Image currently displayed by the decoder, i.e. the result of
+    /// all the frames written so far
+    canvas:     Vec<Rgb>,
+    /// Frame waiting for the next one to know its duration
+    pending:    Option<Vec<Rgb>>,
+    /// Time of `pending` in centiseconds since the beginning
+    pending_cs: u64,
+    /// Emulated time of the first frame and of the last one, in
+    /// SysClk ticks
+    start:      u64,
+    last:       u64,
+    /// Number of emulated frames received
+    frames:     u64,
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Write the GIF header
+    pub fn new(mut out: W) -> Result<GifWriter<W>> {
+        let mut header = Vec::new();
+
+        header.extend(b"GIF89a".iter().cloned());
+        push_u16(&mut header, SCREEN_WIDTH as u16);
+        push_u16(&mut header, SCREEN_HEIGHT as u16);
+        // No global color table, 8 bits per component
+        header.push(0x70);
+        // Background color index and pixel aspect ratio
+        header.push(0);
+        header.push(0);
+
+        // Netscape extension to loop forever
+        header.extend(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00".iter().cloned());
+
+        try!(out.write_all(&header));
+
+        Ok(GifWriter {
+            out:        out,
+            canvas:     Vec::new(),
+            pending:    None,
+            pending_cs: 0,
+            start:      0,
+            last:       0,
+            frames:     0,
+        })
+    }
+
+    /// Add a frame completed at `time` (in SysClk ticks). The frames
+    /// are displayed for as long as the emulated time between them,
+    /// so the previous frame stays up while the LCD is off.
+    pub fn push_frame(&mut self, pixels: &[Rgb], time: u64) -> Result<()> {
+        if self.frames == 0 {
+            self.start = time;
+        }
+
+        self.last    = time;
+        self.frames += 1;
+
+        let now = self.time_cs(time);
+
+        if self.pending.as_ref().map(|p| &p[..]) == Some(pixels) {
+            // Nothing changed, just make the pending frame last longer
+            return Ok(());
+        }
+
+        // Most decoders don't honor delays shorter than 2
+        // centiseconds so we drop the frames that would be displayed
+        // for less than that.
+        if self.pending.is_some() && now - self.pending_cs < MIN_DELAY_CS {
+            self.pending = Some(pixels.to_vec());
+            return Ok(());
+        }
+
+        try!(self.write_pending(now));
+
+        self.pending    = Some(pixels.to_vec());
+        self.pending_cs = now;
+
+        Ok(())
+    }
+
+    /// Write the last frame and the trailer. Returns the number of
+    /// frames received.
+    pub fn finish(mut self) -> Result<u64> {
+        // The last frame lasts one frame period
+        let now = self.time_cs(self.last + CYCLES_PER_FRAME as u64);
+        let end = ::std::cmp::max(now, self.pending_cs + MIN_DELAY_CS);
+
+        try!(self.write_pending(end));
+        try!(self.out.write_all(&[0x3b]));
+        try!(self.out.flush());
+
+        Ok(self.frames)
+    }
+
+    /// Convert the emulated `time` into centiseconds since the first
+    /// frame. It's computed from the total tick count to avoid
+    /// accumulating rounding errors.
+    fn time_cs(&self, time: u64) -> u64 {
+        (time - self.start) * 100 / ::SYSCLK_FREQ as u64
+    }
+
+    /// Write the pending frame (if any) to last until `end`
+    fn write_pending(&mut self, end: u64) -> Result<()> {
+        let image =
+            match self.pending.take() {
+                Some(i) => i,
+                None    => return Ok(()),
+            };
+
+        let width = SCREEN_WIDTH as usize;
+
+        // Bounding box of the pixels that changed since the previous
+        // frame. The first frame is always written completely.
+        let (left, top, right, bottom) =
+            if self.canvas.is_empty() {
+                (0, 0, width, SCREEN_HEIGHT as usize)
+            } else {
+                let mut bb = (width, SCREEN_HEIGHT as usize, 0, 0);
+
+                for (i, (a, b)) in image.iter().zip(self.canvas.iter()).enumerate() {
+                    if a != b {
+                        let (x, y) = (i % width, i / width);
+
+                        bb.0 = ::std::cmp::min(bb.0, x);
+                        bb.1 = ::std::cmp::min(bb.1, y);
+                        bb.2 = ::std::cmp::max(bb.2, x + 1);
+                        bb.3 = ::std::cmp::max(bb.3, y + 1);
+                    }
+                }
+
+                bb
+            };
+
+        let mut rect = Vec::with_capacity((right - left) * (bottom - top));
+
+        for y in top..bottom {
+            rect.extend(image[y * width + left..y * width + right].iter().cloned());
+        }
+
+        let (table, pixels) = index_colors(&rect);
+
+        // The color table size must be a power of two, LZW needs at
+        // least 2 bits per pixel.
+        let mut table_bits = 2;
+
+        while (1 << table_bits) < table.len() {
+            table_bits += 1;
+        }
+
+        let mut block = Vec::new();
+
+        // Graphic control extension: the frame is drawn over the
+        // previous one (disposal method 1)
+        block.extend([0x21, 0xf9, 0x04, 0x04].iter().cloned());
+        push_u16(&mut block, (end - self.pending_cs) as u16);
+        block.push(0);
+        block.push(0);
+
+        // Image descriptor with a local color table
+        block.push(0x2c);
+        push_u16(&mut block, left as u16);
+        push_u16(&mut block, top as u16);
+        push_u16(&mut block, (right - left) as u16);
+        push_u16(&mut block, (bottom - top) as u16);
+        block.push(0x80 | (table_bits - 1));
+
+        for i in 0..(1 << table_bits) {
+            let rgb = table.get(i).cloned().unwrap_or([0; 3]);
+
+            block.extend(rgb.iter().cloned());
+        }
+
+        block.push(table_bits);
+
+        // The LZW stream is split in blocks of at most 255 bytes
+        for chunk in lzw_encode(&pixels, table_bits).chunks(255) {
+            block.push(chunk.len() as u8);
+            block.extend(chunk.iter().cloned());
+        }
+
+        block.push(0);
+
+        try!(self.out.write_all(&block));
+
+        self.canvas = image;
+
+        Ok(())
+    }
+}
+
+/// Build a color table for `image` and convert its pixels into
+/// indices in that table. If there are more than 256 colors the
+/// extra ones are replaced by the closest color in the table.
+fn index_colors(image: &[Rgb]) -> (Vec<Rgb>, Vec<u8>) {
+    let mut table: Vec<Rgb> = Vec::new();
+
+    for p in image {
+        if table.len() < MAX_COLORS && !table.contains(p) {
+            table.push(*p);
+        }
+    }
+
+    let distance = |a: &Rgb, b: &Rgb| {
+        (0..3).fold(0, |d, c| {
+            let delta = a[c] as i32 - b[c] as i32;
+
+            d + delta * delta
+        })
+    };
+
+    let pixels =
+        image.iter().map(|p| {
+            let i =
+                match table.iter().position(|t| t == p) {
+                    Some(i) => i,
+                    None    => table.iter()
+                        .enumerate()
+                        .min_by_key(|&(_, t)| distance(t, p))
+                        .map(|(i, _)| i)
+                        .unwrap_or(0),
+                };
+
+            i as u8
+        })
+        .collect();
+
+    (table, pixels)
+}
+
+/// Compress `pixels` using GIF's variant of LZW with `min_bits`-bit
+/// literals
+fn lzw_encode(pixels: &[u8], min_bits: u8) -> Vec<u8> {
+    let clear = 1u16 << min_bits;
+    let eoi   = clear + 1;
+
+    let mut w = BitWriter::new();
+
+    // Code for each (prefix code, pixel) pair
+    let mut table = vec![0u16; MAX_CODES * clear as usize];
+    // Last code assigned
+    let mut hi    = eoi;
+    let mut bits  = min_bits + 1;
+
+    w.write(clear, bits);
+
+    let mut code =
+        match pixels.first() {
+            Some(&p) => p as u16,
+            None     => {
+                w.write(eoi, bits);
+                return w.finish();
+            }
+        };
+
+    for &p in &pixels[1..] {
+        let entry = code as usize * clear as usize + p as usize;
+
+        if table[entry] != 0 {
+            code = table[entry];
+            continue;
+        }
+
+        w.write(code, bits);
+
+        code = p as u16;
+
+        hi += 1;
+
+        if hi == 1 << bits {
+            bits += 1;
+        }
+
+        if hi as usize == MAX_CODES - 1 {
+            // Table full, start over
+            w.write(clear, bits);
+
+            for t in table.iter_mut() {
+                *t = 0;
+            }
+
+            hi   = eoi;
+            bits = min_bits + 1;
+        } else {
+            table[entry] = hi;
+        }
+    }
+
+    w.write(code, bits);
+
+    // The decoder adds an entry after this code, which might make
+    // the code size grow
+    hi += 1;
+
+    if hi == 1 << bits && bits < 12 {
+        bits += 1;
+    }
+
+    w.write(eoi, bits);
+
+    w.finish()
+}
+
+/// GIF bitstream writer, codes are packed starting from the LSB
+struct BitWriter {
+    out:   Vec<u8>,
+    acc:   u32,
+    nbits: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            out:   Vec::new(),
+            acc:   0,
+            nbits: 0,
+        }
+    }
+
+    fn write(&mut self, code: u16, bits: u8) {
+        self.acc |= (code as u32) << self.nbits;
+        self.nbits += bits;
+
+        while self.nbits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+
+        self.out
+    }
+}
+
+/// GIF integers are little endian
+fn push_u16(v: &mut Vec<u8>, n: u16) {
+    v.push(n as u8);
+    v.push((n >> 8) as u8);
+}
+
+/// Maximum number of LZW codes (12 bits)
+const MAX_CODES: usize = 4096;
+
+/// Shortest frame duration, in centiseconds
+const MIN_DELAY_CS: u64 = 2;
+
+/// Size of the largest GIF color table
+const MAX_COLORS: usize = 256;
+
+#[cfg(test)]
+mod tests {
+    use super::{GifWriter, lzw_encode};
+    use gpu::{SCREEN_WIDTH, SCREEN_HEIGHT, CYCLES_PER_FRAME};
+
+    #[test]
+    fn lzw() {
+        // Example from the GIF89a specification walkthroughs: a
+        // 10x10 image with 3 large areas of solid colors.
+        let mut pixels = Vec::new();
+
+        for y in 0..10 {
+            for x in 0..10 {
+                let p = match (x, y) {
+                    (0...4, 0...2) | (0...2, 3...4) => 1,
+                    (5...9, 0...2) | (7...9, 3...4) => 2,
+                    (0...2, 5...6) | (0...4, 7...9) => 2,
+                    (7...9, 5...6) | (5...9, 7...9) => 1,
+                    _ => 0,
+                };
+
+                pixels.push(p);
+            }
+        }
+
+        let expected = [0x8c, 0x2d, 0x99, 0x87, 0x2a, 0x1c, 0xdc, 0x33,
+                        0xa0, 0x02, 0x75, 0xec, 0x95, 0xfa, 0xa8, 0xde,
+                        0x60, 0x8c, 0x04, 0x91, 0x4c, 0x01];
+
+        assert!(lzw_encode(&pixels, 2) == &expected[..]);
+    }
+
+    #[test]
+    fn timeline() {
+        let size = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+        let frame = CYCLES_PER_FRAME as u64;
+
+        let mut out = Vec::new();
+
+        {
+            let mut gif = GifWriter::new(&mut out).unwrap();
+
+            gif.push_frame(&vec![[0, 0, 0]; size], 1000).unwrap();
+            // The LCD is off for 9 frames
+            gif.push_frame(&vec![[255, 255, 255]; size], 1000 + 10 * frame).unwrap();
+
+            assert!(gif.finish().unwrap() == 2);
+        }
+
+        // Delays of the graphic control extensions
+        let delays: Vec<u16> =
+            out.windows(6)
+            .filter(|w| w[0..3] == [0x21, 0xf9, 0x04])
+            .map(|w| w[4] as u16 | (w[5] as u16) << 8)
+            .collect();
+
+        // The first frame lasts until the LCD is turned back on
+        assert!(delays == vec![16, 2]);
+    }
+}
@@ -0,0 +1,131 @@
+//! Capture of the emulator output: screenshots and recordings
+
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{BufWriter, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use gpu::{Frame, SCREEN_WIDTH, SCREEN_HEIGHT};
+use ui::palette::{Palette, Rgb};
+
+pub mod png;
+pub mod gif;
+pub mod recorder;
+
+mod zlib;
+mod y4m;
+mod wav;
+
+/// Convert `frame` into RGB triplets using `palette`. Each pixel is
+/// repeated `scale` times horizontally and vertically.
+pub fn frame_to_rgb(frame: &Frame, palette: &Palette, scale: u32) -> Vec<u8> {
+    pixels_to_rgb(&palette.render(frame), scale)
+}
+
+/// Flatten the screen `pixels` into RGB triplets. Each pixel is
+/// repeated `scale` times horizontally and vertically.
+pub fn pixels_to_rgb(pixels: &[Rgb], scale: u32) -> Vec<u8> {
+    let width  = SCREEN_WIDTH  * scale;
+    let height = SCREEN_HEIGHT * scale;
+
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (x, y) = (x / scale, y / scale);
+
+            let c = pixels[(y * SCREEN_WIDTH + x) as usize];
+
+            rgb.extend(c.iter().cloned());
+        }
+    }
+
+    rgb
+}
+
+/// Save the screen `pixels` as a PNG image in `dir`, upscaled by
+/// `scale`. The file is named after the game and the current
+/// time. Returns the path of the new file.
+pub fn save_screenshot(dir: &Path,
+                       game: &str,
+                       pixels: &[Rgb],
+                       scale: u32) -> Result<PathBuf> {
+    let path = unique_path(dir, game, "png");
+
+    let rgb = pixels_to_rgb(pixels, scale);
+
+    let mut file = BufWriter::new(try!(File::create(&path)));
+
+    try!(png::write_png(&mut file,
+                        SCREEN_WIDTH  * scale,
+                        SCREEN_HEIGHT * scale,
+                        &rgb));
+
+    Ok(path)
+}
+
+/// Build a path in `dir` for a new capture file named after `game`
+/// and the current time, making sure it doesn't already exist.
+pub fn unique_path(dir: &Path, game: &str, extension: &str) -> PathBuf {
+    // Only keep characters that are safe in file names
+    let game: String = game.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let game = if game.is_empty() { "gb-rs".to_string() } else { game };
+
+    let base = format!("{}-{}", game, timestamp());
+
+    let mut path = dir.join(format!("{}.{}", base, extension));
+    let mut n = 1;
+
+    // Several captures can happen in the same second
+    while path.exists() {
+        path = dir.join(format!("{}-{}.{}", base, n, extension));
+        n += 1;
+    }
+
+    path
+}
+
+/// Return the current UTC time formated as YYYYMMDD-HHMMSS
+fn timestamp() -> String {
+    let secs = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d)  => d.as_secs(),
+        Err(_) => 0,
+    };
+
+    let days = (secs / 86400) as i64;
+    let rem  = secs % 86400;
+
+    let (year, month, day) = civil_from_days(days);
+
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}",
+            year, month, day, rem / 3600, (rem / 60) % 60, rem % 60)
+}
+
+/// Convert a number of days since 1970-01-01 into a (year, month,
+/// day) date. Algorithm from Howard Hinnant's date library.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z   = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp  = (5 * doy + 2) / 153;
+    let d   = doy - (153 * mp + 2) / 5 + 1;
+    let m   = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y   = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    (y, m as u32, d as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn civil_from_days() {
+        assert!(super::civil_from_days(0) == (1970, 1, 1));
+        assert!(super::civil_from_days(11016) == (2000, 2, 29));
+        assert!(super::civil_from_days(20744) == (2026, 10, 18));
+    }
+}
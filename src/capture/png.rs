@@ -0,0 +1,77 @@
+//! PNG image encoder
+
+use std::io::{Write, Result};
+
+use capture::zlib;
+use crc::crc32_update;
+
+/// Write a `width`x`height` image to `w`. `rgb` contains 3 bytes per
+/// pixel in row-major order.
+pub fn write_png<W: Write>(w: &mut W,
+                           width: u32,
+                           height: u32,
+                           rgb: &[u8]) -> Result<()> {
+    assert!(rgb.len() == (width * height * 3) as usize);
+
+    try!(w.write_all(&SIGNATURE));
+
+    let mut ihdr = Vec::with_capacity(13);
+
+    push_u32(&mut ihdr, width);
+    push_u32(&mut ihdr, height);
+    // Bit depth
+    ihdr.push(8);
+    // Color type: RGB triplets
+    ihdr.push(2);
+    // Compression, filter and interlace methods
+    ihdr.push(0);
+    ihdr.push(0);
+    ihdr.push(0);
+
+    try!(write_chunk(w, b"IHDR", &ihdr));
+
+    // Each scanline is prefixed with its filter type, we don't use
+    // any filtering.
+    let stride = (width * 3) as usize;
+
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+
+    for line in rgb.chunks(stride) {
+        raw.push(0);
+        raw.extend(line.iter().cloned());
+    }
+
+    try!(write_chunk(w, b"IDAT", &zlib::compress(&raw)));
+    try!(write_chunk(w, b"IEND", &[]));
+
+    Ok(())
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8], data: &[u8]) -> Result<()> {
+    let mut header = Vec::with_capacity(8);
+
+    push_u32(&mut header, data.len() as u32);
+    header.extend(kind.iter().cloned());
+
+    try!(w.write_all(&header));
+    try!(w.write_all(data));
+
+    // The CRC covers the chunk type and data but not the length
+    let crc = crc32_update(crc32_update(0, kind), data);
+
+    let mut footer = Vec::with_capacity(4);
+    push_u32(&mut footer, crc);
+
+    w.write_all(&footer)
+}
+
+/// PNG integers are big endian
+fn push_u32(v: &mut Vec<u8>, n: u32) {
+    v.push((n >> 24) as u8);
+    v.push((n >> 16) as u8);
+    v.push((n >> 8)  as u8);
+    v.push(n as u8);
+}
+
+/// Magic number at the beginning of all PNG files
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
@@ -0,0 +1,137 @@
+//! Audio and video recording. Everything is driven by the emulated
+//! time so the recording doesn't depend on the speed of the host:
+//! exactly one video frame is written every `CYCLES_PER_FRAME`
+//! ticks and the audio is resampled from the SPU sample clock.
+
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{BufWriter, Result};
+
+use gpu::{SCREEN_WIDTH, SCREEN_HEIGHT, CYCLES_PER_FRAME};
+use spu::{Sample, SAMPLER_DIVIDER, SAMPLE_MAX};
+use ui::palette::Rgb;
+use capture::y4m::Y4mWriter;
+use capture::wav::WavWriter;
+
+pub struct Recorder {
+    video:         Y4mWriter<BufWriter<File>>,
+    audio:         WavWriter<BufWriter<File>>,
+    /// SysClk ticks elapsed since the last video frame was written
+    frame_cycles:  u32,
+    /// SPU samples not yet consumed by the resampler
+    pending:       Vec<Sample>,
+    /// Index (in the SPU sample stream) of `pending[0]`
+    pending_base:  u64,
+    /// Number of audio samples written so far
+    audio_samples: u64,
+    /// Number of video frames written so far
+    frames:        u64,
+}
+
+impl Recorder {
+    /// Start a new recording in `<base>.y4m` and `<base>.wav`
+    pub fn new(base: &Path) -> Result<Recorder> {
+        let (video, audio) = Recorder::paths(base);
+
+        let video = try!(Y4mWriter::new(BufWriter::new(try!(File::create(video))),
+                                        SCREEN_WIDTH,
+                                        SCREEN_HEIGHT,
+                                        ::SYSCLK_FREQ as u32,
+                                        CYCLES_PER_FRAME));
+
+        let audio = try!(WavWriter::new(BufWriter::new(try!(File::create(audio))),
+                                        AUDIO_RATE,
+                                        1));
+
+        Ok(Recorder {
+            video:         video,
+            audio:         audio,
+            frame_cycles:  0,
+            pending:       Vec::new(),
+            pending_base:  0,
+            audio_samples: 0,
+            frames:        0,
+        })
+    }
+
+    /// Return the paths of the video and audio files for `base`
+    pub fn paths(base: &Path) -> (PathBuf, PathBuf) {
+        (base.with_extension("y4m"), base.with_extension("wav"))
+    }
+
+    /// Advance the recording by `cycles` SysClk ticks. `pixels` is
+    /// the image currently on screen, it's written once for each
+    /// frame period elapsed. If the LCD is off the same frame is
+    /// repeated.
+    pub fn step(&mut self, cycles: u32, pixels: &[Rgb]) -> Result<()> {
+        self.frame_cycles += cycles;
+
+        while self.frame_cycles >= CYCLES_PER_FRAME {
+            self.frame_cycles -= CYCLES_PER_FRAME;
+
+            let rgb: Vec<u8> =
+                pixels.iter().flat_map(|p| p.iter().cloned()).collect();
+
+            try!(self.video.write_frame(&rgb));
+
+            self.frames += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Add the samples generated by the SPU. They're resampled to
+    /// `AUDIO_RATE` using linear interpolation.
+    pub fn push_samples(&mut self, samples: &[Sample]) -> Result<()> {
+        self.pending.extend(samples.iter().cloned());
+
+        // Output sample `n` sits at position n * SYSCLK_FREQ /
+        // (SAMPLER_DIVIDER * AUDIO_RATE) in the SPU sample stream. We
+        // use integer arithmetic to avoid any drift.
+        let den = SAMPLER_DIVIDER as u64 * AUDIO_RATE as u64;
+
+        let mut out = Vec::new();
+
+        loop {
+            let pos = self.audio_samples * ::SYSCLK_FREQ as u64;
+
+            let i = (pos / den - self.pending_base) as usize;
+
+            if i + 1 >= self.pending.len() {
+                break;
+            }
+
+            let frac = (pos % den) as f32 / den as f32;
+
+            let a = self.pending[i]     as f32;
+            let b = self.pending[i + 1] as f32;
+
+            let s = a + (b - a) * frac;
+
+            out.push((s * 32767. / SAMPLE_MAX as f32) as i16);
+
+            self.audio_samples += 1;
+        }
+
+        // Drop the samples we won't need anymore
+        let next = self.audio_samples * ::SYSCLK_FREQ as u64 / den;
+        let consumed = (next - self.pending_base) as usize;
+
+        self.pending.drain(..consumed);
+        self.pending_base = next;
+
+        self.audio.write_samples(&out)
+    }
+
+    /// Flush both streams and complete the audio file header. Returns
+    /// the number of video frames written.
+    pub fn finish(mut self) -> Result<u64> {
+        try!(self.video.flush());
+        try!(self.audio.finish());
+
+        Ok(self.frames)
+    }
+}
+
+/// Sample rate of the recorded audio
+const AUDIO_RATE: u32 = 48000;
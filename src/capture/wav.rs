@@ -0,0 +1,116 @@
+//! WAV audio file writer, 16bit signed PCM
+
+use std::io::{Write, Seek, SeekFrom, Result};
+
+pub struct WavWriter<W: Write + Seek> {
+    out:      W,
+    channels: u16,
+    /// Number of bytes of sample data written so far
+    len:      u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Write the file header. The chunk sizes are filled by
+    /// `finish`.
+    pub fn new(mut out: W, sample_rate: u32, channels: u16) -> Result<WavWriter<W>> {
+        let block_align = channels * 2;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+
+        header.extend(b"RIFF".iter().cloned());
+        push_u32(&mut header, 0);
+        header.extend(b"WAVE".iter().cloned());
+
+        header.extend(b"fmt ".iter().cloned());
+        push_u32(&mut header, 16);
+        // PCM
+        push_u16(&mut header, 1);
+        push_u16(&mut header, channels);
+        push_u32(&mut header, sample_rate);
+        push_u32(&mut header, sample_rate * block_align as u32);
+        push_u16(&mut header, block_align);
+        // Bits per sample
+        push_u16(&mut header, 16);
+
+        header.extend(b"data".iter().cloned());
+        push_u32(&mut header, 0);
+
+        try!(out.write_all(&header));
+
+        Ok(WavWriter {
+            out:      out,
+            channels: channels,
+            len:      0,
+        })
+    }
+
+    /// Write interleaved samples
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        assert!(samples.len() % self.channels as usize == 0);
+
+        let mut data = Vec::with_capacity(samples.len() * 2);
+
+        for &s in samples {
+            push_u16(&mut data, s as u16);
+        }
+
+        self.len += data.len() as u32;
+
+        self.out.write_all(&data)
+    }
+
+    /// Fill the chunk sizes in the header now that the length of the
+    /// stream is known
+    pub fn finish(&mut self) -> Result<()> {
+        let mut size = Vec::with_capacity(4);
+
+        push_u32(&mut size, HEADER_SIZE - 8 + self.len);
+        try!(self.out.seek(SeekFrom::Start(4)));
+        try!(self.out.write_all(&size));
+
+        size.clear();
+        push_u32(&mut size, self.len);
+        try!(self.out.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4)));
+        try!(self.out.write_all(&size));
+
+        try!(self.out.seek(SeekFrom::End(0)));
+        self.out.flush()
+    }
+}
+
+/// WAV integers are little endian
+fn push_u16(v: &mut Vec<u8>, n: u16) {
+    v.push(n as u8);
+    v.push((n >> 8) as u8);
+}
+
+fn push_u32(v: &mut Vec<u8>, n: u32) {
+    push_u16(v, n as u16);
+    push_u16(v, (n >> 16) as u16);
+}
+
+/// Size of the RIFF, fmt and data headers
+const HEADER_SIZE: u32 = 44;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::WavWriter;
+
+    #[test]
+    fn header() {
+        let mut w = WavWriter::new(Cursor::new(Vec::new()), 48000, 1).unwrap();
+
+        w.write_samples(&[0, -1, 0x1234]).unwrap();
+        w.finish().unwrap();
+
+        let data = w.out.into_inner();
+
+        assert!(data.len() == 44 + 6);
+        assert!(&data[0..4] == b"RIFF");
+        assert!(&data[4..8] == &[42, 0, 0, 0]);
+        assert!(&data[24..28] == &[0x80, 0xbb, 0, 0]);
+        assert!(&data[40..44] == &[6, 0, 0, 0]);
+        assert!(&data[44..] == &[0, 0, 0xff, 0xff, 0x34, 0x12]);
+    }
+}
@@ -0,0 +1,77 @@
+//! YUV4MPEG2 video stream writer. The format is trivial and
+//! understood by most video encoders (ffmpeg, x264...).
+
+use std::io::{Write, Result};
+
+pub struct Y4mWriter<W: Write> {
+    out:    W,
+    width:  u32,
+    height: u32,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Write the stream header. The frame rate is
+    /// `rate_num`/`rate_den` frames per second.
+    pub fn new(mut out: W,
+               width: u32,
+               height: u32,
+               rate_num: u32,
+               rate_den: u32) -> Result<Y4mWriter<W>> {
+        // Progressive, square pixels, no chroma subsampling
+        try!(write!(out, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444\n",
+                    width, height, rate_num, rate_den));
+
+        Ok(Y4mWriter {
+            out:    out,
+            width:  width,
+            height: height,
+        })
+    }
+
+    /// Write a frame. `rgb` contains 3 bytes per pixel in row-major
+    /// order.
+    pub fn write_frame(&mut self, rgb: &[u8]) -> Result<()> {
+        let npixels = (self.width * self.height) as usize;
+
+        assert!(rgb.len() == npixels * 3);
+
+        // The three planes are stored one after the other
+        let mut data = vec![0; npixels * 3];
+
+        for (i, p) in rgb.chunks(3).enumerate() {
+            let (y, u, v) = rgb_to_yuv(p[0], p[1], p[2]);
+
+            data[i]               = y;
+            data[npixels + i]     = u;
+            data[npixels * 2 + i] = v;
+        }
+
+        try!(self.out.write_all(b"FRAME\n"));
+        self.out.write_all(&data)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Convert an RGB color to limited range BT.601 YCbCr, which is what
+/// Y4M readers assume by default.
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+
+    let y = (( 66 * r + 129 * g +  25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r -  74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r -  94 * g -  18 * b + 128) >> 8) + 128;
+
+    (y as u8, u as u8, v as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn rgb_to_yuv() {
+        assert!(super::rgb_to_yuv(0, 0, 0) == (16, 128, 128));
+        assert!(super::rgb_to_yuv(0xff, 0xff, 0xff) == (235, 128, 128));
+    }
+}
@@ -0,0 +1,249 @@
+//! Minimal zlib/deflate compressor. It only uses the fixed Huffman
+//! codes with a simple LZ77 match finder, which is more than enough
+//! for Game Boy images since they contain very few colors and lots
+//! of repetitions.
+
+/// Compress `data` into a zlib stream
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::new();
+
+    // CMF: deflate with a 32KB window. FLG: no dictionary, fastest
+    // compression, FCHECK makes the header a multiple of 31.
+    w.out.push(0x78);
+    w.out.push(0x01);
+
+    deflate(&mut w, data);
+
+    let mut out = w.finish();
+
+    let adler = adler32(data);
+
+    out.push((adler >> 24) as u8);
+    out.push((adler >> 16) as u8);
+    out.push((adler >> 8)  as u8);
+    out.push(adler as u8);
+
+    out
+}
+
+/// Adler-32 checksum used by zlib
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+/// Generate a single deflate block using the fixed Huffman codes
+fn deflate(w: &mut BitWriter, data: &[u8]) {
+    // BFINAL
+    w.write_bits(1, 1);
+    // BTYPE: fixed Huffman codes
+    w.write_bits(1, 2);
+
+    // Last position of each 3-byte hash in `data` + 1 (0 means
+    // empty)
+    let mut head = vec![0usize; HASH_SIZE];
+    // Previous position with the same hash, for each position in the
+    // window
+    let mut prev = vec![0usize; WINDOW_SIZE];
+
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let (len, dist) = longest_match(data, pos, &head, &prev);
+
+        let advance =
+            if len >= MIN_MATCH {
+                write_match(w, len, dist);
+                len
+            } else {
+                write_literal(w, data[pos] as u16);
+                1
+            };
+
+        // Insert all the positions we've just consumed in the hash
+        // chains
+        for p in pos..pos + advance {
+            if p + MIN_MATCH <= data.len() {
+                let h = hash(&data[p..]);
+
+                prev[p % WINDOW_SIZE] = head[h];
+                head[h] = p + 1;
+            }
+        }
+
+        pos += advance;
+    }
+
+    // End of block
+    write_literal(w, 256);
+}
+
+/// Look for the longest previous occurence of the bytes at `pos`.
+/// Returns the (length, distance) of the match, length is 0 if none
+/// was found.
+fn longest_match(data: &[u8],
+                 pos: usize,
+                 head: &[usize],
+                 prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_len = ::std::cmp::min(MAX_MATCH, data.len() - pos);
+
+    let mut best = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    let mut chain = 0;
+
+    while candidate != 0 && chain < MAX_CHAIN {
+        let c = candidate - 1;
+        let dist = pos - c;
+
+        if dist > WINDOW_SIZE {
+            break;
+        }
+
+        let mut len = 0;
+
+        while len < max_len && data[c + len] == data[pos + len] {
+            len += 1;
+        }
+
+        if len > best.0 {
+            best = (len, dist);
+
+            if len == max_len {
+                break;
+            }
+        }
+
+        let next = prev[c % WINDOW_SIZE];
+
+        // Make sure we only move backwards, the entry might have been
+        // overwritten by a more recent position
+        if next >= candidate {
+            break;
+        }
+
+        candidate = next;
+        chain += 1;
+    }
+
+    best
+}
+
+fn hash(data: &[u8]) -> usize {
+    let h = (data[0] as usize) << 10 ^ (data[1] as usize) << 5 ^ data[2] as usize;
+
+    h % HASH_SIZE
+}
+
+/// Write a literal/length symbol using the fixed Huffman code
+fn write_literal(w: &mut BitWriter, sym: u16) {
+    match sym {
+        0...143   => w.write_code(0x30 + sym as u32, 8),
+        144...255 => w.write_code(0x190 + (sym - 144) as u32, 9),
+        256...279 => w.write_code((sym - 256) as u32, 7),
+        _         => w.write_code(0xc0 + (sym - 280) as u32, 8),
+    }
+}
+
+fn write_match(w: &mut BitWriter, len: usize, dist: usize) {
+    let lcode = LENGTH_BASE.iter().rposition(|&b| b as usize <= len).unwrap();
+
+    write_literal(w, 257 + lcode as u16);
+    w.write_bits((len - LENGTH_BASE[lcode] as usize) as u32,
+                 LENGTH_EXTRA[lcode]);
+
+    let dcode = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+
+    w.write_code(dcode as u32, 5);
+    w.write_bits((dist - DIST_BASE[dcode] as usize) as u32,
+                 DIST_EXTRA[dcode]);
+}
+
+/// Deflate bitstream writer. Bits are packed starting from the LSB
+/// of each byte.
+struct BitWriter {
+    out:    Vec<u8>,
+    /// Pending bits not yet written to `out`
+    acc:    u32,
+    /// Number of valid bits in `acc`
+    nbits:  u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            out:   Vec::new(),
+            acc:   0,
+            nbits: 0,
+        }
+    }
+
+    /// Write the `n` low bits of `v`, LSB first
+    fn write_bits(&mut self, v: u32, n: u8) {
+        for i in 0..n {
+            self.acc |= ((v >> i) & 1) << self.nbits;
+            self.nbits += 1;
+
+            if self.nbits == 8 {
+                self.out.push(self.acc as u8);
+                self.acc   = 0;
+                self.nbits = 0;
+            }
+        }
+    }
+
+    /// Write an `n`-bit Huffman code, MSB first
+    fn write_code(&mut self, code: u32, n: u8) {
+        for i in (0..n).rev() {
+            self.write_bits(code >> i, 1);
+        }
+    }
+
+    /// Flush the pending bits and return the output
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+
+        self.out
+    }
+}
+
+const MIN_MATCH:   usize = 3;
+const MAX_MATCH:   usize = 258;
+const WINDOW_SIZE: usize = 32 * 1024;
+const HASH_SIZE:   usize = 1 << 15;
+/// Maximum number of candidates checked for each match. Higher
+/// values give better compression but are slower.
+const MAX_CHAIN:   usize = 64;
+
+/// Base match length for each length code (starting at 257)
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258 ];
+
+/// Number of extra bits for each length code
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0 ];
+
+/// Base distance for each distance code
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577 ];
+
+/// Number of extra bits for each distance code
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13 ];
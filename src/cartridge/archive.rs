@@ -0,0 +1,408 @@
+//! Extraction of ROM images stored in zip and gzip archives
+
+use std::path::{Path, PathBuf};
+
+use super::CartridgeError;
+use super::inflate::inflate;
+use crc::crc32_update;
+
+/// Return the ROM image contained in `data`, read from the file at
+/// `path`. Plain ROM files are returned untouched. For zip archives
+/// `entry` selects the ROM by name, otherwise the first Game Boy ROM
+/// is used.
+///
+/// Also returns the path the ROM would have if it was unpacked next
+/// to the archive, the save file is named after it.
+pub fn extract(path: &Path,
+               data: Vec<u8>,
+               entry: Option<&str>) -> Result<(Vec<u8>, PathBuf), CartridgeError> {
+    if data.starts_with(&ZIP_SIGNATURE) {
+        let (name, rom) = try!(unzip(&data, entry));
+
+        let name =
+            match name.rsplit('/').next() {
+                Some(n) if !n.is_empty() => n.to_string(),
+                _ => return Err(error(format!("invalid entry name '{}'", name))),
+            };
+
+        return Ok((rom, path.with_file_name(name)));
+    }
+
+    if let Some(entry) = entry {
+        return Err(error(format!("can't select '{}', not a zip archive", entry)));
+    }
+
+    if data.starts_with(&GZIP_SIGNATURE) {
+        let (name, rom) = try!(gunzip(&data));
+
+        let unpacked =
+            match name {
+                Some(n) => path.with_file_name(n),
+                // Strip the .gz extension
+                None    => path.with_extension(""),
+            };
+
+        return Ok((rom, unpacked));
+    }
+
+    Ok((data, PathBuf::from(path)))
+}
+
+/// Return the name and the contents of the selected zip entry
+fn unzip(data: &[u8], entry: Option<&str>) -> Result<(String, Vec<u8>), CartridgeError> {
+    // The end of central directory record is followed by a comment
+    // of up to 64KB, look for its signature backwards
+    let min = data.len().saturating_sub(EOCD_SIZE + 0xffff);
+
+    let eocd =
+        match (min..data.len().saturating_sub(EOCD_SIZE - 1)).rev()
+            .find(|&i| data[i..].starts_with(&EOCD_SIGNATURE)) {
+            Some(i) => i,
+            None    => return Err(error("zip central directory not found".to_string())),
+        };
+
+    let count      = le16(data, eocd + 10) as usize;
+    let mut offset = le32(data, eocd + 16) as usize;
+
+    let mut names = Vec::new();
+
+    for _ in 0..count {
+        if offset + CENTRAL_HEADER_SIZE > data.len() ||
+            !data[offset..].starts_with(&CENTRAL_SIGNATURE) {
+            return Err(error("corrupted zip central directory".to_string()));
+        }
+
+        let flags       = le16(data, offset + 8);
+        let method      = le16(data, offset + 10);
+        let crc         = le32(data, offset + 16);
+        let packed      = le32(data, offset + 20) as usize;
+        let size        = le32(data, offset + 24) as usize;
+        let name_len    = le16(data, offset + 28) as usize;
+        let extra_len   = le16(data, offset + 30) as usize;
+        let comment_len = le16(data, offset + 32) as usize;
+        let local       = le32(data, offset + 42) as usize;
+
+        let name_start = offset + CENTRAL_HEADER_SIZE;
+
+        if name_start + name_len > data.len() {
+            return Err(error("corrupted zip central directory".to_string()));
+        }
+
+        let name = String::from_utf8_lossy(&data[name_start..name_start + name_len])
+            .into_owned();
+
+        offset = name_start + name_len + extra_len + comment_len;
+
+        let selected =
+            match entry {
+                Some(e) => name == e || name.rsplit('/').next() == Some(e),
+                None    => is_rom_name(&name),
+            };
+
+        if !selected {
+            names.push(name);
+            continue;
+        }
+
+        if flags & 1 != 0 {
+            return Err(error(format!("{} is encrypted", name)));
+        }
+
+        // The local header repeats the name and has its own extra
+        // field, the data follows it
+        if local + LOCAL_HEADER_SIZE > data.len() ||
+            !data[local..].starts_with(&LOCAL_SIGNATURE) {
+            return Err(error(format!("corrupted zip entry {}", name)));
+        }
+
+        let start = local + LOCAL_HEADER_SIZE +
+            le16(data, local + 26) as usize +
+            le16(data, local + 28) as usize;
+
+        if start > data.len() {
+            return Err(error(format!("corrupted zip entry {}", name)));
+        }
+
+        let contents =
+            match method {
+                // Stored
+                0 => data[start..].iter().take(packed).cloned().collect(),
+                // Deflated
+                8 => try!(inflate(&data[start..], size)
+                          .map_err(|e| error(format!("{}: {}", name, e)))).0,
+                m => return Err(error(format!("{} uses unsupported compression \
+                                               method {}", name, m))),
+            };
+
+        if contents.len() != size || crc32_update(0, &contents) != crc {
+            return Err(error(format!("{} is corrupted", name)));
+        }
+
+        return Ok((name, contents));
+    }
+
+    match entry {
+        Some(e) => Err(error(format!("no entry named '{}' in the archive", e))),
+        None    => Err(error(format!("no Game Boy ROM in the archive (found {})",
+                                     if names.is_empty() {
+                                         "nothing".to_string()
+                                     } else {
+                                         names.join(", ")
+                                     }))),
+    }
+}
+
+/// Return the original file name and the contents of a gzip file
+fn gunzip(data: &[u8]) -> Result<(Option<String>, Vec<u8>), CartridgeError> {
+    let truncated = || error("truncated gzip file".to_string());
+
+    if data.len() < GZIP_HEADER_SIZE + 8 {
+        return Err(truncated());
+    }
+
+    if data[2] != 8 {
+        return Err(error(format!("unsupported gzip compression method {}", data[2])));
+    }
+
+    let flags = data[3];
+
+    let mut pos = GZIP_HEADER_SIZE;
+
+    if flags & FEXTRA != 0 {
+        if pos + 2 > data.len() {
+            return Err(truncated());
+        }
+
+        pos += 2 + le16(data, pos) as usize;
+    }
+
+    let mut name = None;
+
+    for &(flag, is_name) in [(FNAME, true), (FCOMMENT, false)].iter() {
+        if flags & flag == 0 {
+            continue;
+        }
+
+        let len =
+            match data.get(pos..).and_then(|d| d.iter().position(|&b| b == 0)) {
+                Some(l) => l,
+                None    => return Err(truncated()),
+            };
+
+        if is_name {
+            let n: String = data[pos..pos + len].iter().map(|&b| b as char).collect();
+
+            // Only keep the file name, never trust a path found in
+            // the archive
+            name = n.rsplit(|c| c == '/' || c == '\\')
+                .next()
+                .filter(|n| !n.is_empty())
+                .map(|n| n.to_string());
+        }
+
+        pos += len + 1;
+    }
+
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    if pos > data.len() {
+        return Err(truncated());
+    }
+
+    // The uncompressed size is only in the trailer, use the size of
+    // the largest cartridges as the limit
+    let (contents, len) = try!(inflate(&data[pos..], GZIP_MAX_SIZE).map_err(error));
+
+    let trailer = pos + len;
+
+    if trailer + 8 > data.len() {
+        return Err(truncated());
+    }
+
+    if crc32_update(0, &contents) != le32(data, trailer) ||
+        contents.len() as u32 != le32(data, trailer + 4) {
+        return Err(error("corrupted gzip file".to_string()));
+    }
+
+    Ok((name, contents))
+}
+
+/// Return true if `name` has one of the usual extensions of Game Boy
+/// ROM dumps
+fn is_rom_name(name: &str) -> bool {
+    let ext =
+        match name.rfind('.') {
+            Some(i) => name[i + 1..].to_lowercase(),
+            None    => return false,
+        };
+
+    ["gb", "gbc", "cgb", "sgb"].contains(&&ext[..])
+}
+
+fn error(msg: String) -> CartridgeError {
+    CartridgeError::Archive(msg)
+}
+
+fn le16(data: &[u8], pos: usize) -> u16 {
+    data[pos] as u16 | (data[pos + 1] as u16) << 8
+}
+
+fn le32(data: &[u8], pos: usize) -> u32 {
+    le16(data, pos) as u32 | (le16(data, pos + 2) as u32) << 16
+}
+
+const ZIP_SIGNATURE:       [u8; 4] = [b'P', b'K', 3, 4];
+const LOCAL_SIGNATURE:     [u8; 4] = [b'P', b'K', 3, 4];
+const CENTRAL_SIGNATURE:   [u8; 4] = [b'P', b'K', 1, 2];
+const EOCD_SIGNATURE:      [u8; 4] = [b'P', b'K', 5, 6];
+const LOCAL_HEADER_SIZE:   usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const EOCD_SIZE:           usize = 22;
+
+const GZIP_SIGNATURE:      [u8; 2] = [0x1f, 0x8b];
+const GZIP_HEADER_SIZE:    usize = 10;
+/// gzip header flags
+const FHCRC:               u8 = 1 << 1;
+const FEXTRA:              u8 = 1 << 2;
+const FNAME:               u8 = 1 << 3;
+const FCOMMENT:            u8 = 1 << 4;
+/// Largest ROM accepted in a gzip file
+const GZIP_MAX_SIZE:       usize = 8 << 20;
+
+#[cfg(test)]
+mod tests {
+    use super::extract;
+    use cartridge::CartridgeError;
+    use crc::crc32_update;
+    use std::path::{Path, PathBuf};
+
+    /// "Tetris" repeated 10 times, deflated
+    static TETRIS_DEFLATED: [u8; 11] =
+        [0x0b, 0x49, 0x2d, 0x29, 0xca, 0x2c, 0x0e, 0x21, 0x8b, 0x04, 0x00];
+
+    fn tetris() -> Vec<u8> {
+        b"Tetris".iter().cycle().take(60).cloned().collect()
+    }
+
+    fn push16(v: &mut Vec<u8>, n: u16) {
+        v.push(n as u8);
+        v.push((n >> 8) as u8);
+    }
+
+    fn push32(v: &mut Vec<u8>, n: u32) {
+        push16(v, n as u16);
+        push16(v, (n >> 16) as u16);
+    }
+
+    /// Build a zip archive out of (name, method, data, uncompressed
+    /// data) entries
+    fn zip(entries: &[(&str, u16, &[u8], &[u8])]) -> Vec<u8> {
+        let mut zip     = Vec::new();
+        let mut central = Vec::new();
+
+        for &(name, method, data, contents) in entries {
+            let crc = crc32_update(0, contents);
+
+            let mut header = Vec::new();
+
+            push16(&mut header, 20);
+            push16(&mut header, 0);
+            push16(&mut header, method);
+            push32(&mut header, 0);
+            push32(&mut header, crc);
+            push32(&mut header, data.len() as u32);
+            push32(&mut header, contents.len() as u32);
+            push16(&mut header, name.len() as u16);
+            push16(&mut header, 0);
+
+            central.extend_from_slice(b"PK\x01\x02");
+            push16(&mut central, 20);
+            central.extend_from_slice(&header);
+            // Comment, disk number, attributes
+            central.extend_from_slice(&[0; 10]);
+            push32(&mut central, zip.len() as u32);
+            central.extend_from_slice(name.as_bytes());
+
+            zip.extend_from_slice(b"PK\x03\x04");
+            zip.extend_from_slice(&header);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(data);
+        }
+
+        let offset = zip.len();
+
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(b"PK\x05\x06\0\0\0\0");
+        push16(&mut zip, entries.len() as u16);
+        push16(&mut zip, entries.len() as u16);
+        push32(&mut zip, central.len() as u32);
+        push32(&mut zip, offset as u32);
+        push16(&mut zip, 0);
+
+        zip
+    }
+
+    #[test]
+    fn plain() {
+        let path = Path::new("roms/game.gb");
+
+        assert!(extract(path, vec![1, 2, 3], None).ok() ==
+                Some((vec![1, 2, 3], PathBuf::from("roms/game.gb"))));
+        assert!(extract(path, vec![1, 2, 3], Some("game.gb")).is_err());
+    }
+
+    #[test]
+    fn gzip() {
+        let gz = vec![
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff,
+            b't', b'e', b't', b'r', b'i', b's', b'.', b'g', b'b', 0x00,
+            0x0b, 0x49, 0x2d, 0x29, 0xca, 0x2c, 0x0e, 0x21, 0x8b, 0x04,
+            0x00, 0x30, 0x6c, 0xd0, 0xbc, 0x3c, 0x00, 0x00, 0x00 ];
+
+        assert!(extract(Path::new("roms/t.gz"), gz.clone(), None).ok() ==
+                Some((tetris(), PathBuf::from("roms/tetris.gb"))));
+
+        // Bad CRC
+        let mut bad = gz;
+        bad[31] ^= 1;
+
+        assert!(extract(Path::new("roms/t.gz"), bad, None).is_err());
+    }
+
+    #[test]
+    fn zip_entries() {
+        let tetris = tetris();
+
+        let archive = zip(&[("readme.txt", 0, b"hi", b"hi"),
+                            ("roms/Tetris.gb", 8, &TETRIS_DEFLATED, &tetris),
+                            ("roms/Zelda.GBC", 0, b"Zelda", b"Zelda")]);
+
+        let path = Path::new("lib/games.zip");
+
+        assert!(extract(path, archive.clone(), None).ok() ==
+                Some((tetris.clone(), PathBuf::from("lib/Tetris.gb"))));
+        assert!(extract(path, archive.clone(), Some("Zelda.GBC")).ok() ==
+                Some((b"Zelda".to_vec(), PathBuf::from("lib/Zelda.GBC"))));
+        assert!(extract(path, archive.clone(), Some("roms/Tetris.gb")).is_ok());
+        assert!(extract(path, archive, Some("Mario.gb")).is_err());
+
+        let no_rom = zip(&[("readme.txt", 0, b"hi", b"hi")]);
+
+        assert!(extract(path, no_rom, None).is_err());
+
+        // Bad CRC
+        let bad = zip(&[("game.gb", 0, b"Zelda", b"Zeldb")]);
+
+        assert!(extract(path, bad, None).is_err());
+
+        // The entry inflates to more than its declared size
+        let oversized = zip(&[("game.gb", 8, &TETRIS_DEFLATED, &tetris[..10])]);
+
+        match extract(path, oversized, None) {
+            Err(CartridgeError::Archive(e)) => assert!(e.contains("larger than 10 bytes")),
+            _ => panic!("oversized zip entry accepted"),
+        }
+    }
+}
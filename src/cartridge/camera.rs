@@ -0,0 +1,358 @@
+//! Mitsubishi M64282FP image sensor of the Pocket Camera. Instead of
+//! a webcam the pictures come from an image file or from a directory
+//! of images, the next one being used for each capture.
+//!
+//! The sensor output goes through the same steps as on the real
+//! hardware: exposure and gain, edge enhancement and finally the
+//! dithering matrix which turns it into 2bpp tiles written to the
+//! cartridge RAM. The bias, zero point and inversion settings are
+//! ignored.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::image;
+
+pub struct Camera {
+    /// Registers mapped at [0xa000, 0xa035]
+    registers: [u8; REGISTERS],
+    /// Images used for the captures, empty for a single image
+    frames:    Vec<PathBuf>,
+    /// Index of the next image in `frames`
+    next:      usize,
+    /// Current picture, `WIDTH`x`HEIGHT`
+    picture:   Vec<u8>,
+}
+
+impl Camera {
+    /// Create a camera looking at a uniform grey picture
+    pub fn new() -> Camera {
+        Camera {
+            registers: [0; REGISTERS],
+            frames:    Vec::new(),
+            next:      0,
+            picture:   vec![0x80; WIDTH * HEIGHT],
+        }
+    }
+
+    /// Use the image file or the images of the directory at `path`
+    /// as the camera input
+    pub fn set_input(&mut self, path: &Path) -> Result<(), String> {
+        if !path.is_dir() {
+            self.picture = try!(image::load(path).map(|i| scale(&i)));
+            self.frames  = Vec::new();
+
+            return Ok(());
+        }
+
+        let entries = try!(fs::read_dir(path).map_err(|e| e.to_string()));
+
+        let mut frames: Vec<PathBuf> =
+            entries.filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| image::EXTENSIONS.contains(&&e.to_lowercase()[..]))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        if frames.is_empty() {
+            return Err("no image in the directory".to_string());
+        }
+
+        frames.sort();
+
+        self.frames = frames;
+        self.next   = 0;
+
+        Ok(())
+    }
+
+    /// Read register `reg`. Only the first one can be read back, the
+    /// others read as 0.
+    pub fn read(&self, reg: u8) -> u8 {
+        match reg as usize & 0x7f {
+            0 => self.registers[0],
+            _ => 0,
+        }
+    }
+
+    /// Write `val` to register `reg`, the captured pictures are put
+    /// in `ram`. The registers are mirrored every 0x80 bytes.
+    pub fn write(&mut self, reg: u8, val: u8, ram: &mut [u8]) {
+        let reg = reg as usize & 0x7f;
+
+        if reg >= REGISTERS {
+            return;
+        }
+
+        self.registers[reg] = val;
+
+        if reg == 0 && val & 1 != 0 {
+            // The capture completes immediately so the busy flag is
+            // cleared right away
+            self.capture(ram);
+            self.registers[0] &= !1;
+        }
+    }
+
+    /// Load the next picture if the input is a directory
+    fn next_picture(&mut self) {
+        if self.frames.is_empty() {
+            return;
+        }
+
+        let path = self.frames[self.next].clone();
+
+        self.next = (self.next + 1) % self.frames.len();
+
+        match image::load(&path) {
+            Ok(i)  => self.picture = scale(&i),
+            // Keep the previous picture
+            Err(e) => println!("Couldn't load camera image {}: {}", path.display(), e),
+        }
+    }
+
+    /// Take a picture and store it in `ram` as 16x14 tiles
+    fn capture(&mut self, ram: &mut [u8]) {
+        self.next_picture();
+
+        let mut out = [0u8; TILES_SIZE];
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let shade = self.dither(x, y, self.processed(x, y));
+
+                let tile = (y / 8) * (WIDTH / 8) + x / 8;
+                let off  = tile * 16 + (y % 8) * 2;
+                let bit  = 7 - x % 8;
+
+                out[off]     |= (shade & 1) << bit;
+                out[off + 1] |= (shade >> 1) << bit;
+            }
+        }
+
+        if ram.len() >= TILES_START + TILES_SIZE {
+            ram[TILES_START..TILES_START + TILES_SIZE].clone_from_slice(&out);
+        }
+    }
+
+    /// Sensor output for the pixel at (`x`, `y`) after exposure and
+    /// gain
+    fn exposed(&self, x: usize, y: usize) -> f32 {
+        let exposure = (self.registers[2] as u32) << 8 | self.registers[3] as u32;
+
+        // The gain goes up by roughly 0.18dB per step
+        let gain = 10f32.powf((self.registers[1] & 0x1f) as f32 * 0.18 / 20.);
+
+        self.picture[y * WIDTH + x] as f32 * gain * exposure as f32 / 0x1000 as f32
+    }
+
+    /// Sensor output after edge enhancement, from 0 to 255
+    fn processed(&self, x: usize, y: usize) -> u8 {
+        let v = self.exposed(x, y);
+
+        // The N bit enables the edge enhancement, VH selects the
+        // directions: horizontal, vertical or both
+        let mode = self.registers[1] >> 5;
+
+        let (horizontal, vertical) =
+            match mode {
+                0b101 => (true, false),
+                0b110 => (false, true),
+                0b111 => (true, true),
+                _     => (false, false),
+            };
+
+        let ratio = EDGE_RATIOS[(self.registers[4] >> 4) as usize & 7];
+
+        let mut edge = 0.;
+
+        if horizontal {
+            edge += 2. * v
+                - self.exposed(x.saturating_sub(1), y)
+                - self.exposed((x + 1).min(WIDTH - 1), y);
+        }
+
+        if vertical {
+            edge += 2. * v
+                - self.exposed(x, y.saturating_sub(1))
+                - self.exposed(x, (y + 1).min(HEIGHT - 1));
+        }
+
+        (v + edge * ratio).max(0.).min(255.) as u8
+    }
+
+    /// Convert a sensor value to a shade using the thresholds of the
+    /// dithering matrix, 3 is the darkest
+    fn dither(&self, x: usize, y: usize, v: u8) -> u8 {
+        let m = 6 + ((y % 4) * 4 + x % 4) * 3;
+
+        let (low, medium, high) =
+            (self.registers[m], self.registers[m + 1], self.registers[m + 2]);
+
+        if v < low {
+            3
+        } else if v < medium {
+            2
+        } else if v < high {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// Scale `image` to the sensor resolution, cropping it to the same
+/// aspect ratio first
+fn scale(image: &image::Image) -> Vec<u8> {
+    let (w, h) = (image.width, image.height);
+
+    let (cw, ch) =
+        if w * HEIGHT > h * WIDTH {
+            (h * WIDTH / HEIGHT, h)
+        } else {
+            (w, w * HEIGHT / WIDTH)
+        };
+
+    let (cw, ch) = (cw.max(1), ch.max(1));
+
+    let (x0, y0) = ((w - cw) / 2, (h - ch) / 2);
+
+    let mut picture = Vec::with_capacity(WIDTH * HEIGHT);
+
+    // Each pixel is the average of the area it covers in the image
+    for y in 0..HEIGHT {
+        let top    = y0 + y * ch / HEIGHT;
+        let bottom = (y0 + (y + 1) * ch / HEIGHT).max(top + 1);
+
+        for x in 0..WIDTH {
+            let left  = x0 + x * cw / WIDTH;
+            let right = (x0 + (x + 1) * cw / WIDTH).max(left + 1);
+
+            let mut sum = 0;
+
+            for sy in top..bottom {
+                for sx in left..right {
+                    sum += image.pixels[sy * w + sx] as usize;
+                }
+            }
+
+            picture.push((sum / ((bottom - top) * (right - left))) as u8);
+        }
+    }
+
+    picture
+}
+
+/// Sensor resolution
+const WIDTH:  usize = 128;
+const HEIGHT: usize = 112;
+
+/// Number of camera registers: control, sensor settings and the
+/// 4x4x3 thresholds of the dithering matrix
+const REGISTERS: usize = 0x36;
+
+/// The picture is stored in RAM bank 0 at [0xa100, 0xaeff]
+const TILES_START: usize = 0x100;
+const TILES_SIZE:  usize = WIDTH * HEIGHT / 4;
+
+/// Edge enhancement ratio selected by bits [6:4] of register 4
+const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1., 1.25, 2., 3., 4., 5.];
+
+#[cfg(test)]
+mod tests {
+    use super::{Camera, scale};
+    use cartridge::image::Image;
+
+    /// Set the dithering matrix thresholds to `low`, `medium` and
+    /// `high` everywhere, with a 1x exposure and no gain
+    fn camera(low: u8, medium: u8, high: u8) -> Camera {
+        let mut camera = Camera::new();
+
+        for m in 0..16 {
+            camera.registers[6 + m * 3]     = low;
+            camera.registers[6 + m * 3 + 1] = medium;
+            camera.registers[6 + m * 3 + 2] = high;
+        }
+
+        camera.registers[2] = 0x10;
+
+        camera
+    }
+
+    #[test]
+    fn capture() {
+        let mut camera = camera(0x40, 0x80, 0xc0);
+        let mut ram    = vec![0; 0x2000];
+
+        // Left half black, right half at 0x90
+        for y in 0..112 {
+            for x in 0..128 {
+                camera.picture[y * 128 + x] = if x < 64 { 0 } else { 0x90 };
+            }
+        }
+
+        camera.write(0, 0x01, &mut ram);
+
+        // Capture complete
+        assert!(camera.read(0x80) == 0);
+
+        // Black is shade 3, 0x90 is shade 1
+        assert!(ram[0x100] == 0xff && ram[0x101] == 0xff);
+        assert!(ram[0x100 + 15 * 16] == 0xff && ram[0x100 + 15 * 16 + 1] == 0x00);
+
+        // Double the exposure to saturate the right half
+        camera.write(2, 0x20, &mut ram);
+        camera.write(0, 0x01, &mut ram);
+        assert!(ram[0x100 + 15 * 16] == 0x00 && ram[0x100 + 15 * 16 + 1] == 0x00);
+    }
+
+    #[test]
+    fn edges() {
+        let mut camera = camera(0x40, 0x80, 0xc0);
+
+        // Horizontal line on a black background
+        for (i, p) in camera.picture.iter_mut().enumerate() {
+            *p = if i / 128 == 50 { 0x90 } else { 0 };
+        }
+
+        assert!(camera.processed(10, 50) == 0x90);
+        assert!(camera.processed(10, 49) == 0x00);
+
+        // 2D enhancement with a 0.5 ratio
+        camera.registers[1] = 0xe0;
+
+        assert!(camera.processed(10, 50) == 0xff);
+        assert!(camera.processed(10, 49) == 0x00);
+
+        // Horizontal only doesn't see a horizontal line
+        camera.registers[1] = 0xa0;
+
+        assert!(camera.processed(10, 50) == 0x90);
+    }
+
+    #[test]
+    fn scaling() {
+        // 256x112 image, the sides are cropped
+        let mut pixels = vec![0; 256 * 112];
+
+        for y in 0..112 {
+            for x in 64..192 {
+                pixels[y * 256 + x] = x as u8;
+            }
+        }
+
+        let picture = scale(&Image { width: 256, height: 112, pixels: pixels });
+
+        assert!(picture[0] == 64 && picture[127] == 191);
+
+        // Downscaling averages the pixels
+        let pixels = (0..256 * 224).map(|i| if i % 2 == 0 { 0 } else { 100 }).collect();
+
+        let image = Image { width: 256, height: 224, pixels: pixels };
+
+        assert!(scale(&image).iter().all(|&p| p == 50));
+    }
+}
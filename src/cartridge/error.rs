@@ -0,0 +1,72 @@
+//! Errors returned when a cartridge can't be loaded
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    /// Couldn't read the ROM or access the save file
+    Io(io::Error),
+    /// The ROM image is smaller than the header says
+    Truncated {
+        expected: usize,
+        actual:   usize,
+    },
+    /// The zip or gzip archive containing the ROM couldn't be
+    /// extracted
+    Archive(String),
+    /// The patch couldn't be applied to the ROM
+    Patch {
+        path:  PathBuf,
+        error: String,
+    },
+    /// The camera input couldn't be loaded
+    Camera {
+        path:  PathBuf,
+        error: String,
+    },
+    /// Cartridge type not emulated
+    UnsupportedMapper(u8),
+    /// Invalid value in the cartridge header
+    BadHeader {
+        field: &'static str,
+        value: u8,
+    },
+    /// The save file doesn't match the size of the cartridge RAM
+    SaveSize {
+        path:     PathBuf,
+        expected: usize,
+        actual:   u64,
+    },
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(e: io::Error) -> CartridgeError {
+        CartridgeError::Io(e)
+    }
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CartridgeError::Io(ref e) => write!(f, "{}", e),
+            CartridgeError::Truncated { expected, actual } =>
+                write!(f, "ROM is truncated: expected {} bytes, got {}",
+                       expected, actual),
+            CartridgeError::Archive(ref e) => write!(f, "{}", e),
+            CartridgeError::Patch { ref path, ref error } =>
+                write!(f, "Bad patch {}: {}", path.display(), error),
+            CartridgeError::Camera { ref path, ref error } =>
+                write!(f, "Can't use {} as the camera input: {}",
+                       path.display(), error),
+            CartridgeError::UnsupportedMapper(id) =>
+                write!(f, "Unsupported cartridge type 0x{:02x}", id),
+            CartridgeError::BadHeader { field, value } =>
+                write!(f, "Invalid {} in the header: 0x{:02x}", field, value),
+            CartridgeError::SaveSize { ref path, expected, actual } =>
+                write!(f, "Unexpected save file size for {}: expected {} got {}",
+                       path.display(), expected, actual),
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! Cartridge header found at [0x100, 0x14f] in the ROM. The bootrom
+//! refuses to start the game if the Nintendo logo or the header
+//! checksum is wrong.
+
+use std::fmt::{Debug, Formatter, Error};
+use ascii::AsciiCast;
+
+use super::offsets;
+
+/// Nintendo logo displayed by the bootrom, the cartridge must contain
+/// an identical copy
+pub static LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b,
+    0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e,
+    0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc,
+    0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+/// Game Boy Color support advertised by the cartridge
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum CgbSupport {
+    /// Original Game Boy game
+    None,
+    /// Enhanced for the Game Boy Color but runs on the original
+    Enhanced,
+    /// Only runs on the Game Boy Color
+    Only,
+}
+
+/// Market the cartridge was sold in
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+    Unknown(u8),
+}
+
+/// Publisher of the game
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum Licensee {
+    /// One byte code used by the older cartridges
+    Old(u8),
+    /// Two character code used when the old code is 0x33
+    New(String),
+}
+
+#[derive(Clone)]
+pub struct CartridgeHeader {
+    /// Game title, `None` if it contains invalid characters
+    pub title:           Option<String>,
+    /// Sum of the 16 title bytes, the Game Boy Color bootrom uses it
+    /// to pick a color palette for the game.
+    pub title_checksum:  u8,
+    pub cgb:             CgbSupport,
+    /// Super Game Boy functions supported
+    pub sgb:             bool,
+    /// Cartridge type, i.e. memory controller and extra hardware
+    pub cartridge_type:  u8,
+    /// ROM size code
+    pub rom_size:        u8,
+    /// RAM size code
+    pub ram_size:        u8,
+    pub destination:     Destination,
+    pub licensee:        Licensee,
+    /// Mask ROM version number, usually 0
+    pub version:         u8,
+    /// `true` if the Nintendo logo is intact
+    pub logo_ok:         bool,
+    /// Checksum of [0x134, 0x14c] stored in the header
+    pub header_checksum: u8,
+    /// Checksum of [0x134, 0x14c] computed from the ROM
+    pub header_computed: u8,
+    /// Sum of all the ROM bytes (except the checksum itself) stored
+    /// in the header. Nothing checks it on the real hardware.
+    pub global_checksum: u16,
+    /// Sum of all the ROM bytes computed from the ROM
+    pub global_computed: u16,
+}
+
+impl CartridgeHeader {
+    /// Parse the header of the ROM area starting at `offset` in
+    /// `full_rom`, there must be at least 0x150 bytes after it. The
+    /// global checksum is computed over the whole ROM.
+    pub fn parse(full_rom: &[u8], offset: usize) -> CartridgeHeader {
+        let rom = &full_rom[offset..];
+
+        let cgb =
+            match rom[offsets::CGB_FLAG] {
+                0xc0 => CgbSupport::Only,
+                f if f & 0x80 != 0 => CgbSupport::Enhanced,
+                _ => CgbSupport::None,
+            };
+
+        let destination =
+            match rom[offsets::DESTINATION] {
+                0 => Destination::Japan,
+                1 => Destination::Overseas,
+                d => Destination::Unknown(d),
+            };
+
+        let licensee =
+            match rom[offsets::OLD_LICENSEE] {
+                0x33 => {
+                    let code = &rom[offsets::NEW_LICENSEE..offsets::NEW_LICENSEE + 2];
+
+                    Licensee::New(code.iter().map(|&b| b as char).collect())
+                }
+                c => Licensee::Old(c),
+            };
+
+        let title = &rom[offsets::TITLE..offsets::TITLE + 16];
+
+        let global_checksum =
+            ((rom[offsets::GLOBAL_CHECKSUM] as u16) << 8) |
+            rom[offsets::GLOBAL_CHECKSUM + 1] as u16;
+
+        CartridgeHeader {
+            // The last title bytes are used for the CGB flag on the
+            // newer cartridges
+            title:           parse_title(if cgb == CgbSupport::None {
+                                             title
+                                         } else {
+                                             &title[..15]
+                                         }),
+            title_checksum:  title.iter().fold(0u8, |s, &b| s.wrapping_add(b)),
+            cgb:             cgb,
+            sgb:             rom[offsets::SGB_FLAG] == 0x03,
+            cartridge_type:  rom[offsets::TYPE],
+            rom_size:        rom[offsets::ROM_SIZE],
+            ram_size:        rom[offsets::RAM_SIZE],
+            destination:     destination,
+            licensee:        licensee,
+            version:         rom[offsets::VERSION],
+            logo_ok:         &rom[offsets::LOGO..offsets::LOGO + 48] == &LOGO[..],
+            header_checksum: rom[offsets::HEADER_CHECKSUM],
+            header_computed: header_checksum(rom),
+            global_checksum: global_checksum,
+            global_computed: global_checksum_of(full_rom, offset),
+        }
+    }
+
+    /// Return `true` if the bootrom accepts this header
+    pub fn bootable(&self) -> bool {
+        self.logo_ok && self.header_checksum == self.header_computed
+    }
+
+    pub fn global_checksum_ok(&self) -> bool {
+        self.global_checksum == self.global_computed
+    }
+
+    /// Return the name of the publisher if it's a well known one
+    pub fn licensee_name(&self) -> Option<&'static str> {
+        // The new codes don't match the old ones with the same value
+        match self.licensee {
+            Licensee::Old(code) => OLD_LICENSEES.iter()
+                .find(|&&(c, _)| c == code)
+                .map(|&(_, name)| name),
+            Licensee::New(ref code) => NEW_LICENSEES.iter()
+                .find(|&&(c, _)| c == code)
+                .map(|&(_, name)| name),
+        }
+    }
+}
+
+impl Debug for CartridgeHeader {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let licensee =
+            match self.licensee {
+                Licensee::Old(c)     => format!("0x{:02x}", c),
+                Licensee::New(ref c) => format!("\"{}\"", c),
+            };
+
+        try!(write!(f,
+                    "CGB: {:?}, SGB: {}, Licensee: {} ({}), \
+                     Destination: {:?}, Version: {}, Logo: {}, \
+                     Header checksum: {}, Global checksum: {}",
+                    self.cgb,
+                    self.sgb,
+                    licensee,
+                    self.licensee_name().unwrap_or("unknown"),
+                    self.destination,
+                    self.version,
+                    if self.logo_ok { "OK" } else { "BAD" },
+                    if self.header_checksum == self.header_computed { "OK" } else { "BAD" },
+                    if self.global_checksum_ok() { "OK" } else { "BAD" }));
+
+        Ok(())
+    }
+}
+
+/// Parse the title, it's padded with 0s if it's shorter than the
+/// field
+fn parse_title(bytes: &[u8]) -> Option<String> {
+    let mut name = String::with_capacity(bytes.len());
+
+    for &b in bytes {
+        let c =
+            match b.to_ascii() {
+                Ok(c) => c,
+                _     => return None,
+            };
+
+        if c.as_byte() == 0 {
+            break;
+        }
+
+        // Only uppercase ASCII is valid, but let's be a little
+        // more lenient
+        if !c.is_print() {
+            return None;
+        }
+
+        name.push(c.as_char());
+    }
+
+    Some(name)
+}
+
+/// Compute the checksum of the header bytes the same way the bootrom
+/// does
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[offsets::TITLE..offsets::HEADER_CHECKSUM].iter()
+        .fold(0u8, |s, &b| s.wrapping_sub(b).wrapping_sub(1))
+}
+
+/// Compute the sum of all the bytes of the ROM except for the global
+/// checksum itself, found in the header at `offset`
+fn global_checksum_of(rom: &[u8], offset: usize) -> u16 {
+    let c = offset + offsets::GLOBAL_CHECKSUM;
+
+    rom[..c].iter().chain(rom[c + 2..].iter())
+        .fold(0u16, |s, &b| s.wrapping_add(b as u16))
+}
+
+/// Old licensee codes of the most common publishers
+static OLD_LICENSEES: [(u8, &'static str); 64] = [
+    (0x00, "none"),
+    (0x01, "Nintendo"),
+    (0x08, "Capcom"),
+    (0x09, "Hot-B"),
+    (0x0a, "Jaleco"),
+    (0x0b, "Coconuts Japan"),
+    (0x0c, "Elite Systems"),
+    (0x13, "Electronic Arts"),
+    (0x18, "Hudson Soft"),
+    (0x19, "ITC Entertainment"),
+    (0x1a, "Yanoman"),
+    (0x1f, "Virgin"),
+    (0x24, "PCM Complete"),
+    (0x25, "San-X"),
+    (0x28, "Kemco"),
+    (0x29, "Seta"),
+    (0x30, "Infogrames"),
+    (0x31, "Nintendo"),
+    (0x32, "Bandai"),
+    (0x34, "Konami"),
+    (0x35, "Hector"),
+    (0x38, "Capcom"),
+    (0x39, "Banpresto"),
+    (0x41, "Ubi Soft"),
+    (0x42, "Atlus"),
+    (0x44, "Malibu"),
+    (0x46, "Angel"),
+    (0x49, "Irem"),
+    (0x4f, "U.S. Gold"),
+    (0x50, "Absolute"),
+    (0x51, "Acclaim"),
+    (0x52, "Activision"),
+    (0x53, "American Sammy"),
+    (0x54, "GameTek"),
+    (0x56, "LJN"),
+    (0x57, "Matchbox"),
+    (0x59, "Milton Bradley"),
+    (0x5a, "Mindscape"),
+    (0x5d, "Tradewest"),
+    (0x60, "Titus"),
+    (0x61, "Virgin"),
+    (0x67, "Ocean"),
+    (0x69, "Electronic Arts"),
+    (0x70, "Infogrames"),
+    (0x71, "Interplay"),
+    (0x72, "Broderbund"),
+    (0x78, "THQ"),
+    (0x79, "Accolade"),
+    (0x7f, "Kemco"),
+    (0x80, "Misawa"),
+    (0x8b, "Bullet-Proof"),
+    (0x91, "Chunsoft"),
+    (0x92, "Video System"),
+    (0x95, "Varie"),
+    (0x9b, "Tecmo"),
+    (0xa4, "Konami"),
+    (0xa7, "Takara"),
+    (0xaf, "Namco"),
+    (0xb6, "HAL"),
+    (0xb7, "SNK"),
+    (0xbb, "Sunsoft"),
+    (0xc0, "Taito"),
+    (0xc3, "Square"),
+    (0xe9, "Natsume"),
+];
+
+/// New licensee codes of the most common publishers
+static NEW_LICENSEES: [(&'static str, &'static str); 62] = [
+    ("00", "none"),
+    ("01", "Nintendo"),
+    ("08", "Capcom"),
+    ("09", "Hot-B"),
+    ("0A", "Jaleco"),
+    ("0B", "Coconuts Japan"),
+    ("0C", "Elite Systems"),
+    ("13", "Electronic Arts"),
+    ("18", "Hudson Soft"),
+    ("19", "B-AI"),
+    ("20", "KSS"),
+    ("22", "POW"),
+    ("24", "PCM Complete"),
+    ("25", "San-X"),
+    ("28", "Kemco"),
+    ("29", "Seta"),
+    ("30", "Viacom"),
+    ("31", "Nintendo"),
+    ("32", "Bandai"),
+    ("33", "Ocean/Acclaim"),
+    ("34", "Konami"),
+    ("35", "Hector"),
+    ("37", "Taito"),
+    ("38", "Hudson"),
+    ("39", "Banpresto"),
+    ("41", "Ubi Soft"),
+    ("42", "Atlus"),
+    ("44", "Malibu"),
+    ("46", "Angel"),
+    ("47", "Bullet-Proof"),
+    ("49", "Irem"),
+    ("50", "Absolute"),
+    ("51", "Acclaim"),
+    ("52", "Activision"),
+    ("53", "American Sammy"),
+    ("54", "Konami"),
+    ("55", "Hi Tech Entertainment"),
+    ("56", "LJN"),
+    ("57", "Matchbox"),
+    ("58", "Mattel"),
+    ("59", "Milton Bradley"),
+    ("60", "Titus"),
+    ("61", "Virgin"),
+    ("64", "LucasArts"),
+    ("67", "Ocean"),
+    ("69", "Electronic Arts"),
+    ("70", "Infogrames"),
+    ("71", "Interplay"),
+    ("72", "Broderbund"),
+    ("73", "Sculptured Software"),
+    ("75", "SCI"),
+    ("78", "THQ"),
+    ("79", "Accolade"),
+    ("80", "Misawa"),
+    ("83", "LOZC"),
+    ("86", "Tokuma Shoten"),
+    ("87", "Tsukuda Original"),
+    ("91", "Chunsoft"),
+    ("92", "Video System"),
+    ("93", "Ocean/Acclaim"),
+    ("95", "Varie"),
+    ("97", "Kaneko"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{CartridgeHeader, CgbSupport, Destination, Licensee, LOGO};
+    use super::header_checksum;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+
+        for (i, &b) in LOGO.iter().enumerate() {
+            rom[0x104 + i] = b;
+        }
+
+        for (i, b) in "POKEMON YELLOW".bytes().enumerate() {
+            rom[0x134 + i] = b;
+        }
+
+        rom[0x143] = 0x80;
+        rom[0x144] = b'0';
+        rom[0x145] = b'1';
+        rom[0x146] = 0x03;
+        rom[0x14a] = 0x01;
+        rom[0x14b] = 0x33;
+
+        rom[0x14d] = header_checksum(&rom);
+
+        // Only 0x14d contributes to the global checksum
+        let sum = rom[0x14d] as u16 + LOGO.iter().fold(0, |s, &b| s + b as u16) +
+            "POKEMON YELLOW01".bytes().fold(0, |s, b| s + b as u16) +
+            0x80 + 0x03 + 0x01 + 0x33;
+
+        rom[0x14e] = (sum >> 8) as u8;
+        rom[0x14f] = sum as u8;
+
+        rom
+    }
+
+    #[test]
+    fn parse() {
+        let h = CartridgeHeader::parse(&rom(), 0);
+
+        assert!(h.title == Some("POKEMON YELLOW".to_string()));
+        assert!(h.cgb == CgbSupport::Enhanced);
+        assert!(h.sgb);
+        assert!(h.destination == Destination::Overseas);
+        assert!(h.licensee == Licensee::New("01".to_string()));
+        assert!(h.licensee_name() == Some("Nintendo"));
+        assert!(h.bootable());
+        assert!(h.global_checksum_ok());
+    }
+
+    #[test]
+    fn licensees() {
+        let mut h = CartridgeHeader::parse(&rom(), 0);
+
+        h.licensee = Licensee::Old(0x38);
+        assert!(h.licensee_name() == Some("Capcom"));
+
+        h.licensee = Licensee::New("38".to_string());
+        assert!(h.licensee_name() == Some("Hudson"));
+
+        h.licensee = Licensee::Old(0x01);
+        assert!(h.licensee_name() == Some("Nintendo"));
+
+        h.licensee = Licensee::Old(0xa4);
+        assert!(h.licensee_name() == Some("Konami"));
+
+        h.licensee = Licensee::New("ZZ".to_string());
+        assert!(h.licensee_name().is_none());
+    }
+
+    #[test]
+    fn header_at_offset() {
+        // Header at the end of a 64KB ROM, like the MMM01 menus
+        let mut full = vec![0; 0x8000];
+
+        full[0x10] = 0x42;
+        full.extend(rom().into_iter());
+
+        let h = CartridgeHeader::parse(&full, 0x8000);
+
+        assert!(h.title == Some("POKEMON YELLOW".to_string()));
+        assert!(h.bootable());
+        // The byte outside of the header's 32KB counts
+        assert!(h.global_computed == CartridgeHeader::parse(&rom(), 0).global_computed + 0x42);
+        assert!(!h.global_checksum_ok());
+    }
+
+    #[test]
+    fn checksums() {
+        let mut rom = rom();
+
+        rom[0x4000] = 1;
+
+        let h = CartridgeHeader::parse(&rom, 0);
+
+        // The bootrom doesn't care about the global checksum
+        assert!(h.bootable());
+        assert!(!h.global_checksum_ok());
+
+        rom[0x14c] = 1;
+
+        assert!(!CartridgeHeader::parse(&rom, 0).bootable());
+
+        rom[0x14c] = 0;
+        rom[0x104] = 0;
+
+        assert!(!CartridgeHeader::parse(&rom, 0).bootable());
+    }
+}
@@ -0,0 +1,419 @@
+//! Image decoder for the pictures seen by the Pocket Camera. It
+//! supports non-interlaced PNG images and binary PGM and PPM images,
+//! all converted to shades of grey.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crc::crc32_update;
+use super::inflate::inflate;
+
+/// Greyscale image
+pub struct Image {
+    pub width:  usize,
+    pub height: usize,
+    /// Luminance of each pixel in row-major order, 0 is black
+    pub pixels: Vec<u8>,
+}
+
+/// Extensions of the files `load` can decode
+pub static EXTENSIONS: [&'static str; 4] = ["png", "pgm", "ppm", "pnm"];
+
+/// Load the image at `path`, the format is detected from its contents
+pub fn load(path: &Path) -> Result<Image, String> {
+    let mut data = Vec::new();
+
+    try!(File::open(path)
+         .and_then(|mut f| f.read_to_end(&mut data))
+         .map_err(|e| e.to_string()));
+
+    decode(&data)
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, String> {
+    let image =
+        if data.starts_with(&PNG_SIGNATURE) {
+            try!(png(data))
+        } else if data.starts_with(b"P5") || data.starts_with(b"P6") {
+            try!(pnm(data))
+        } else {
+            return Err("unknown image format".to_string());
+        };
+
+    if image.width == 0 || image.height == 0 {
+        return Err("empty image".to_string());
+    }
+
+    Ok(image)
+}
+
+fn png(data: &[u8]) -> Result<Image, String> {
+    let mut pos = PNG_SIGNATURE.len();
+
+    let mut header  = None;
+    let mut palette = Vec::new();
+    let mut idat    = Vec::new();
+
+    loop {
+        if pos + 12 > data.len() {
+            return Err("truncated PNG".to_string());
+        }
+
+        let len  = be32(&data[pos..]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+
+        if pos + 12 + len > data.len() {
+            return Err("truncated PNG".to_string());
+        }
+
+        let chunk = &data[pos + 8..pos + 8 + len];
+
+        if crc32_update(0, &data[pos + 4..pos + 8 + len]) != be32(&data[pos + 8 + len..]) {
+            return Err(format!("bad CRC in PNG {} chunk",
+                               String::from_utf8_lossy(kind)));
+        }
+
+        pos += 12 + len;
+
+        match kind {
+            b"IHDR" => {
+                if len != 13 {
+                    return Err("invalid PNG header".to_string());
+                }
+
+                header = Some(PngHeader {
+                    width:  be32(chunk) as usize,
+                    height: be32(&chunk[4..]) as usize,
+                    depth:  chunk[8],
+                    color:  chunk[9],
+                });
+
+                if chunk[12] != 0 {
+                    return Err("interlaced PNG images aren't supported".to_string());
+                }
+            }
+            b"PLTE" => palette = chunk.to_vec(),
+            b"IDAT" => idat.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Ancillary chunks don't matter
+            _ => (),
+        }
+    }
+
+    let header = try!(header.ok_or("missing PNG header".to_string()));
+
+    let channels =
+        match (header.color, header.depth) {
+            (0, 1) | (0, 2) | (0, 4) | (0, 8) | (0, 16) => 1,
+            (3, 1) | (3, 2) | (3, 4) | (3, 8)           => 1,
+            (4, 8) | (4, 16)                            => 2,
+            (2, 8) | (2, 16)                            => 3,
+            (6, 8) | (6, 16)                            => 4,
+            _ => return Err(format!("unsupported PNG color type {} with depth {}",
+                                    header.color, header.depth)),
+        };
+
+    // Skip the 2 byte zlib header, the adler32 at the end is ignored
+    if idat.len() < 2 {
+        return Err("missing PNG image data".to_string());
+    }
+
+    let bits   = channels * header.depth as usize;
+    // Distance to the corresponding byte of the previous pixel, at
+    // least 1 for the filters
+    let bpp    = (bits + 7) / 8;
+    let stride = (header.width * bits + 7) / 8;
+
+    // Each line starts with its filter type
+    let (raw, _) = try!(inflate(&idat[2..], (stride + 1) * header.height));
+
+    if raw.len() < (stride + 1) * header.height {
+        return Err("truncated PNG image data".to_string());
+    }
+
+    let mut pixels = Vec::with_capacity(header.width * header.height);
+    let mut prev   = vec![0; stride];
+
+    for y in 0..header.height {
+        let start  = y * (stride + 1);
+        let filter = raw[start];
+        let mut line = raw[start + 1..start + 1 + stride].to_vec();
+
+        try!(unfilter(filter, &mut line, &prev, bpp));
+
+        for x in 0..header.width {
+            let sample = |c: usize| -> u8 {
+                match header.depth {
+                    8  => line[x * channels + c],
+                    // Only keep the most significant byte
+                    16 => line[(x * channels + c) * 2],
+                    d  => {
+                        let d   = d as usize;
+                        let bit = x * d;
+                        let v   = line[bit / 8] >> (8 - d - bit % 8);
+
+                        v & ((1 << d) - 1) as u8
+                    }
+                }
+            };
+
+            let luma =
+                match header.color {
+                    0 => {
+                        // Scale low bit depths to [0, 255]
+                        let max = (1u32 << header.depth.min(8)) - 1;
+
+                        (sample(0) as u32 * 255 / max) as u8
+                    }
+                    3 => {
+                        let i = sample(0) as usize * 3;
+
+                        if i + 3 > palette.len() {
+                            return Err("invalid PNG palette index".to_string());
+                        }
+
+                        luminance(palette[i], palette[i + 1], palette[i + 2])
+                    }
+                    4 => sample(0),
+                    _ => luminance(sample(0), sample(1), sample(2)),
+                };
+
+            pixels.push(luma);
+        }
+
+        prev = line;
+    }
+
+    Ok(Image {
+        width:  header.width,
+        height: header.height,
+        pixels: pixels,
+    })
+}
+
+struct PngHeader {
+    width:  usize,
+    height: usize,
+    depth:  u8,
+    color:  u8,
+}
+
+/// Undo the PNG `filter` of `line`, `prev` is the previous line
+/// already unfiltered
+fn unfilter(filter: u8, line: &mut [u8], prev: &[u8], bpp: usize) -> Result<(), String> {
+    for i in 0..line.len() {
+        let a = if i >= bpp { line[i - bpp] } else { 0 };
+        let b = prev[i];
+        let c = if i >= bpp { prev[i - bpp] } else { 0 };
+
+        let predictor =
+            match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("invalid PNG filter {}", filter)),
+            };
+
+        line[i] = line[i].wrapping_add(predictor);
+    }
+
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p  = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Binary PGM (P5) and PPM (P6) images
+fn pnm(data: &[u8]) -> Result<Image, String> {
+    let channels = if data[1] == b'5' { 1 } else { 3 };
+
+    // The header is made of the magic number, the width, the height
+    // and the maximum value separated by whitespace, with optional
+    // comments. A single whitespace precedes the pixels.
+    let mut pos    = 2;
+    let mut fields = [0usize; 3];
+
+    for field in fields.iter_mut() {
+        loop {
+            match data.get(pos) {
+                Some(&b'#') =>
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    },
+                Some(c) if (*c as char).is_whitespace() => pos += 1,
+                _ => break,
+            }
+        }
+
+        let start = pos;
+
+        while pos < data.len() && (data[pos] as char).is_digit(10) {
+            pos += 1;
+        }
+
+        *field =
+            try!(String::from_utf8_lossy(&data[start..pos]).parse()
+                 .map_err(|_| "invalid PNM header".to_string()));
+    }
+
+    let (width, height, max) = (fields[0], fields[1], fields[2]);
+
+    if max == 0 || max > 255 {
+        return Err(format!("unsupported PNM maximum value {}", max));
+    }
+
+    let start = pos + 1;
+    let len   = width * height * channels;
+
+    if data.len() < start + len {
+        return Err("truncated PNM image".to_string());
+    }
+
+    let scale = |v: u8| (v as usize * 255 / max) as u8;
+
+    let pixels =
+        data[start..start + len].chunks(channels)
+        .map(|p| {
+            if channels == 1 {
+                scale(p[0])
+            } else {
+                luminance(scale(p[0]), scale(p[1]), scale(p[2]))
+            }
+        })
+        .collect();
+
+    Ok(Image {
+        width:  width,
+        height: height,
+        pixels: pixels,
+    })
+}
+
+/// Convert an RGB color to a shade of grey (ITU-R BT.601 weights)
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+fn be32(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 |
+    (data[1] as u32) << 16 |
+    (data[2] as u32) << 8 |
+    data[3] as u32
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use capture::png::write_png;
+    use crc::crc32_update;
+
+    #[test]
+    fn png_rgb() {
+        let mut png = Vec::new();
+
+        let rgb = [255, 255, 255,  255, 0, 0,
+                   0,   0,   0,    0,   0, 255];
+
+        write_png(&mut png, 2, 2, &rgb).unwrap();
+
+        let image = decode(&png).unwrap();
+
+        assert!(image.width == 2 && image.height == 2);
+        assert!(image.pixels == vec![255, 76, 0, 29]);
+
+        let len = png.len();
+        png[len - 20] ^= 1;
+
+        assert!(decode(&png).is_err());
+    }
+
+    /// Build a greyscale PNG from filtered scanlines
+    fn grey_png(width: u32, height: u32, depth: u8, raw: &[u8]) -> Vec<u8> {
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+        let chunk = |png: &mut Vec<u8>, kind: &[u8], data: &[u8]| {
+            let len = data.len() as u32;
+
+            png.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8,
+                                    (len >> 8) as u8, len as u8]);
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+
+            let crc = crc32_update(crc32_update(0, kind), data);
+
+            png.extend_from_slice(&[(crc >> 24) as u8, (crc >> 16) as u8,
+                                    (crc >> 8) as u8, crc as u8]);
+        };
+
+        let ihdr = [0, 0, 0, width as u8, 0, 0, 0, height as u8, depth, 0, 0, 0, 0];
+
+        chunk(&mut png, b"IHDR", &ihdr);
+        // zlib stream with a single stored block, the decoder
+        // doesn't check the adler32
+        let len  = raw.len();
+        let mut idat = vec![0x78, 0x01, 0x01, len as u8, (len >> 8) as u8,
+                            !len as u8, (!len >> 8) as u8];
+
+        idat.extend_from_slice(raw);
+        idat.extend_from_slice(&[0; 4]);
+
+        chunk(&mut png, b"IDAT", &idat);
+        chunk(&mut png, b"IEND", &[]);
+
+        png
+    }
+
+    #[test]
+    fn png_filters() {
+        // Sub, up, average then paeth
+        let raw = [1, 10, 5, 5,
+                   2, 1, 1, 1,
+                   3, 0, 0, 0,
+                   4, 1, 1, 1];
+
+        let image = decode(&grey_png(3, 4, 8, &raw)).unwrap();
+
+        assert!(image.pixels == vec![10, 15, 20,
+                                     11, 16, 21,
+                                     5, 10, 15,
+                                     6, 11, 16]);
+
+        // 2 bit greyscale
+        let image = decode(&grey_png(3, 1, 2, &[0, 0b00_01_11_00])).unwrap();
+
+        assert!(image.pixels == vec![0, 85, 255]);
+    }
+
+    #[test]
+    fn pnm() {
+        let mut pgm = b"P5\n# comment\n2 1\n15\n".to_vec();
+
+        pgm.extend_from_slice(&[0, 15]);
+
+        assert!(decode(&pgm).unwrap().pixels == vec![0, 255]);
+        assert!(decode(&pgm[..pgm.len() - 1]).is_err());
+
+        let mut ppm = b"P6 1 1 255 ".to_vec();
+
+        ppm.extend_from_slice(&[0, 255, 0]);
+
+        assert!(decode(&ppm).unwrap().pixels == vec![149]);
+    }
+}
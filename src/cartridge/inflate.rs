@@ -0,0 +1,420 @@
+//! Deflate decompressor used to load ROMs from zip and gzip
+//! archives. It follows the structure of zlib's "puff": simple and
+//! fast enough for a few megabytes of ROM data.
+
+/// Decompress the raw deflate stream `data`. Returns the
+/// decompressed bytes and the length of the compressed stream. It
+/// fails if the output would be larger than `max` bytes so that a
+/// small corrupted or malicious file can't exhaust the memory.
+pub fn inflate(data: &[u8], max: usize) -> Result<(Vec<u8>, usize), String> {
+    let mut s = State {
+        input: data,
+        pos:   0,
+        acc:   0,
+        nbits: 0,
+        out:   Vec::new(),
+        max:   max,
+    };
+
+    loop {
+        let last = try!(s.bits(1));
+
+        match try!(s.bits(2)) {
+            0 => try!(s.stored()),
+            1 => {
+                let (lencode, distcode) = fixed_codes();
+
+                try!(s.codes(&lencode, &distcode));
+            }
+            2 => {
+                let (lencode, distcode) = try!(s.dynamic_codes());
+
+                try!(s.codes(&lencode, &distcode));
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+
+        if last == 1 {
+            break;
+        }
+    }
+
+    Ok((s.out, s.pos))
+}
+
+/// Decoder state
+struct State<'a> {
+    input: &'a [u8],
+    /// Position of the next input byte
+    pos:   usize,
+    /// Bits read from the input but not yet consumed
+    acc:   u32,
+    /// Number of valid bits in `acc`
+    nbits: u8,
+    out:   Vec<u8>,
+    /// Maximum length of `out`
+    max:   usize,
+}
+
+impl<'a> State<'a> {
+    /// Read `n` bits from the input, LSB first
+    fn bits(&mut self, n: u8) -> Result<u32, String> {
+        let mut v = self.acc;
+
+        while self.nbits < n {
+            let b =
+                match self.input.get(self.pos) {
+                    Some(&b) => b,
+                    None     => return Err("truncated deflate stream".to_string()),
+                };
+
+            self.pos += 1;
+
+            v |= (b as u32) << self.nbits;
+            self.nbits += 8;
+        }
+
+        self.acc    = v >> n;
+        self.nbits -= n;
+
+        Ok(v & ((1 << n) - 1))
+    }
+
+    /// Make sure there's room for `len` more bytes in the output
+    fn reserve(&self, len: usize) -> Result<(), String> {
+        if self.out.len() + len > self.max {
+            Err(format!("decompressed data larger than {} bytes", self.max))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Copy an uncompressed block
+    fn stored(&mut self) -> Result<(), String> {
+        // Stored blocks start on a byte boundary
+        self.acc   = 0;
+        self.nbits = 0;
+
+        if self.pos + 4 > self.input.len() {
+            return Err("truncated deflate stream".to_string());
+        }
+
+        let len  = self.input[self.pos]     as usize | (self.input[self.pos + 1] as usize) << 8;
+        let nlen = self.input[self.pos + 2] as usize | (self.input[self.pos + 3] as usize) << 8;
+
+        if len != !nlen & 0xffff {
+            return Err("invalid stored block length".to_string());
+        }
+
+        self.pos += 4;
+
+        if self.pos + len > self.input.len() {
+            return Err("truncated deflate stream".to_string());
+        }
+
+        try!(self.reserve(len));
+
+        self.out.extend_from_slice(&self.input[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(())
+    }
+
+    /// Decode a symbol using `h`. Codes are stored MSB first in the
+    /// stream so they have to be read one bit at a time.
+    fn decode(&mut self, h: &Huffman) -> Result<u16, String> {
+        // First code of the current length
+        let mut first = 0;
+        // Index of the first code of the current length in `symbols`
+        let mut index = 0;
+        let mut code  = 0;
+
+        for len in 1..MAX_BITS + 1 {
+            code |= try!(self.bits(1)) as i32;
+
+            let count = h.counts[len] as i32;
+
+            if code - count < first {
+                return Ok(h.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first += count;
+            first <<= 1;
+            code  <<= 1;
+        }
+
+        Err("invalid Huffman code".to_string())
+    }
+
+    /// Decode a compressed block until the end of block symbol
+    fn codes(&mut self, lencode: &Huffman, distcode: &Huffman) -> Result<(), String> {
+        loop {
+            let sym = try!(self.decode(lencode)) as usize;
+
+            if sym < 256 {
+                try!(self.reserve(1));
+
+                self.out.push(sym as u8);
+                continue;
+            }
+
+            if sym == 256 {
+                return Ok(());
+            }
+
+            let sym = sym - 257;
+
+            if sym >= LENGTH_BASE.len() {
+                return Err("invalid length symbol".to_string());
+            }
+
+            let len = LENGTH_BASE[sym] as usize +
+                try!(self.bits(LENGTH_EXTRA[sym])) as usize;
+
+            let dsym = try!(self.decode(distcode)) as usize;
+
+            if dsym >= DIST_BASE.len() {
+                return Err("invalid distance symbol".to_string());
+            }
+
+            let dist = DIST_BASE[dsym] as usize +
+                try!(self.bits(DIST_EXTRA[dsym])) as usize;
+
+            if dist > self.out.len() {
+                return Err("distance too far back".to_string());
+            }
+
+            try!(self.reserve(len));
+
+            // The match can overlap the bytes being copied so it has
+            // to be done one byte at a time
+            let start = self.out.len() - dist;
+
+            for i in 0..len {
+                let b = self.out[start + i];
+
+                self.out.push(b);
+            }
+        }
+    }
+
+    /// Read the code lengths of a dynamic block and build its codes
+    fn dynamic_codes(&mut self) -> Result<(Huffman, Huffman), String> {
+        let nlen  = try!(self.bits(5)) as usize + 257;
+        let ndist = try!(self.bits(5)) as usize + 1;
+        let ncode = try!(self.bits(4)) as usize + 4;
+
+        if nlen > 286 || ndist > 30 {
+            return Err("too many length or distance codes".to_string());
+        }
+
+        let mut lengths = [0u8; 286 + 30];
+
+        for &i in CODE_LENGTH_ORDER[..ncode].iter() {
+            lengths[i] = try!(self.bits(3)) as u8;
+        }
+
+        let lencode = try!(Huffman::new(&lengths[..19]));
+
+        for l in lengths.iter_mut() {
+            *l = 0;
+        }
+
+        let mut index = 0;
+
+        while index < nlen + ndist {
+            let sym = try!(self.decode(&lencode));
+
+            if sym < 16 {
+                lengths[index] = sym as u8;
+                index += 1;
+                continue;
+            }
+
+            let (len, repeat) =
+                match sym {
+                    16 => {
+                        if index == 0 {
+                            return Err("repeat with no previous length".to_string());
+                        }
+
+                        (lengths[index - 1], 3 + try!(self.bits(2)))
+                    }
+                    17 => (0, 3  + try!(self.bits(3))),
+                    _  => (0, 11 + try!(self.bits(7))),
+                };
+
+            let repeat = repeat as usize;
+
+            if index + repeat > nlen + ndist {
+                return Err("too many code lengths".to_string());
+            }
+
+            for l in lengths[index..index + repeat].iter_mut() {
+                *l = len;
+            }
+
+            index += repeat;
+        }
+
+        if lengths[256] == 0 {
+            return Err("missing end of block code".to_string());
+        }
+
+        let lencode  = try!(Huffman::new(&lengths[..nlen]));
+        let distcode = try!(Huffman::new(&lengths[nlen..nlen + ndist]));
+
+        Ok((lencode, distcode))
+    }
+}
+
+/// Canonical Huffman code
+struct Huffman {
+    /// Number of codes of each length
+    counts:  [u16; MAX_BITS + 1],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Build the code from the length of each symbol's code, 0 for
+    /// unused symbols
+    fn new(lengths: &[u8]) -> Result<Huffman, String> {
+        let mut counts = [0u16; MAX_BITS + 1];
+
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+
+        // Make sure the code isn't over-subscribed. Incomplete codes
+        // are allowed, a single distance code is common.
+        let mut left = 1i32;
+
+        for len in 1..MAX_BITS + 1 {
+            left <<= 1;
+            left -= counts[len] as i32;
+
+            if left < 0 {
+                return Err("over-subscribed Huffman code".to_string());
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+
+        for (sym, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbols[offsets[l as usize] as usize] = sym as u16;
+                offsets[l as usize] += 1;
+            }
+        }
+
+        counts[0] = 0;
+
+        Ok(Huffman {
+            counts:  counts,
+            symbols: symbols,
+        })
+    }
+}
+
+/// Codes used by the fixed Huffman blocks
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+
+    for (sym, l) in lengths.iter_mut().enumerate() {
+        *l = match sym {
+            0...143   => 8,
+            144...255 => 9,
+            256...279 => 7,
+            _         => 8,
+        };
+    }
+
+    // The fixed codes are always valid
+    (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+}
+
+/// Maximum length of a Huffman code
+const MAX_BITS: usize = 15;
+
+/// Order of the code length code lengths in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15 ];
+
+/// Base match length for each length symbol (starting at 257)
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258 ];
+
+/// Number of extra bits for each length symbol
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0 ];
+
+/// Base distance for each distance symbol
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577 ];
+
+/// Number of extra bits for each distance symbol
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13 ];
+
+#[cfg(test)]
+mod tests {
+    use super::inflate;
+
+    #[test]
+    fn stored() {
+        let data = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0xaa];
+
+        assert!(inflate(&data, 0x100) == Ok((b"hello".to_vec(), 10)));
+        assert!(inflate(&data, 5).is_ok());
+        assert!(inflate(&data, 4).is_err());
+        assert!(inflate(&data[..8], 0x100).is_err());
+    }
+
+    #[test]
+    fn fixed() {
+        let data = [0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xf0,
+                    0x40, 0xa2, 0x14, 0x01];
+
+        assert!(inflate(&data, 0x100) == Ok((b"Hello, Hello, Hello!".to_vec(), 12)));
+        // The limit applies to the back references too
+        assert!(inflate(&data, 19).is_err());
+    }
+
+    #[test]
+    fn dynamic() {
+        let data = [
+            0xb5, 0xcb, 0xd1, 0x01, 0x80, 0x10, 0x14, 0x46, 0xe1, 0x55,
+            0xfe, 0x16, 0x68, 0x96, 0x1e, 0x2c, 0x40, 0x11, 0x15, 0x37,
+            0x84, 0x98, 0xbe, 0xbb, 0x44, 0xcf, 0xe7, 0x3b, 0xc2, 0x6a,
+            0xc4, 0xe2, 0xd6, 0x13, 0x2a, 0x51, 0x0b, 0x30, 0xf4, 0xe2,
+            0x28, 0xfe, 0xce, 0xa0, 0xaa, 0x13, 0x1e, 0xce, 0x97, 0x1c,
+            0x1d, 0x1b, 0xed, 0x33, 0xc4, 0x6f, 0x78, 0x91, 0xec, 0x7c,
+            0x87, 0x62, 0xd4, 0xdc, 0x63, 0x61, 0x5c, 0xd5, 0x9c, 0x86,
+            0x0e, 0xb8, 0x5c, 0x2c, 0x94, 0xf8, 0xdd, 0xf3, 0xf4, 0x01 ];
+
+        let mut expected = Vec::new();
+
+        for _ in 0..3 {
+            expected.extend_from_slice(b"The quick brown fox jumps over the lazy dog. ");
+        }
+
+        expected.extend_from_slice(b"Pack my box with five dozen liquor jugs!");
+
+        assert!(inflate(&data, 0x100) == Ok((expected, data.len())));
+        // Corrupted code lengths
+        assert!(inflate(&[0x05, 0xff, 0xff, 0xff, 0xff], 0x100).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Interface between the cartridge and its memory controller, the
+//! "mapper". Each mapper owns its registers and decides what the CPU
+//! sees in the ROM area at [0x0000, 0x7fff] and in the external RAM
+//! area at [0xa000, 0xbfff].
+//!
+//! The mappers are in `models`, a new one (for homebrew hardware for
+//! instance) implements `Mapper` and is added to `models::new`.
+
+use super::{CartridgeHeader, ROM_BANK_SIZE};
+use super::camera::Camera;
+use ui::Tilt;
+
+/// Memory controller of a cartridge
+pub trait Mapper {
+    /// Name of the memory controller
+    fn name(&self) -> &'static str;
+
+    /// Return the number of RAM banks along with the size of each
+    /// bank in bytes, `None` if the RAM size in the header isn't
+    /// valid
+    fn ram_banks(&self) -> Option<(usize, usize)>;
+
+    /// Return the byte read at `offset` in [0x0000, 0x7fff]
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8;
+
+    /// Handle a write at `offset` in [0x0000, 0x7fff], usually a
+    /// register write
+    fn write_rom(&mut self, offset: u16, val: u8);
+
+    /// Return the byte read at `offset` in [0xa000, 0xbfff], `ram` is
+    /// the whole cartridge RAM
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8;
+
+    /// Handle a write at `offset` in [0xa000, 0xbfff]
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8);
+
+    /// Called once per machine cycle, for the hardware following the
+    /// emulated time
+    fn step(&mut self) {
+    }
+
+    /// Return the data stored in the save file after the RAM (the
+    /// state of an RTC for instance)
+    fn save_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restore the data returned by `save_data`
+    fn load_save_data(&mut self, _: &[u8]) {
+    }
+
+    /// Return the accelerometer of the cartridge, if it has one
+    fn accelerometer(&mut self) -> Option<&mut Accelerometer> {
+        None
+    }
+
+    /// Return the image sensor of the cartridge, if it has one
+    fn camera(&mut self) -> Option<&mut Camera> {
+        None
+    }
+}
+
+/// Accelerometer measuring the tilt of the console
+pub trait Accelerometer {
+    /// Update the tilt, the cartridge latches it when the game asks
+    /// for a measurement
+    fn set_tilt(&mut self, tilt: Tilt);
+}
+
+/// Description of the cartridge given to the mapper constructors
+pub struct Info<'a> {
+    /// Whole ROM image
+    pub rom:       &'a [u8],
+    pub header:    &'a CartridgeHeader,
+    /// Number of 16KB ROM banks
+    pub rom_banks: usize,
+}
+
+/// Banking shared by most mappers: two 16KB ROM banks mapped at
+/// [0x0000, 0x3fff] (normally bank 0) and [0x4000, 0x7fff], and a
+/// write protected 8KB RAM bank
+pub struct Banks {
+    /// Total number of ROM banks in the cartridge
+    rom_banks:   usize,
+    /// Number of RAM banks and size of each bank from the header
+    ram_banks:   Option<(usize, usize)>,
+    /// Offset in the ROM of the bank mapped at [0x0000, 0x3fff]
+    rom0_offset: usize,
+    /// Offset in the ROM of the bank mapped at [0x4000, 0x7fff]
+    rom_offset:  usize,
+    /// Offset in the RAM of the current RAM bank
+    ram_offset:  usize,
+    /// If `true` RAM is write protected
+    ram_wp:      bool,
+}
+
+impl Banks {
+    /// Map banks 0 and 1, with the RAM write protected
+    pub fn new(info: &Info) -> Banks {
+        Banks {
+            rom_banks:   info.rom_banks,
+            ram_banks:   ram_banks(info.header.ram_size),
+            rom0_offset: 0,
+            rom_offset:  ROM_BANK_SIZE,
+            ram_offset:  0,
+            ram_wp:      true,
+        }
+    }
+
+    pub fn rom_banks(&self) -> usize {
+        self.rom_banks
+    }
+
+    /// Return the number of RAM banks and their size advertised by
+    /// the header
+    pub fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.ram_banks
+    }
+
+    pub fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        let off = offset as usize;
+
+        if off < ROM_BANK_SIZE {
+            rom[self.rom0_offset + off]
+        } else {
+            rom[self.rom_offset + off - ROM_BANK_SIZE]
+        }
+    }
+
+    /// Map ROM `bank` at [0x4000, 0x7fff]. Bank 0 can't be mapped
+    /// there, it's replaced by bank 1.
+    pub fn set_rom_bank(&mut self, bank: usize) {
+        let bank =
+            match bank {
+                0 => 1,
+                n => n,
+            };
+
+        self.map_rom(0, bank);
+    }
+
+    /// Map ROM bank `low` at [0x0000, 0x3fff] and `high` at
+    /// [0x4000, 0x7fff]. If the banks overflow they wrap around.
+    pub fn map_rom(&mut self, low: usize, high: usize) {
+        self.rom0_offset = (low  % self.rom_banks) * ROM_BANK_SIZE;
+        self.rom_offset  = (high % self.rom_banks) * ROM_BANK_SIZE;
+    }
+
+    /// Map RAM `bank` at [0xa000, 0xbfff]
+    pub fn set_ram_bank(&mut self, bank: usize) {
+        // Bankable RAM is always 8KB per bank
+        self.ram_offset = bank * 8 * 1024;
+    }
+
+    /// Enable or disable RAM write protect
+    pub fn set_ram_wp(&mut self, wp: bool) {
+        self.ram_wp = wp
+    }
+
+    /// Return the value of RAM byte at `offset` in the current RAM
+    /// bank
+    pub fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        *ram.get(self.ram_offset + offset as usize).unwrap_or(&0)
+    }
+
+    /// Set the value of RAM byte at `offset` in the current RAM bank
+    /// unless it's write protected
+    pub fn write_ram(&self, ram: &mut [u8], offset: u16, val: u8) {
+        if self.ram_wp {
+            debug!("Attempt to write to cartridge RAM while protected");
+            return;
+        }
+
+        if let Some(b) = ram.get_mut(self.ram_offset + offset as usize) {
+            *b = val;
+        }
+    }
+}
+
+/// Return the number of RAM banks and the size of each bank in bytes
+/// for the RAM size code `id` found in the header
+fn ram_banks(id: u8) -> Option<(usize, usize)> {
+    let (nbanks, bank_size_kb) =
+        match id {
+            0x00 => (0,  0),
+            0x01 => (1,  2),
+            0x02 => (1,  8),
+            0x03 => (4,  8),
+            0x04 => (16, 8),
+            // Unknown value
+            _    => return None,
+        };
+
+    Some((nbanks, bank_size_kb * 1024))
+}
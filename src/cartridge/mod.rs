@@ -3,110 +3,166 @@
 
 use std::fmt::{Debug, Formatter, Error};
 use std::path::{Path, PathBuf};
-use std::fs::{File, OpenOptions};
-use std::iter::repeat;
-use ascii::AsciiCast;
+use std::fs::{File, OpenOptions, create_dir_all};
 use std::io::{SeekFrom, Read, Write, Seek};
 use std::io::Result as IoResult;
 
+pub use self::header::CartridgeHeader;
+pub use self::error::CartridgeError;
+
+pub mod mapper;
 mod models;
+mod error;
+mod archive;
+mod inflate;
+mod patch;
+mod rtc;
+mod camera;
+mod image;
+pub mod header;
 
 /// Common state for all cartridge types
 pub struct Cartridge {
     /// Cartridge ROM data
-    rom:        Vec<u8>,
+    rom:         Vec<u8>,
+    /// Header parsed when the ROM is loaded
+    header:      CartridgeHeader,
     /// Cartridge RAM data
-    ram:        Vec<u8>,
+    ram:         Vec<u8>,
     /// Total number of ROM banks in this cart
-    rom_banks:  u8,
-    /// Current number of the rom bank mapped at [0x4000, 0x7fff]
-    rom_bank:   u8,
-    /// Current bank offset for the bank mapped at [0x4000, 0x7fff].
-    /// This value is added to ROM register addresses when they're in
-    /// that range.
-    rom_offset: i32,
-    /// Current bank offset for the RAM
-    ram_offset: u32,
-    /// If `true` RAM is write protected
-    ram_wp:     bool,
-    /// Certain cartridges allow banking either the RAM or ROM
-    /// depending on the value of this flag.
-    bank_ram:   bool,
-    /// struct used to handle model specific functions
-    model:      models::Model,
+    rom_banks:   u8,
+    /// Memory controller, owns the banking registers and any other
+    /// model specific state
+    mapper:      Box<mapper::Mapper>,
     /// Path to the ROM image for this cartridge
-    path:       PathBuf,
+    path:        PathBuf,
     /// optional save file used to store non-volatile RAM on emulator
     /// shutdown
-    save_file:  Option<File>,
+    save_file:   Option<File>,
 }
 
-impl Cartridge {
-    /// Load a Cartridge ROM from `path`.
-    pub fn from_path(rom_path: &Path) -> IoResult<Cartridge> {
-        let mut source = try!(File::open(rom_path));
-
-        let mut rom = Vec::new();
-
-        // There must always be at least two ROM banks
-        try!((&mut source).take(2 * ROM_BANK_SIZE as u64)
-             .read_to_end(&mut rom));
-
-        let model = models::from_id(rom[offsets::TYPE]);
-
-        let mut cartridge = Cartridge {
-            rom:        rom,
-            ram:        Vec::new(),
-            rom_banks:  2,
-            // Default to bank 1 for bankable region
-            rom_bank:   1,
-            rom_offset: 0,
-            ram_offset: 0,
-            ram_wp:     true,
-            bank_ram:   false,
-            model:      model,
-            path:       PathBuf::from(rom_path),
-            save_file:  None,
+/// Load a ROM image from `path` which can be a plain ROM image, a
+/// gzip file or a zip archive. Returns the ROM along with the path of
+/// the ROM file, used to name the save file.
+///
+/// `entry` selects the ROM in a zip archive, the first file with a
+/// Game Boy ROM extension is used if it's `None`. The returned path
+/// is next to the archive and named after the ROM it contains.
+///
+/// The IPS, UPS or BPS `patch` is applied to the ROM in memory. If
+/// it's `None` a patch with the same name as the ROM is used if
+/// there's one.
+pub fn load_rom(path: &Path,
+                entry: Option<&str>,
+                patch: Option<&Path>) -> Result<(Vec<u8>, PathBuf), CartridgeError> {
+    let mut data = Vec::new();
+
+    try!(try!(File::open(path)).read_to_end(&mut data));
+
+    let (mut rom, rom_path) = try!(archive::extract(path, data, entry));
+
+    let patch =
+        match patch {
+            Some(p) => Some(PathBuf::from(p)),
+            None    => patch::EXTENSIONS.iter()
+                .map(|ext| rom_path.with_extension(ext))
+                .find(|p| p.is_file()),
         };
 
-        let rombanks = match cartridge.parse_rom_banks() {
-            Some(n) => n,
-            None    => panic!("Can't determine ROM size"),
-        };
+    if let Some(patch) = patch {
+        println!("Applying patch {}", patch.display());
 
-        cartridge.rom_banks = rombanks;
+        rom = try!(apply_patch(&patch, &rom));
+    }
 
-        // Read the remaining roms banks
-        if rombanks > 2 {
-            let remb      = (rombanks - 2) as usize;
-            let mut off   = 2    * ROM_BANK_SIZE as usize;
-            let mut remsz = remb * ROM_BANK_SIZE as usize;
+    Ok((rom, rom_path))
+}
 
-            // Reserve space for the remaining banks
-            cartridge.rom.extend(repeat(0).take(remsz));
+/// Parse the header describing the cartridge in `rom`
+pub fn parse_header(rom: &[u8]) -> Result<CartridgeHeader, CartridgeError> {
+    if rom.len() < offsets::HEADER_END {
+        return Err(CartridgeError::Truncated {
+            expected: offsets::HEADER_END,
+            actual:   rom.len(),
+        });
+    }
 
-            while remsz > 0 {
-                let r = try!(source.read(&mut cartridge.rom[off..]));
+    // MMM01 multicarts boot on a menu stored at the end of the ROM,
+    // its header describes the whole cartridge
+    Ok(CartridgeHeader::parse(rom, models::header_offset(rom)))
+}
 
-                remsz -= r;
-                off   += r;
-            }
+impl Cartridge {
+    /// Load a Cartridge ROM with `load_rom`. `init_ram` must be
+    /// called before running the game.
+    pub fn from_path(path: &Path,
+                     entry: Option<&str>,
+                     patch: Option<&Path>) -> Result<Cartridge, CartridgeError> {
+        let (rom, rom_path) = try!(load_rom(path, entry, patch));
+
+        Cartridge::from_rom(rom, rom_path)
+    }
+
+    /// Create a Cartridge from the ROM image `rom`. `path` is the
+    /// location of the ROM file, used to name the save file.
+    pub fn from_rom(mut rom: Vec<u8>, path: PathBuf) -> Result<Cartridge, CartridgeError> {
+        let header = try!(parse_header(&rom));
+
+        let rom_banks =
+            match rom_banks(header.rom_size) {
+                Some(n) => n,
+                None    => return Err(CartridgeError::BadHeader {
+                    field: "ROM size",
+                    value: header.rom_size,
+                }),
+            };
+
+        let rom_size = rom_banks as usize * ROM_BANK_SIZE;
+
+        if rom.len() < rom_size {
+            return Err(CartridgeError::Truncated {
+                expected: rom_size,
+                actual:   rom.len(),
+            });
         }
 
-        try!(cartridge.init_ram());
+        // Ignore any trailing garbage after the last bank
+        rom.truncate(rom_size);
+
+        let mapper = {
+            let info = mapper::Info {
+                rom:       &rom,
+                header:    &header,
+                rom_banks: rom_banks as usize,
+            };
+
+            match models::new(&info) {
+                Some(m) => m,
+                None    => return Err(CartridgeError::UnsupportedMapper(header.cartridge_type)),
+            }
+        };
+
+        let cartridge = Cartridge {
+            header:      header,
+            rom:         rom,
+            ram:         Vec::new(),
+            rom_banks:   rom_banks,
+            mapper:      mapper,
+            path:        path,
+            save_file:   None,
+        };
+
+        // Make sure the RAM size is valid before going any further
+        try!(cartridge.ram_size());
 
         Ok(cartridge)
     }
 
     /// Init cartridge RAM and tie it with a `File` for saving if
-    /// necessary.
-    fn init_ram(&mut self) -> IoResult<()> {
-        let (rambanks, banksize) = match self.parse_ram_banks() {
-            Some(v) => v,
-            None    => panic!("Can't determine RAM size"),
-        };
-
-        let ramsize = rambanks * banksize;
+    /// necessary. The save file is put in `save_dir` if it's not
+    /// `None`, next to the ROM otherwise.
+    pub fn init_ram(&mut self, save_dir: Option<&Path>) -> Result<(), CartridgeError> {
+        let ramsize = try!(self.ram_size());
 
         if ramsize == 0 {
             // No RAM on this cartridge, we're done
@@ -115,7 +171,16 @@ impl Cartridge {
 
         // We have some RAM, open the save file or create it if it
         // doesn't exist yet
-        let mut savepath = self.path.clone();
+        let mut savepath =
+            match (save_dir, self.path.file_name()) {
+                (Some(dir), Some(name)) => {
+                    try!(create_dir_all(dir));
+
+                    dir.join(name)
+                }
+                _ => self.path.clone(),
+            };
+
         savepath.set_extension("sav");
 
         let mut save_file = try!(OpenOptions::new()
@@ -126,6 +191,9 @@ impl Cartridge {
 
         let save_size = try!(save_file.metadata()).len();
 
+        // Some models store more than the RAM (the RTC for instance)
+        let extra = self.mapper.save_data();
+
         if save_size == 0 {
             // The file is empty (probably new). initialize
             // the RAM with 0s.
@@ -133,12 +201,28 @@ impl Cartridge {
             // Then fill the file with the right amount of 0s
             // to reserve enough space for saving later.
             try!(save_file.write_all(&self.ram));
-        } else if save_size == (ramsize as u64) {
-            // The file contains a RAM image
-            try!((&mut save_file).take(ramsize as u64).read_to_end(&mut self.ram));
+            try!(save_file.write_all(&extra));
+        } else if save_size == (ramsize as u64) ||
+            save_size == (ramsize + extra.len()) as u64 {
+            // The file contains a RAM image, the extra data is
+            // optional since other emulators might not save it
+            let mut data = Vec::new();
+
+            try!(save_file.read_to_end(&mut data));
+
+            let extra = data.split_off(ramsize);
+
+            self.ram = data;
+
+            if !extra.is_empty() {
+                self.mapper.load_save_data(&extra);
+            }
         } else {
-            panic!("Unexpected save file size for {}: expected {} got {}",
-                   savepath.display(), ramsize, save_size);
+            return Err(CartridgeError::SaveSize {
+                path:     savepath,
+                expected: ramsize + extra.len(),
+                actual:   save_size,
+            });
         }
 
         // Store the file handle to save progress later
@@ -147,6 +231,27 @@ impl Cartridge {
         Ok(())
     }
 
+    /// Init cartridge RAM without any save file, its contents are
+    /// lost when the emulator stops
+    pub fn init_volatile_ram(&mut self) -> Result<(), CartridgeError> {
+        let ramsize = try!(self.ram_size());
+
+        self.ram = vec![0; ramsize];
+
+        Ok(())
+    }
+
+    /// Return the total size of the cartridge RAM in bytes
+    fn ram_size(&self) -> Result<usize, CartridgeError> {
+        match self.parse_ram_banks() {
+            Some((rambanks, banksize)) => Ok(rambanks * banksize),
+            None => Err(CartridgeError::BadHeader {
+                field: "RAM size",
+                value: self.header.ram_size,
+            }),
+        }
+    }
+
     /// Update the save file
     pub fn save_ram(&mut self) -> IoResult<()> {
         if let Some(mut f) = self.save_file.as_mut() {
@@ -156,6 +261,7 @@ impl Cartridge {
 
             try!(f.seek(SeekFrom::Start(0)));
             try!(f.write_all(&self.ram));
+            try!(f.write_all(&self.mapper.save_data()));
         }
 
         Ok(())
@@ -163,171 +269,86 @@ impl Cartridge {
 
     /// Attempt to retreive the rom's name
     pub fn name(&self) -> Option<String> {
-        let mut name = String::with_capacity(16);
-
-        for i in 0..16 {
-            let c =
-                match self.rom[offsets::TITLE + i].to_ascii() {
-                    Ok(c) => c,
-                    _     => return None,
-                };
-
-            // If the name is shorter than 16bytes it's padded with 0s
-            if c.as_byte() == 0 {
-                break;
-            }
-
-            // Only uppercase ASCII is valid, but let's be a little
-            // more lenient
-            if !c.is_print() {
-                return None;
-            }
-
-            // Append new character
-            name.push(c.as_char());
-        }
-
-        Some(name)
+        self.header.title.clone()
     }
 
-    /// Return the number of ROM banks declared in the header. Each
-    /// bank is 16KB.
-    fn parse_rom_banks(&self) -> Option<u8> {
-        let id = self.rom_byte(offsets::ROM_SIZE as u16);
-
-        let nbanks =
-            match id {
-                0x00 => 2,
-                0x01 => 4,
-                0x02 => 8,
-                0x03 => 16,
-                0x04 => 32,
-                0x05 => 64,
-                0x06 => 128,
-                0x52 => 72,
-                0x53 => 80,
-                0x54 => 96,
-                // Unknown value
-                _    => return None,
-            };
+    /// Return the sum of the bytes of the title. The Game Boy Color
+    /// bootrom uses it to pick a color palette for the game.
+    pub fn title_checksum(&self) -> u8 {
+        self.header.title_checksum
+    }
 
-        Some(nbanks)
+    /// Return the parsed cartridge header
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
     }
 
     /// Return the number of RAM banks for this ROM along with the
     /// size of each bank in bytes.
     pub fn parse_ram_banks(&self) -> Option<(usize, usize)> {
-
-        let model = models::from_id(self.rom_byte(offsets::TYPE as u16));
-
-        // Special case for MBC2, the RAM_SIZE field is not
-        // trustworthy here (it advertises 0 banks but there's still
-        // some RAM on the cartridge).
-        if model.name == "MBC2" {
-            // MBC2 contains 1 "bank" of 256bytes
-            return Some((1, 256));
-        }
-
-        let id = self.rom_byte(offsets::RAM_SIZE as u16);
-
-        let (nbanks, bank_size_kb) =
-            match id {
-                0x00 => (0,  0),
-                0x01 => (1,  2),
-                0x02 => (1,  8),
-                0x03 => (4,  8),
-                0x04 => (16, 8),
-                // Unknown value
-                _    => return None,
-            };
-
-        Some((nbanks, bank_size_kb * 1024))
+        self.mapper.ram_banks()
     }
 
     pub fn rom_byte(&self, offset: u16) -> u8 {
-        let off = offset as i32;
-
-        if off < ROM_BANK_SIZE {
-            self.rom[off as usize]
-        } else {
-            self.rom[(self.rom_offset + off) as usize]
-        }
+        self.mapper.read_rom(&self.rom, offset)
     }
 
     pub fn set_rom_byte(&mut self, offset: u16, val: u8) {
-        (self.model.write_rom)(self, offset, val)
+        self.mapper.write_rom(offset, val)
     }
 
     /// Return the value of RAM byte at `offset` in the currently
     /// selected RAM bank
     pub fn ram_byte(&self, offset: u16) -> u8 {
-        let addr = self.ram_offset + offset as u32;
-
-        (self.model.read_ram)(self, addr)
-    }
-
-    /// Return the value of a RAM byte at absolute address `addr`
-    fn ram_byte_absolute(&self, addr: u32) -> u8 {
-        *self.ram.get(addr as usize).unwrap_or(&0)
-    }
-
-    fn ram_byte_absolute_mut(&mut self, addr: u32) -> Option<&mut u8> {
-        self.ram.get_mut(addr as usize)
+        self.mapper.read_ram(&self.ram, offset)
     }
 
     /// Set value of RAM byte at `offset` in the curretly selected RAM
     /// bank
     pub fn set_ram_byte(&mut self, offset: u16, val: u8) {
-        let addr = self.ram_offset + offset as u32;
-
-        if self.ram_wp {
-            debug!("Attempt to write to cartridge RAM while protected");
-            return;
-        }
-
-        (self.model.write_ram)(self, addr, val);
+        self.mapper.write_ram(&mut self.ram, offset, val)
     }
 
-    /// Retreive the number of ROM banks in the cartridge
-    pub fn rom_banks(&self) -> u8 {
-        self.rom_banks
-    }
-
-    /// Retrieve current ROM bank number for the bankable range at
-    /// [0x4000, 0x7fff]
-    pub fn rom_bank(&self) -> u8 {
-        self.rom_bank
+    /// Advance the cartridge hardware by one machine cycle
+    pub fn step(&mut self) {
+        self.mapper.step()
     }
 
-    /// Set new ROM bank number for the bankable range at
-    /// [0x4000, 0x7fff]
-    pub fn set_rom_bank(&mut self, bank: u8) {
-        self.rom_bank = bank;
-    }
+    /// Use the image file or the directory of images at `path` as
+    /// the input of the Pocket Camera
+    pub fn set_camera_input(&mut self, path: &Path) -> Result<(), CartridgeError> {
+        let result =
+            match self.mapper.camera() {
+                Some(camera) => camera.set_input(path),
+                None         => Err("not a Pocket Camera cartridge".to_string()),
+            };
 
-    pub fn set_rom_offset(&mut self, offset: i32) {
-        self.rom_offset = offset;
+        result.map_err(|e| {
+            CartridgeError::Camera {
+                path:  path.to_path_buf(),
+                error: e,
+            }
+        })
     }
 
-    /// Enable or disable RAM write protect
-    pub fn set_ram_wp(&mut self, wp: bool) {
-        self.ram_wp = wp
+    /// Return the accelerometer of the cartridge, if it has one
+    pub fn accelerometer(&mut self) -> Option<&mut mapper::Accelerometer> {
+        self.mapper.accelerometer()
     }
 
-    /// Return the value of the `bank_ram` flag
-    pub fn bank_ram(&self) -> bool {
-        self.bank_ram
+    /// Retreive the number of ROM banks in the cartridge
+    pub fn rom_banks(&self) -> u8 {
+        self.rom_banks
     }
 
-    /// Set the value of the `bank_ram` flag
-    pub fn set_bank_ram(&mut self, v: bool) {
-        self.bank_ram = v
+    /// Return the whole ROM image
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
     }
 
-    /// Set new RAM bank number
-    pub fn set_ram_bank(&mut self, bank: u8) {
-        // Bankable RAM is always 8KB per bank
-        self.ram_offset = bank as u32 * 8 * 1024;
+    /// Return the name of the memory controller
+    pub fn model_name(&self) -> &'static str {
+        self.mapper.name()
     }
 
     /// Create a Cartridge instance from a ROM provided in a
@@ -335,18 +356,24 @@ impl Cartridge {
     /// banking.
     #[cfg(test)]
     pub fn from_vec(rom: Vec<u8>) -> Cartridge {
+        let mut header = CartridgeHeader::parse(&rom, 0);
+
+        header.cartridge_type = 0x00;
+
+        let mapper = models::new(&mapper::Info {
+            rom:       &rom,
+            header:    &header,
+            rom_banks: 2,
+        }).unwrap();
+
         Cartridge {
-            rom:        rom,
-            ram:        Vec::new(),
-            rom_bank:   1,
-            rom_banks:  2,
-            rom_offset: 0,
-            ram_offset: 0,
-            ram_wp:     true,
-            bank_ram:   false,
-            model:      models::from_id(0x00),
-            path:       PathBuf::from("dummy"),
-            save_file:  None,
+            header:      header,
+            rom:         rom,
+            ram:         Vec::new(),
+            rom_banks:   2,
+            mapper:      mapper,
+            path:        PathBuf::from("dummy"),
+            save_file:   None,
         }
     }
 }
@@ -380,23 +407,199 @@ impl Debug for Cartridge {
                     "'{}' (Model: {}, \
                            ROM banks: {}, \
                            RAM banks: {}, \
-                           RAM bank size: {}B)",
-                    name, self.model.name, rombanks, rambanks, rambanksize));
+                           RAM bank size: {}B, \
+                           {:?})",
+                    name, self.mapper.name(), rombanks, rambanks, rambanksize,
+                    self.header));
 
         Ok(())
     }
 }
 
+/// Apply the patch file at `path` to `rom`
+fn apply_patch(path: &Path, rom: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let mut patch = Vec::new();
+
+    try!(try!(File::open(path)).read_to_end(&mut patch));
+
+    patch::apply(&patch, rom).map_err(|e| CartridgeError::Patch {
+        path:  PathBuf::from(path),
+        error: e,
+    })
+}
+
+/// Return the number of ROM banks for the ROM size code `id` found
+/// in the header. Each bank is 16KB.
+fn rom_banks(id: u8) -> Option<u8> {
+    let nbanks =
+        match id {
+            0x00 => 2,
+            0x01 => 4,
+            0x02 => 8,
+            0x03 => 16,
+            0x04 => 32,
+            0x05 => 64,
+            0x06 => 128,
+            0x52 => 72,
+            0x53 => 80,
+            0x54 => 96,
+            // Unknown value
+            _    => return None,
+        };
+
+    Some(nbanks)
+}
+
 // Each ROM bank is always 16KB
-const ROM_BANK_SIZE: i32 = 16 * 1024;
+const ROM_BANK_SIZE: usize = 16 * 1024;
 
 mod offsets {
     //! Various offset values to access special memory locations within the ROM
 
+    /// Nintendo logo checked by the bootrom
+    pub const LOGO:            usize = 0x104;
     /// Title. Upper case ASCII 16bytes long, padded with 0s if shorter
-    pub const TITLE:    usize = 0x134;
+    pub const TITLE:           usize = 0x134;
+    /// Game Boy Color support, overlaps the last byte of the title
+    pub const CGB_FLAG:        usize = 0x143;
+    /// Two character licensee code, used if OLD_LICENSEE is 0x33
+    pub const NEW_LICENSEE:    usize = 0x144;
+    /// Super Game Boy support
+    pub const SGB_FLAG:        usize = 0x146;
     /// Cartridge type
-    pub const TYPE:     usize = 0x147;
-    pub const ROM_SIZE: usize = 0x148;
-    pub const RAM_SIZE: usize = 0x149;
+    pub const TYPE:            usize = 0x147;
+    pub const ROM_SIZE:        usize = 0x148;
+    pub const RAM_SIZE:        usize = 0x149;
+    /// 0 for Japan, 1 for the rest of the world
+    pub const DESTINATION:     usize = 0x14a;
+    pub const OLD_LICENSEE:    usize = 0x14b;
+    /// Mask ROM version
+    pub const VERSION:         usize = 0x14c;
+    /// Checksum of [TITLE, VERSION], checked by the bootrom
+    pub const HEADER_CHECKSUM: usize = 0x14d;
+    /// Big endian sum of all the ROM bytes
+    pub const GLOBAL_CHECKSUM: usize = 0x14e;
+    /// End of the cartridge header
+    pub const HEADER_END:      usize = 0x150;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cartridge, CartridgeError};
+    use std::fs::{File, remove_file};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    /// Return a ROM path in the temporary directory for the test
+    /// `name`, unique to this process so that concurrent test runs
+    /// don't share the files
+    pub fn temp_rom_path(name: &str) -> PathBuf {
+        ::std::env::temp_dir().join(format!("gb-rs-{}-{}.gb", name, ::std::process::id()))
+    }
+
+    /// Write a ROM image of `len` bytes with the given header values
+    /// in the temporary directory
+    fn rom_file(name: &str, len: usize, kind: u8, rom_size: u8, ram_size: u8) -> PathBuf {
+        let mut rom = vec![0; ::std::cmp::max(len, 0x150)];
+
+        rom[0x147] = kind;
+        rom[0x148] = rom_size;
+        rom[0x149] = ram_size;
+
+        rom.truncate(len);
+
+        let path = temp_rom_path(name);
+
+        File::create(&path).unwrap().write_all(&rom).unwrap();
+
+        path
+    }
+
+    /// Remove the ROM written by `rom_file` and the files the test
+    /// created next to it
+    fn remove_rom(path: &Path) {
+        for ext in &["gb", "sav", "ips", "bad"] {
+            let _ = remove_file(path.with_extension(ext));
+        }
+    }
+
+    fn load(name: &str, len: usize, kind: u8, rom_size: u8, ram_size: u8)
+            -> Result<Cartridge, CartridgeError> {
+        let path = rom_file(name, len, kind, rom_size, ram_size);
+
+        let cart = Cartridge::from_path(&path, None, None);
+
+        remove_rom(&path);
+
+        cart
+    }
+
+    #[test]
+    fn load_errors() {
+        match load("header", 0x100, 0, 0, 0) {
+            Err(CartridgeError::Truncated { .. }) => (),
+            _ => panic!("truncated header not detected"),
+        }
+
+        match load("short", 0x8000, 0x01, 0x01, 0) {
+            Err(CartridgeError::Truncated { expected: 0x10000, actual: 0x8000 }) => (),
+            _ => panic!("truncated ROM not detected"),
+        }
+
+        match load("mapper", 0x8000, 0x04, 0, 0) {
+            Err(CartridgeError::UnsupportedMapper(0x04)) => (),
+            _ => panic!("unsupported mapper not detected"),
+        }
+
+        match load("rom_size", 0x8000, 0, 0x42, 0) {
+            Err(CartridgeError::BadHeader { field: "ROM size", value: 0x42 }) => (),
+            _ => panic!("bad ROM size not detected"),
+        }
+
+        match load("ram_size", 0x8000, 0, 0, 0x42) {
+            Err(CartridgeError::BadHeader { field: "RAM size", value: 0x42 }) => (),
+            _ => panic!("bad RAM size not detected"),
+        }
+
+        let path = rom_file("save", 0x8000, 0x03, 0, 0x02);
+
+        File::create(path.with_extension("sav")).unwrap().write_all(&[0; 100]).unwrap();
+
+        let mut cart = Cartridge::from_path(&path, None, None).unwrap();
+
+        match cart.init_ram(None) {
+            Err(CartridgeError::SaveSize { expected: 0x2000, actual: 100, .. }) => (),
+            _ => panic!("bad save size not detected"),
+        }
+
+        remove_rom(&path);
+    }
+
+    #[test]
+    fn patches() {
+        let path = rom_file("patched", 0x8000, 0, 0, 0);
+
+        // IPS patch writing the title
+        let mut ips = b"PATCH\x00\x01\x34\x00\x04GAME".to_vec();
+
+        ips.extend_from_slice(b"EOF");
+
+        File::create(path.with_extension("ips")).unwrap().write_all(&ips).unwrap();
+
+        let cart = Cartridge::from_path(&path, None, None).unwrap();
+
+        assert!(cart.name() == Some("GAME".to_string()));
+
+        // An explicit patch replaces the one next to the ROM
+        let bad = path.with_extension("bad");
+
+        File::create(&bad).unwrap().write_all(b"UPS1").unwrap();
+
+        match Cartridge::from_path(&path, None, Some(&bad)) {
+            Err(CartridgeError::Patch { .. }) => (),
+            _ => panic!("bad patch not detected"),
+        }
+
+        remove_rom(&path);
+    }
 }
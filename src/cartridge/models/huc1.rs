@@ -0,0 +1,124 @@
+//! Hudson HuC1 memory bank controller with an infrared port
+
+use cartridge::mapper::{Mapper, Info, Banks};
+
+pub struct Huc1 {
+    banks:    Banks,
+    /// The infrared port is mapped at [0xa000, 0xbfff] instead of
+    /// the RAM
+    ir:       bool,
+    infrared: Infrared,
+}
+
+impl Huc1 {
+    pub fn new(info: &Info) -> Huc1 {
+        Huc1 {
+            banks:    Banks::new(info),
+            ir:       false,
+            infrared: Infrared::new(),
+        }
+    }
+}
+
+impl Mapper for Huc1 {
+    fn name(&self) -> &'static str {
+        "HuC1"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff => {
+                // 0xe selects the infrared port, anything else the
+                // RAM. There's no write protection.
+                self.ir = val & 0xf == 0xe;
+
+                self.banks.set_ram_wp(false);
+            }
+            0x2000...0x3fff => self.banks.set_rom_bank(val as usize & 0x3f),
+            0x4000...0x5fff => self.banks.set_ram_bank(val as usize & 0x3),
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        if self.ir {
+            self.infrared.read()
+        } else {
+            self.banks.read_ram(ram, offset)
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        if self.ir {
+            self.infrared.write(val);
+        } else {
+            self.banks.write_ram(ram, offset, val);
+        }
+    }
+}
+
+/// Infrared port of the HuC1 and HuC3: bit 0 drives the LED when
+/// written and is set when the sensor receives light when read.
+/// There's no other console to talk to so the sensor only sees the
+/// light of our own LED, like on the real hardware.
+pub struct Infrared {
+    led: bool,
+}
+
+impl Infrared {
+    pub fn new() -> Infrared {
+        Infrared {
+            led: false,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        IR_DARK | self.led as u8
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.led = val & 1 != 0;
+    }
+}
+
+/// Value of the IR port when no light is received
+const IR_DARK: u8 = 0xc0;
+
+#[cfg(test)]
+mod tests {
+    use cartridge::models::tests::{cartridge, banks};
+
+    #[test]
+    fn huc1() {
+        let mut cart = cartridge(0xff, 4, 0x03, false);
+
+        assert!(cart.model_name() == "HuC1");
+
+        cart.set_rom_byte(0x0000, 0x0a);
+        cart.set_rom_byte(0x4000, 0x02);
+        cart.set_ram_byte(0, 0x55);
+        assert!(cart.ram_byte(0) == 0x55);
+
+        // Infrared port
+        cart.set_rom_byte(0x0000, 0x0e);
+        assert!(cart.ram_byte(0) == 0xc0);
+        cart.set_ram_byte(0, 0x01);
+        assert!(cart.ram_byte(0) == 0xc1);
+        cart.set_ram_byte(0, 0x00);
+        assert!(cart.ram_byte(0) == 0xc0);
+
+        cart.set_rom_byte(0x0000, 0x00);
+        assert!(cart.ram_byte(0) == 0x55);
+
+        cart.set_rom_byte(0x2000, 0x03);
+        assert!(banks(&cart) == (0, 3));
+    }
+}
@@ -0,0 +1,312 @@
+//! Hudson HuC3 memory bank controller with a real time clock
+
+use cartridge::mapper::{Mapper, Info, Banks};
+use cartridge::rtc::Clock;
+use super::huc1::Infrared;
+
+/// The HuC3 maps either the RAM or one of its I/O registers at
+/// [0xa000, 0xbfff] depending on the mode written at
+/// [0x0000, 0x1fff]. The RTC is accessed through a small memory
+/// addressed one nibble at a time using commands.
+pub struct Huc3 {
+    banks:         Banks,
+    mode:          u8,
+    clock:         Clock,
+    alarm_minutes: u16,
+    alarm_days:    u16,
+    alarm_enabled: bool,
+    /// Address of the next RTC memory access
+    address:       u8,
+    /// Last command
+    command:       u8,
+    /// Value returned by the last read command
+    value:         u8,
+    /// Argument of the last "special" command (6)
+    special:       u8,
+    infrared:      Infrared,
+}
+
+impl Huc3 {
+    pub fn new(info: &Info) -> Huc3 {
+        Huc3 {
+            banks:         Banks::new(info),
+            mode:          0,
+            clock:         Clock::new(),
+            alarm_minutes: 0,
+            alarm_days:    0,
+            alarm_enabled: false,
+            address:       0,
+            command:       0,
+            value:         0,
+            special:       0,
+            infrared:      Infrared::new(),
+        }
+    }
+
+    /// Execute the RTC command `val`: command in bits [6:4],
+    /// argument in bits [3:0]
+    fn command(&mut self, val: u8) {
+        let arg = val & 0xf;
+
+        self.command = (val >> 4) & 7;
+
+        self.clock.update();
+
+        match self.command {
+            // Read and increment the address
+            1 => {
+                self.value = self.nibble();
+                self.address = self.address.wrapping_add(1);
+            }
+            // Write
+            2 => self.set_nibble(arg),
+            // Write and increment the address
+            3 => {
+                self.set_nibble(arg);
+                self.address = self.address.wrapping_add(1);
+            }
+            // Set the address, low then high nibble
+            4 => self.address = (self.address & 0xf0) | arg,
+            5 => self.address = (self.address & 0x0f) | (arg << 4),
+            6 => {
+                self.special = arg;
+
+                // The speaker isn't emulated
+                if arg == 0xe {
+                    debug!("HuC3 speaker tone");
+                }
+            }
+            c => debug!("Unhandled HuC3 command {:x}", c),
+        }
+    }
+
+    /// Return the RTC register nibble at `address` along with the
+    /// nibble index. Minutes are at [0x00, 0x02], days at
+    /// [0x03, 0x06] and the alarm at [0x58, 0x5f].
+    fn register(&mut self) -> Option<(&mut u16, u8)> {
+        match self.address {
+            0x00...0x02 => Some((&mut self.clock.minutes, self.address)),
+            0x03...0x06 => Some((&mut self.clock.days,    self.address - 0x03)),
+            0x58...0x5a => Some((&mut self.alarm_minutes, self.address - 0x58)),
+            0x5b...0x5e => Some((&mut self.alarm_days,    self.address - 0x5b)),
+            _ => None,
+        }
+    }
+
+    fn nibble(&mut self) -> u8 {
+        if self.address == 0x5f {
+            return self.alarm_enabled as u8;
+        }
+
+        match self.register() {
+            Some((r, n)) => (*r >> (n * 4)) as u8 & 0xf,
+            None         => 0,
+        }
+    }
+
+    fn set_nibble(&mut self, val: u8) {
+        if self.address == 0x5f {
+            self.alarm_enabled = val & 1 != 0;
+            return;
+        }
+
+        match self.register() {
+            Some((r, n)) => {
+                *r &= !(0xf << (n * 4));
+                *r |= (val as u16) << (n * 4);
+            }
+            None => debug!("Unhandled HuC3 RTC write {:02x}", val),
+        }
+    }
+}
+
+impl Mapper for Huc3 {
+    fn name(&self) -> &'static str {
+        "HuC3"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff => {
+                self.mode = val & 0xf;
+
+                // In mode 0 the RAM is read-only, the other modes
+                // are handled by write_ram
+                self.banks.set_ram_wp(self.mode == 0);
+            }
+            0x2000...0x3fff => self.banks.set_rom_bank(val as usize & 0x7f),
+            0x4000...0x5fff => self.banks.set_ram_bank(val as usize & 0x3),
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        match self.mode {
+            0x0 | 0xa => self.banks.read_ram(ram, offset),
+            // Result of the last command, with the command in the
+            // upper nibble
+            0xc =>
+                if self.special == 0x2 {
+                    1
+                } else {
+                    (self.command << 4) | self.value
+                },
+            // RTC semaphore: always ready
+            0xd => 1,
+            0xe => self.infrared.read(),
+            _ => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        match self.mode {
+            0xa => self.banks.write_ram(ram, offset, val),
+            0xb => self.command(val),
+            0xe => self.infrared.write(val),
+            // Games write 0xfe to the RTC semaphore, we don't
+            // need it
+            _ => (),
+        }
+    }
+
+    /// The RTC is stored the way SameBoy does it: the host
+    /// timestamp (64 bits), minutes, days, alarm minutes and
+    /// days (16 bits each) and the alarm enable flag, little
+    /// endian.
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SAVE_SIZE);
+
+        for i in 0..8 {
+            data.push((self.clock.timestamp >> (i * 8)) as u8);
+        }
+
+        for &v in [self.clock.minutes,
+                   self.clock.days,
+                   self.alarm_minutes,
+                   self.alarm_days].iter() {
+            data.push(v as u8);
+            data.push((v >> 8) as u8);
+        }
+
+        data.push(self.alarm_enabled as u8);
+
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if data.len() != SAVE_SIZE {
+            return;
+        }
+
+        let u16_at = |i: usize| data[i] as u16 | (data[i + 1] as u16) << 8;
+
+        self.clock.timestamp = (0..8).fold(0, |t, i| t | (data[i] as u64) << (i * 8));
+        self.clock.minutes   = u16_at(8);
+        self.clock.days      = u16_at(10);
+        self.alarm_minutes   = u16_at(12);
+        self.alarm_days      = u16_at(14);
+        self.alarm_enabled   = data[16] != 0;
+    }
+}
+
+/// Size of the RTC data in the save file
+const SAVE_SIZE: usize = 17;
+
+#[cfg(test)]
+mod tests {
+    use cartridge::Cartridge;
+    use cartridge::tests::temp_rom_path;
+
+    /// Send HuC3 RTC commands
+    fn huc3_commands(cart: &mut Cartridge, commands: &[u8]) {
+        cart.set_rom_byte(0x0000, 0x0b);
+
+        for &c in commands {
+            cart.set_ram_byte(0, c);
+        }
+    }
+
+    /// Read `n` nibbles of the HuC3 RTC memory at `address`
+    fn huc3_read(cart: &mut Cartridge, address: u8, n: usize) -> u32 {
+        huc3_commands(cart, &[0x40 | (address & 0xf), 0x50 | (address >> 4)]);
+
+        (0..n).fold(0, |v, i| {
+            huc3_commands(cart, &[0x10]);
+
+            cart.set_rom_byte(0x0000, 0x0c);
+
+            let r = cart.ram_byte(0);
+
+            // The command is returned in the upper nibble
+            assert!(r >> 4 == 1);
+
+            v | ((r & 0xf) as u32) << (i * 4)
+        })
+    }
+
+    #[test]
+    fn huc3() {
+        let path = temp_rom_path("huc3");
+
+        let mut rom = vec![0; 0x8000];
+
+        rom[0x147] = 0xfe;
+        rom[0x149] = 0x02;
+
+        let mut cart = Cartridge::from_rom(rom.clone(), path.clone()).unwrap();
+
+        cart.init_ram(None).unwrap();
+
+        assert!(cart.model_name() == "HuC3");
+
+        // RAM is read-only in mode 0
+        cart.set_rom_byte(0x0000, 0x0a);
+        cart.set_ram_byte(0x10, 0x42);
+        cart.set_rom_byte(0x0000, 0x00);
+        cart.set_ram_byte(0x10, 0x24);
+        assert!(cart.ram_byte(0x10) == 0x42);
+
+        // Set the clock to day 0x123, minute 0x45
+        huc3_commands(&mut cart, &[0x40, 0x50,
+                                   0x35, 0x34, 0x30,
+                                   0x33, 0x32, 0x31, 0x30]);
+
+        assert!(huc3_read(&mut cart, 0, 3) == 0x45);
+        assert!(huc3_read(&mut cart, 3, 4) == 0x123);
+
+        cart.set_rom_byte(0x0000, 0x0d);
+        assert!(cart.ram_byte(0) == 1);
+
+        // Infrared port, the sensor sees the LED
+        cart.set_rom_byte(0x0000, 0x0e);
+        assert!(cart.ram_byte(0) == 0xc0);
+        cart.set_ram_byte(0, 0x01);
+        assert!(cart.ram_byte(0) == 0xc1);
+
+        // The RAM and the clock are saved
+        drop(cart);
+
+        assert!(::std::fs::metadata(path.with_extension("sav")).unwrap().len() ==
+                0x2000 + 17);
+
+        let mut cart = Cartridge::from_rom(rom, path.clone()).unwrap();
+
+        cart.init_ram(None).unwrap();
+
+        cart.set_rom_byte(0x0000, 0x00);
+        assert!(cart.ram_byte(0x10) == 0x42);
+        assert!(huc3_read(&mut cart, 3, 4) == 0x123);
+
+        drop(cart);
+
+        ::std::fs::remove_file(path.with_extension("sav")).unwrap();
+    }
+}
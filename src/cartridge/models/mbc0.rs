@@ -0,0 +1,41 @@
+//! ROM only cartridges, without any banking
+
+use cartridge::mapper::{Mapper, Info, Banks};
+
+pub struct Mbc0 {
+    banks: Banks,
+}
+
+impl Mbc0 {
+    pub fn new(info: &Info) -> Mbc0 {
+        Mbc0 {
+            banks: Banks::new(info),
+        }
+    }
+}
+
+impl Mapper for Mbc0 {
+    fn name(&self) -> &'static str {
+        "MBC0"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        debug!("Unhandled ROM write: {:04x} {:02x}", offset, val);
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        self.banks.read_ram(ram, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        self.banks.write_ram(ram, offset, val);
+    }
+}
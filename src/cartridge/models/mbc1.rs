@@ -0,0 +1,221 @@
+//! MBC1 memory bank controller, including the multicart wiring
+
+use cartridge::mapper::{Mapper, Info, Banks};
+use cartridge::ROM_BANK_SIZE;
+
+pub struct Mbc1 {
+    banks: Banks,
+    /// 5bit BANK1 register, lower bits of the ROM bank
+    bank1: u8,
+    /// 2bit BANK2 register, upper bits of the ROM bank
+    bank2: u8,
+    /// Banking mode: when set BANK2 also selects the RAM bank and
+    /// the bank mapped at [0x0000, 0x3fff]
+    mode1: bool,
+    /// Position of BANK2 in the ROM bank number
+    shift: u8,
+}
+
+impl Mbc1 {
+    /// MBC1M multicarts wire BANK2 to ROM address lines 18 and 19
+    /// instead of 19 and 20 so that each game has 16 banks. The
+    /// top bit of BANK1 is then ignored.
+    pub fn new(info: &Info) -> Mbc1 {
+        Mbc1 {
+            banks: Banks::new(info),
+            bank1: 1,
+            bank2: 0,
+            mode1: false,
+            shift: if is_multicart(info.rom) { 4 } else { 5 },
+        }
+    }
+
+    /// Update the ROM and RAM mappings from the registers.
+    ///
+    /// BANK2 always provides the upper bits of the bank mapped at
+    /// [0x4000, 0x7fff]. In mode 1 it's also used for the bank
+    /// mapped at [0x0000, 0x3fff] (normally bank 0) and to select
+    /// the RAM bank, in mode 0 they're both forced to 0.
+    fn remap(&mut self) {
+        let shift = self.shift;
+
+        // BANK1 can't be 0, it's checked on all 5 bits so 0x20,
+        // 0x40 and 0x60 can't be mapped at [0x4000, 0x7fff] and
+        // on multicarts writing 0x10 maps bank 0 of the current
+        // game.
+        let bank1 =
+            if self.bank1 == 0 {
+                1
+            } else {
+                self.bank1
+            };
+
+        let bank1 = bank1 & ((1 << shift) - 1);
+
+        let high = ((self.bank2 << shift) | bank1) as usize;
+        let low  = if self.mode1 { (self.bank2 << shift) as usize } else { 0 };
+
+        // If the bank overflows it wraps around. This assumes
+        // that MBC1 carts can only have a power of two number of
+        // banks.
+        self.banks.map_rom(low, high);
+
+        // Carts with 8KB of RAM or less ignore BANK2
+        let ram_banks = self.banks.ram_banks().map(|(n, _)| n).unwrap_or(0);
+
+        let ram_bank =
+            if self.mode1 && ram_banks > 1 {
+                self.bank2 as usize & (ram_banks - 1)
+            } else {
+                0
+            };
+
+        self.banks.set_ram_bank(ram_bank);
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn name(&self) -> &'static str {
+        if self.shift == 4 {
+            "MBC1M"
+        } else {
+            "MBC1"
+        }
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff =>
+                // Writing a low nibble 0xa to anywhere in that
+                // address range removes RAM write protect, All
+                // other values enable it.
+                self.banks.set_ram_wp(val & 0xf != 0xa),
+            0x2000...0x3fff => self.bank1 = val & 0x1f,
+            0x4000...0x5fff => self.bank2 = val & 3,
+            0x6000...0x7fff => self.mode1 = val & 1 != 0,
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+
+        self.remap();
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        self.banks.read_ram(ram, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        self.banks.write_ram(ram, offset, val);
+    }
+}
+
+/// Return true if `rom` looks like a multicart: 1MB with the
+/// Nintendo logo repeated in the header of each game, every 16
+/// banks.
+pub fn is_multicart(rom: &[u8]) -> bool {
+    use cartridge::header::LOGO;
+
+    if rom.len() != 64 * ROM_BANK_SIZE {
+        return false;
+    }
+
+    // The first game is the menu, check the others. Some
+    // multicarts only have 3 games so the last one is optional.
+    (1..3).all(|game| {
+        let logo = game * 16 * ROM_BANK_SIZE + 0x104;
+
+        &rom[logo..logo + LOGO.len()] == &LOGO[..]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cartridge::models::tests::{cartridge, banks};
+
+    #[test]
+    fn mbc1() {
+        let mut cart = cartridge(0x01, 128, 0, false);
+
+        assert!(cart.model_name() == "MBC1");
+        assert!(banks(&cart) == (0, 1));
+
+        cart.set_rom_byte(0x2000, 0x05);
+        assert!(banks(&cart) == (0, 5));
+
+        // Bank 0 is replaced by 1, only bits [4:0] are checked
+        cart.set_rom_byte(0x2000, 0x00);
+        assert!(banks(&cart) == (0, 1));
+
+        cart.set_rom_byte(0x4000, 0x01);
+        assert!(banks(&cart) == (0, 0x21));
+
+        cart.set_rom_byte(0x2000, 0x03);
+        cart.set_rom_byte(0x4000, 0x03);
+        assert!(banks(&cart) == (0, 0x63));
+
+        // Mode 1 also maps BANK2 at 0x0000
+        cart.set_rom_byte(0x6000, 0x01);
+        assert!(banks(&cart) == (0x60, 0x63));
+
+        cart.set_rom_byte(0x6000, 0x00);
+        assert!(banks(&cart) == (0, 0x63));
+
+        // Small ROMs ignore the upper bits
+        let mut cart = cartridge(0x01, 4, 0, false);
+
+        cart.set_rom_byte(0x2000, 0x07);
+        cart.set_rom_byte(0x4000, 0x01);
+        cart.set_rom_byte(0x6000, 0x01);
+        assert!(banks(&cart) == (0, 3));
+    }
+
+    #[test]
+    fn mbc1_ram() {
+        // 32KB of RAM
+        let mut cart = cartridge(0x03, 4, 0x03, false);
+
+        cart.set_rom_byte(0x0000, 0x0a);
+
+        cart.set_ram_byte(0, 0x11);
+
+        // BANK2 selects the RAM bank in mode 1 only
+        cart.set_rom_byte(0x4000, 0x02);
+        assert!(cart.ram_byte(0) == 0x11);
+
+        cart.set_rom_byte(0x6000, 0x01);
+        cart.set_ram_byte(0, 0x22);
+        assert!(cart.ram_byte(0) == 0x22);
+
+        cart.set_rom_byte(0x6000, 0x00);
+        assert!(cart.ram_byte(0) == 0x11);
+    }
+
+    #[test]
+    fn mbc1_multicart() {
+        let mut cart = cartridge(0x01, 64, 0, true);
+
+        assert!(cart.model_name() == "MBC1M");
+
+        // BANK2 selects the game, BANK1 is only 4 bits wide
+        cart.set_rom_byte(0x4000, 0x02);
+        cart.set_rom_byte(0x2000, 0x13);
+        assert!(banks(&cart) == (0, 0x23));
+
+        // 0x10 isn't 0 so bank 0 of the game is mapped
+        cart.set_rom_byte(0x2000, 0x10);
+        assert!(banks(&cart) == (0, 0x20));
+
+        cart.set_rom_byte(0x6000, 0x01);
+        assert!(banks(&cart) == (0x20, 0x20));
+
+        // Without the logos it's a regular MBC1
+        assert!(cartridge(0x01, 64, 0, false).model_name() == "MBC1");
+    }
+}
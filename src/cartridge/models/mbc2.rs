@@ -0,0 +1,53 @@
+//! MBC2 memory bank controller with its built-in RAM
+
+use cartridge::mapper::{Mapper, Info, Banks};
+
+pub struct Mbc2 {
+    banks: Banks,
+}
+
+impl Mbc2 {
+    pub fn new(info: &Info) -> Mbc2 {
+        Mbc2 {
+            banks: Banks::new(info),
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn name(&self) -> &'static str {
+        "MBC2"
+    }
+
+    /// The RAM size in the header is not trustworthy here (it
+    /// advertises 0 banks but there's still some RAM on the
+    /// cartridge): the MBC2 contains 1 "bank" of 256 bytes.
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        Some((1, 256))
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff =>
+                // Writing a low nibble 0xa to anywhere in that
+                // address range removes RAM write protect, All
+                // other values enable it.
+                self.banks.set_ram_wp(val & 0xf != 0xa),
+            0x2000...0x3fff =>
+                self.banks.set_rom_bank(val as usize & 0xf),
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        self.banks.read_ram(ram, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        self.banks.write_ram(ram, offset, val);
+    }
+}
@@ -0,0 +1,55 @@
+//! MBC3 memory bank controller
+
+use cartridge::mapper::{Mapper, Info, Banks};
+
+pub struct Mbc3 {
+    banks: Banks,
+}
+
+impl Mbc3 {
+    pub fn new(info: &Info) -> Mbc3 {
+        Mbc3 {
+            banks: Banks::new(info),
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn name(&self) -> &'static str {
+        "MBC3"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff =>
+                // Writing a low nibble 0xa to anywhere in that
+                // address range removes RAM write protect, All
+                // other values enable it.
+                self.banks.set_ram_wp(val & 0xf != 0xa),
+            0x2000...0x3fff =>
+                // Select a new ROM bank
+                self.banks.set_rom_bank(val as usize & 0x7f),
+            0x4000...0x5fff =>
+                // Select a new RAM bank
+                self.banks.set_ram_bank(val as usize),
+            0x6000...0x7fff => debug!("Unhandled RTC access"),
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        self.banks.read_ram(ram, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        self.banks.write_ram(ram, offset, val);
+    }
+}
@@ -0,0 +1,345 @@
+//! MBC6 memory bank controller and its flash memory
+
+use cartridge::mapper::{Mapper, Info, Banks};
+
+/// The MBC6 has two independent 8KB windows at [0x4000, 0x5fff]
+/// and [0x6000, 0x7fff] mapping either ROM or flash banks, and two
+/// 4KB RAM windows at [0xa000, 0xafff] and [0xb000, 0xbfff]. The
+/// flash contents are stored in the save file after the RAM.
+pub struct Mbc6 {
+    /// Only used for the bank mapped at [0x0000, 0x3fff]
+    banks:         Banks,
+    ram_enabled:   bool,
+    /// ROM or flash bank of each window
+    rom_banks:     [u8; 2],
+    /// Set when the window maps the flash instead of the ROM
+    flash_mapped:  [bool; 2],
+    ram_banks:     [u8; 2],
+    /// The flash can only be programmed when both are set
+    flash_enabled: bool,
+    flash_write:   bool,
+    flash:         Flash,
+}
+
+impl Mbc6 {
+    pub fn new(info: &Info) -> Mbc6 {
+        Mbc6 {
+            banks:         Banks::new(info),
+            ram_enabled:   false,
+            rom_banks:     [0; 2],
+            flash_mapped:  [false; 2],
+            ram_banks:     [0; 2],
+            flash_enabled: false,
+            flash_write:   false,
+            flash:         Flash::new(),
+        }
+    }
+
+    /// Return the window containing the ROM `offset` and the
+    /// address within the ROM or flash of the bank it maps
+    fn address(&self, offset: u16) -> (usize, usize) {
+        let window = (offset as usize >> 13) & 1;
+
+        (window, self.rom_banks[window] as usize * BANK_SIZE + (offset as usize & (BANK_SIZE - 1)))
+    }
+
+    /// Return the RAM address of `offset` in the RAM window it
+    /// belongs to
+    fn ram_address(&self, offset: u16) -> usize {
+        let bank = self.ram_banks[(offset as usize >> 12) & 1];
+
+        bank as usize * RAM_BANK_SIZE + (offset as usize & (RAM_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Mbc6 {
+    fn name(&self) -> &'static str {
+        "MBC6"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        if offset < 0x4000 {
+            return self.banks.read_rom(rom, offset);
+        }
+
+        let (window, addr) = self.address(offset);
+
+        if self.flash_mapped[window] {
+            self.flash.read(addr)
+        } else {
+            rom[addr % rom.len()]
+        }
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        if offset >= 0x4000 {
+            let (window, addr) = self.address(offset);
+
+            if self.flash_mapped[window] && self.flash_enabled && self.flash_write {
+                self.flash.write(addr, val);
+            }
+
+            return;
+        }
+
+        match offset {
+            0x0000...0x03ff => self.ram_enabled = val & 0xf == 0xa,
+            0x0400...0x07ff => self.ram_banks[0] = val & 7,
+            0x0800...0x0bff => self.ram_banks[1] = val & 7,
+            0x0c00...0x0fff => self.flash_enabled = val & 1 != 0,
+            0x1000          => self.flash_write = val & 1 != 0,
+            0x2000...0x27ff => self.rom_banks[0] = val & 0x7f,
+            0x2800...0x2fff => self.flash_mapped[0] = val == 0x08,
+            0x3000...0x37ff => self.rom_banks[1] = val & 0x7f,
+            0x3800...0x3fff => self.flash_mapped[1] = val == 0x08,
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        *ram.get(self.ram_address(offset)).unwrap_or(&0)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        if !self.ram_enabled {
+            debug!("Attempt to write to cartridge RAM while protected");
+            return;
+        }
+
+        if let Some(b) = ram.get_mut(self.ram_address(offset)) {
+            *b = val;
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.flash.data.clone()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if data.len() == FLASH_SIZE {
+            self.flash.data = data.to_vec();
+        }
+    }
+}
+
+/// State of the command decoder of the flash
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Reading the array, waiting for the first unlock cycle
+    Idle,
+    /// 0xaa written to 0x5555
+    Unlock1,
+    /// 0x55 written to 0x2aaa, the next write is the command
+    Unlock2,
+    /// The next write is programmed
+    Program,
+}
+
+/// Macronix MX29F008 flash, 1MB. It uses the usual JEDEC command
+/// sequences: two unlock cycles followed by the command. Erasing
+/// and programming complete immediately.
+struct Flash {
+    data:  Vec<u8>,
+    phase: Phase,
+    /// Set after the erase setup command (0x80), the next command
+    /// selects what to erase
+    erase: bool,
+    /// The chip returns its ID instead of the array
+    id:    bool,
+}
+
+impl Flash {
+    fn new() -> Flash {
+        Flash {
+            // Erased flash
+            data:  vec![0xff; FLASH_SIZE],
+            phase: Phase::Idle,
+            erase: false,
+            id:    false,
+        }
+    }
+
+    fn read(&self, addr: usize) -> u8 {
+        if self.id {
+            // Manufacturer then device code
+            match addr & 1 {
+                0 => 0xc2,
+                _ => 0x81,
+            }
+        } else {
+            self.data[addr % FLASH_SIZE]
+        }
+    }
+
+    fn write(&mut self, addr: usize, val: u8) {
+        let addr = addr % FLASH_SIZE;
+
+        if self.phase == Phase::Program {
+            // Programming can only clear bits
+            self.data[addr] &= val;
+            self.phase = Phase::Idle;
+            return;
+        }
+
+        if val == 0xf0 {
+            // Reset
+            self.phase = Phase::Idle;
+            self.erase = false;
+            self.id    = false;
+            return;
+        }
+
+        self.phase =
+            match (self.phase, addr & 0x7fff, val) {
+                (Phase::Idle,    0x5555, 0xaa) => Phase::Unlock1,
+                (Phase::Unlock1, 0x2aaa, 0x55) => Phase::Unlock2,
+                (Phase::Unlock2, _, _)         => return self.command(addr, val),
+                _ => {
+                    debug!("Unexpected MBC6 flash write {:05x} {:02x}", addr, val);
+                    self.erase = false;
+                    Phase::Idle
+                }
+            };
+    }
+
+    /// Execute the command `val` written to `addr` after the
+    /// unlock cycles
+    fn command(&mut self, addr: usize, val: u8) {
+        let erase = self.erase;
+
+        self.phase = Phase::Idle;
+        self.erase = false;
+
+        match (erase, addr & 0x7fff, val) {
+            (false, 0x5555, 0x80) => self.erase = true,
+            (false, 0x5555, 0x90) => self.id = true,
+            (false, 0x5555, 0xa0) => self.phase = Phase::Program,
+            (true,  0x5555, 0x10) =>
+                for b in self.data.iter_mut() {
+                    *b = 0xff;
+                },
+            (true,  _,      0x30) => {
+                let sector = addr & !(SECTOR_SIZE - 1);
+
+                for b in &mut self.data[sector..sector + SECTOR_SIZE] {
+                    *b = 0xff;
+                }
+            }
+            _ => debug!("Unhandled MBC6 flash command {:05x} {:02x}", addr, val),
+        }
+    }
+}
+
+/// The ROM and flash are mapped in 8KB banks
+const BANK_SIZE: usize = 8 * 1024;
+/// The RAM is mapped in 4KB banks
+const RAM_BANK_SIZE: usize = 4 * 1024;
+const FLASH_SIZE: usize = 1024 * 1024;
+/// The flash is erased in 128KB sectors
+const SECTOR_SIZE: usize = 128 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use cartridge::Cartridge;
+    use cartridge::tests::temp_rom_path;
+
+    /// Send a command to the MBC6 flash through the window at
+    /// [0x4000, 0x5fff], most commands are written to 0x5555 in bank
+    /// 2
+    fn mbc6_flash_command(cart: &mut Cartridge, bank: u8, offset: u16, command: u8) {
+        for &(bank, offset, val) in [(2, 0x5555, 0xaa),
+                                     (1, 0x4aaa, 0x55),
+                                     (bank, offset, command)].iter() {
+            cart.set_rom_byte(0x2000, bank);
+            cart.set_rom_byte(offset, val);
+        }
+    }
+
+    #[test]
+    fn mbc6() {
+        let path = temp_rom_path("mbc6");
+
+        let mut rom = vec![0; 64 * 0x4000];
+
+        rom[0x147] = 0x20;
+        rom[0x148] = 0x05;
+        rom[0x149] = 0x03;
+        // 8KB banks 4 and 5
+        rom[0x8200] = 4;
+        rom[0xa200] = 5;
+
+        let mut cart = Cartridge::from_rom(rom.clone(), path.clone()).unwrap();
+
+        cart.init_ram(None).unwrap();
+
+        assert!(cart.model_name() == "MBC6");
+
+        // Two independent 8KB windows
+        cart.set_rom_byte(0x2000, 0x05);
+        cart.set_rom_byte(0x3000, 0x04);
+        assert!(cart.rom_byte(0x4200) == 5 && cart.rom_byte(0x6200) == 4);
+
+        // Two independent 4KB RAM windows
+        cart.set_rom_byte(0x0000, 0x0a);
+        cart.set_rom_byte(0x0400, 0x01);
+        cart.set_rom_byte(0x0800, 0x02);
+        cart.set_ram_byte(0x0000, 0x11);
+        cart.set_ram_byte(0x1000, 0x22);
+        assert!(cart.ram[0x1000] == 0x11);
+        assert!(cart.ram[0x2000] == 0x22);
+
+        // Map the flash, it can't be written until enabled
+        cart.set_rom_byte(0x2800, 0x08);
+        assert!(cart.rom_byte(0x4000) == 0xff);
+
+        mbc6_flash_command(&mut cart, 2, 0x5555, 0xa0);
+        cart.set_rom_byte(0x2000, 0x03);
+        cart.set_rom_byte(0x4010, 0x5a);
+        assert!(cart.rom_byte(0x4010) == 0xff);
+
+        cart.set_rom_byte(0x0c00, 0x01);
+        cart.set_rom_byte(0x1000, 0x01);
+
+        mbc6_flash_command(&mut cart, 2, 0x5555, 0xa0);
+        cart.set_rom_byte(0x2000, 0x03);
+        cart.set_rom_byte(0x4010, 0x5a);
+        assert!(cart.rom_byte(0x4010) == 0x5a);
+
+        // Programming can only clear bits
+        mbc6_flash_command(&mut cart, 2, 0x5555, 0xa0);
+        cart.set_rom_byte(0x2000, 0x03);
+        cart.set_rom_byte(0x4010, 0xa5);
+        assert!(cart.rom_byte(0x4010) == 0x00);
+
+        // The flash is saved after the RAM
+        drop(cart);
+
+        assert!(::std::fs::metadata(path.with_extension("sav")).unwrap().len() ==
+                0x8000 + 0x100000);
+
+        let mut cart = Cartridge::from_rom(rom, path.clone()).unwrap();
+
+        cart.init_ram(None).unwrap();
+
+        cart.set_rom_byte(0x2000, 0x03);
+        cart.set_rom_byte(0x2800, 0x08);
+        assert!(cart.rom_byte(0x4010) == 0x00);
+        assert!(cart.ram[0x2000] == 0x22);
+
+        // Sector erase
+        cart.set_rom_byte(0x0c00, 0x01);
+        cart.set_rom_byte(0x1000, 0x01);
+
+        mbc6_flash_command(&mut cart, 2, 0x5555, 0x80);
+        mbc6_flash_command(&mut cart, 3, 0x4000, 0x30);
+        assert!(cart.rom_byte(0x4010) == 0xff);
+
+        drop(cart);
+
+        ::std::fs::remove_file(path.with_extension("sav")).unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+//! MBC7 memory bank controller with an accelerometer and an EEPROM
+
+use cartridge::mapper::{Mapper, Accelerometer, Info, Banks};
+use ui::Tilt;
+
+/// The MBC7 maps its registers at [0xa000, 0xafff] instead of
+/// RAM: a two-axis accelerometer and the pins of a 93LC56 serial
+/// EEPROM. The EEPROM contents are kept in the cartridge RAM (128
+/// little endian 16bit words) so they're saved like RAM.
+pub struct Mbc7 {
+    banks:   Banks,
+    /// The registers are only accessible when both enable
+    /// registers are set
+    enable1: bool,
+    enable2: bool,
+    /// Tilt given by the frontend
+    tilt:    Tilt,
+    /// Set when the latched values have been erased, the
+    /// accelerometer can then be latched
+    erased:  bool,
+    /// Latched accelerometer values
+    x:       u16,
+    y:       u16,
+    eeprom:  Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new(info: &Info) -> Mbc7 {
+        Mbc7 {
+            banks:   Banks::new(info),
+            enable1: false,
+            enable2: false,
+            tilt:    Tilt::level(),
+            erased:  false,
+            x:       ERASED,
+            y:       ERASED,
+            eeprom:  Eeprom::new(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enable1 && self.enable2
+    }
+
+    /// Latch the current tilt. The X value decreases when the
+    /// console is tilted to the right, Y increases when it's
+    /// tilted towards the player.
+    fn latch(&mut self) {
+        let clamp = |v: f32| v.max(-1.).min(1.);
+
+        self.x = (CENTER as f32 - RANGE * clamp(self.tilt.x)) as u16;
+        self.y = (CENTER as f32 + RANGE * clamp(self.tilt.y)) as u16;
+    }
+}
+
+impl Mapper for Mbc7 {
+    fn name(&self) -> &'static str {
+        "MBC7"
+    }
+
+    /// The MBC7 has a 256 byte EEPROM instead of RAM
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        Some((1, 256))
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff => self.enable1 = val == 0x0a,
+            0x2000...0x3fff => self.banks.set_rom_bank(val as usize & 0x7f),
+            0x4000...0x5fff => self.enable2 = val == 0x40,
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, _: &[u8], offset: u16) -> u8 {
+        if !self.enabled() || offset >= 0x1000 {
+            return 0xff;
+        }
+
+        match (offset >> 4) & 0xf {
+            0x2 => self.x as u8,
+            0x3 => (self.x >> 8) as u8,
+            0x4 => self.y as u8,
+            0x5 => (self.y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.pins(),
+            _   => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        if !self.enabled() || offset >= 0x1000 {
+            return;
+        }
+
+        match (offset >> 4) & 0xf {
+            // Writing 0x55 then 0xaa latches the accelerometer
+            0x0 => if val == 0x55 {
+                self.erased = true;
+                self.x      = ERASED;
+                self.y      = ERASED;
+            },
+            0x1 => if val == 0xaa && self.erased {
+                self.erased = false;
+                self.latch();
+            },
+            0x8 => self.eeprom.set_pins(val, ram),
+            _ => debug!("Unhandled MBC7 write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn accelerometer(&mut self) -> Option<&mut Accelerometer> {
+        Some(self)
+    }
+}
+
+impl Accelerometer for Mbc7 {
+    fn set_tilt(&mut self, tilt: Tilt) {
+        self.tilt = tilt;
+    }
+}
+
+/// 93LC56 EEPROM in 16bit mode, commands and data are sent one
+/// bit at a time on DI, sampled on the rising edges of the
+/// clock. Each command starts with a 1 followed by a 2bit opcode
+/// and an 8bit address (the top bit is ignored).
+struct Eeprom {
+    /// Chip select, the current command is aborted when it goes
+    /// low
+    cs:            bool,
+    clk:           bool,
+    di:            bool,
+    /// Data out, also used to signal the end of write commands
+    data_out:      bool,
+    /// Writes are ignored until an EWEN command is received
+    write_enabled: bool,
+    phase:         Phase,
+}
+
+#[derive(Clone,Copy)]
+enum Phase {
+    /// Waiting for the start bit
+    Idle,
+    /// Receiving a command, along with the number of bits
+    /// received so far
+    Command(u16, u8),
+    /// Shifting out the word at an address, along with the number
+    /// of bits left
+    Read(u8, u16, u8),
+    /// Receiving the data of a WRITE command or, if the address
+    /// is `None`, of a WRAL command
+    Write(Option<u8>, u16, u8),
+    /// Command complete, waiting for chip select to go low
+    Done,
+}
+
+impl Eeprom {
+    fn new() -> Eeprom {
+        Eeprom {
+            cs:            false,
+            clk:           false,
+            di:            false,
+            data_out:      true,
+            write_enabled: false,
+            phase:         Phase::Idle,
+        }
+    }
+
+    /// Value of the pins register
+    fn pins(&self) -> u8 {
+        (self.cs       as u8) << 7 |
+        (self.clk      as u8) << 6 |
+        (self.di       as u8) << 1 |
+        (self.data_out as u8)
+    }
+
+    fn set_pins(&mut self, val: u8, memory: &mut [u8]) {
+        let cs  = val & 0x80 != 0;
+        let clk = val & 0x40 != 0;
+        let di  = val & 0x02 != 0;
+
+        if !cs {
+            self.phase    = Phase::Idle;
+            self.data_out = true;
+        } else if clk && !self.clk {
+            self.clock(di, memory);
+        }
+
+        self.cs  = cs;
+        self.clk = clk;
+        self.di  = di;
+    }
+
+    /// Handle a rising edge of the clock
+    fn clock(&mut self, di: bool, memory: &mut [u8]) {
+        self.phase =
+            match self.phase {
+                Phase::Idle =>
+                    if di {
+                        Phase::Command(0, 0)
+                    } else {
+                        Phase::Idle
+                    },
+                Phase::Command(command, n) => {
+                    let command = command << 1 | di as u16;
+
+                    if n + 1 < COMMAND_BITS {
+                        Phase::Command(command, n + 1)
+                    } else {
+                        self.execute(command, memory)
+                    }
+                }
+                Phase::Read(address, word, n) => {
+                    self.data_out = word & 0x8000 != 0;
+
+                    if n > 1 {
+                        Phase::Read(address, word << 1, n - 1)
+                    } else {
+                        // Keep going with the next word
+                        let address = (address + 1) & ADDRESS_MASK;
+
+                        Phase::Read(address, read_word(memory, address), 16)
+                    }
+                }
+                Phase::Write(address, data, n) => {
+                    let data = data << 1 | di as u16;
+
+                    if n + 1 < 16 {
+                        Phase::Write(address, data, n + 1)
+                    } else {
+                        match address {
+                            Some(a) => self.write(memory, a, data),
+                            None    => self.write_all(memory, data),
+                        }
+
+                        Phase::Done
+                    }
+                }
+                Phase::Done => Phase::Done,
+            };
+    }
+
+    fn execute(&mut self, command: u16, memory: &mut [u8]) -> Phase {
+        let address = command as u8 & ADDRESS_MASK;
+
+        match command >> 8 {
+            // READ, a dummy 0 is output before the data
+            0b10 => {
+                self.data_out = false;
+
+                Phase::Read(address, read_word(memory, address), 16)
+            }
+            // WRITE
+            0b01 => Phase::Write(Some(address), 0, 0),
+            // ERASE
+            0b11 => {
+                self.write(memory, address, 0xffff);
+
+                Phase::Done
+            }
+            // The other commands use the top bits of the address
+            _ => match (command >> 6) & 3 {
+                // EWEN
+                0b11 => {
+                    self.write_enabled = true;
+
+                    Phase::Done
+                }
+                // EWDS
+                0b00 => {
+                    self.write_enabled = false;
+
+                    Phase::Done
+                }
+                // ERAL
+                0b10 => {
+                    self.write_all(memory, 0xffff);
+
+                    Phase::Done
+                }
+                // WRAL
+                _ => Phase::Write(None, 0, 0),
+            },
+        }
+    }
+
+    /// Write a word if writes are enabled. Writes complete
+    /// immediately so the chip always reports it's ready.
+    fn write(&mut self, memory: &mut [u8], address: u8, val: u16) {
+        if self.write_enabled {
+            let off = address as usize * 2;
+
+            memory[off]     = val as u8;
+            memory[off + 1] = (val >> 8) as u8;
+        }
+
+        self.data_out = true;
+    }
+
+    fn write_all(&mut self, memory: &mut [u8], val: u16) {
+        for address in 0..ADDRESS_MASK + 1 {
+            self.write(memory, address, val);
+        }
+    }
+}
+
+fn read_word(memory: &[u8], address: u8) -> u16 {
+    let off = address as usize * 2;
+
+    memory[off] as u16 | (memory[off + 1] as u16) << 8
+}
+
+/// Accelerometer value when the console is flat
+const CENTER: u16 = 0x81d0;
+/// Difference between the flat and fully tilted values
+const RANGE: f32 = 0x70 as f32;
+/// Accelerometer value after the latch is erased
+const ERASED: u16 = 0x8000;
+
+/// Length of a command after the start bit
+const COMMAND_BITS: u8 = 10;
+/// The 93LC56 holds 128 words
+const ADDRESS_MASK: u8 = 0x7f;
+
+#[cfg(test)]
+mod tests {
+    use cartridge::Cartridge;
+    use cartridge::models::tests::{cartridge, banks};
+
+    /// Clock `n` bits of `value` (MSB first) into the MBC7 EEPROM
+    /// and return the bits read after each rising edge
+    fn eeprom(cart: &mut Cartridge, value: u32, n: usize) -> u32 {
+        (0..n).rev().fold(0, |out, i| {
+            let di = ((value >> i) as u8 & 1) << 1;
+
+            cart.set_ram_byte(0x80, 0x80 | di);
+            cart.set_ram_byte(0x80, 0xc0 | di);
+
+            out << 1 | (cart.ram_byte(0x80) & 1) as u32
+        })
+    }
+
+    /// Toggle the EEPROM chip select to start a new command
+    fn eeprom_select(cart: &mut Cartridge) {
+        cart.set_ram_byte(0x80, 0x00);
+        cart.set_ram_byte(0x80, 0x80);
+    }
+
+    #[test]
+    fn mbc7() {
+        let mut cart = cartridge(0x22, 4, 0, false);
+
+        assert!(cart.model_name() == "MBC7");
+
+        // Both enable registers must be set
+        cart.set_rom_byte(0x0000, 0x0a);
+        assert!(cart.ram_byte(0x60) == 0xff);
+        cart.set_rom_byte(0x4000, 0x40);
+        assert!(cart.ram_byte(0x60) == 0x00);
+
+        cart.accelerometer().unwrap().set_tilt(::ui::Tilt { x: 1., y: -0.5 });
+
+        // The accelerometer is only latched after an erase
+        cart.set_ram_byte(0x10, 0xaa);
+        assert!(cart.ram_byte(0x20) == 0x00 && cart.ram_byte(0x30) == 0x80);
+
+        cart.set_ram_byte(0x00, 0x55);
+
+        cart.set_ram_byte(0x10, 0xaa);
+        assert!(cart.ram_byte(0x20) == 0x60 && cart.ram_byte(0x30) == 0x81);
+        assert!(cart.ram_byte(0x40) == 0x98 && cart.ram_byte(0x50) == 0x81);
+
+        // Writes are ignored until EWEN
+        eeprom_select(&mut cart);
+        eeprom(&mut cart, 0b1_01_00000011_0001001000110100, 27);
+        assert!(cart.ram[6] == 0);
+
+        eeprom_select(&mut cart);
+        eeprom(&mut cart, 0b1_00_11000000, 11);
+
+        eeprom_select(&mut cart);
+        eeprom(&mut cart, 0b1_01_00000011_0001001000110100, 27);
+        eeprom_select(&mut cart);
+        // Ready
+        assert!(cart.ram_byte(0x80) & 1 == 1);
+        assert!(cart.ram[6] == 0x34);
+        assert!(cart.ram[7] == 0x12);
+
+        // READ, the last bit of the command is the dummy 0
+        assert!(eeprom(&mut cart, 0b1_10_00000011, 11) & 1 == 0);
+        assert!(eeprom(&mut cart, 0, 16) == 0x1234);
+        // Sequential read of the next word
+        assert!(eeprom(&mut cart, 0, 16) == 0);
+
+        // ERAL
+        eeprom_select(&mut cart);
+        eeprom(&mut cart, 0b1_00_10000000, 11);
+        assert!(cart.ram[0] == 0xff);
+        assert!(cart.ram[0xff] == 0xff);
+
+        cart.set_rom_byte(0x2000, 0x03);
+        assert!(banks(&cart) == (0, 3));
+    }
+}
@@ -0,0 +1,238 @@
+//! MMM01 multicart controller
+
+use cartridge::mapper::{Mapper, Info, Banks};
+use cartridge::ROM_BANK_SIZE;
+
+/// The MMM01 starts with the last 32KB of the ROM mapped, where
+/// the menu is stored. The menu then configures the registers to
+/// select a game and locks them: from then on the game sees an
+/// MBC1-like controller restricted to its own banks.
+pub struct Mmm01 {
+    banks:    Banks,
+    /// Set once the menu has selected a game, most of the
+    /// register bits become read-only
+    locked:   bool,
+    /// ROM bank number, 9 bits
+    rom_bank: u16,
+    /// Bits of `rom_bank` the game can still change once locked,
+    /// the others select the game
+    rom_mask: u16,
+    /// RAM bank number, the upper 2 bits select the game
+    ram_bank: u8,
+    /// MBC1 banking mode: when set the lower 2 bits of `ram_bank`
+    /// are used
+    mode:     bool,
+}
+
+impl Mmm01 {
+    pub fn new(info: &Info) -> Mmm01 {
+        let mut mmm01 = Mmm01 {
+            banks:    Banks::new(info),
+            locked:   false,
+            rom_bank: 0,
+            rom_mask: 0x1f,
+            ram_bank: 0,
+            mode:     false,
+        };
+
+        mmm01.remap();
+
+        mmm01
+    }
+
+    /// Update the ROM and RAM mappings from the registers
+    fn remap(&mut self) {
+        let banks = self.banks.rom_banks();
+
+        let (low, high) =
+            if self.locked {
+                // The first bank of the game is the one with all
+                // the bits the game controls cleared
+                let base = self.rom_bank & !self.rom_mask;
+
+                // Like on the MBC1 bank 0 is replaced by 1
+                let bank =
+                    if self.rom_bank & self.rom_mask == 0 {
+                        self.rom_bank | 1
+                    } else {
+                        self.rom_bank
+                    };
+
+                (base as usize, bank as usize)
+            } else {
+                // The menu is always in the last 32KB
+                (banks - 2, banks - 1)
+            };
+
+        self.banks.map_rom(low, high);
+
+        let ram_banks = self.banks.ram_banks().map(|(n, _)| n).unwrap_or(0);
+
+        let ram_bank =
+            if self.mode {
+                self.ram_bank
+            } else {
+                self.ram_bank & !3
+            };
+
+        if ram_banks > 0 {
+            self.banks.set_ram_bank(ram_bank as usize % ram_banks);
+        }
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn name(&self) -> &'static str {
+        "MMM01"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        // Bits of the registers which are only writable until the
+        // game is selected
+        let menu = !self.locked;
+
+        match offset {
+            0x0000...0x1fff => {
+                // Writing a low nibble 0xa to anywhere in that
+                // address range removes RAM write protect, All
+                // other values enable it.
+                self.banks.set_ram_wp(val & 0xf != 0xa);
+
+                // Bit 6 locks the mapping
+                if menu && val & 0x40 != 0 {
+                    self.locked = true;
+                }
+            }
+            0x2000...0x3fff => {
+                // Bits [4:0] of the ROM bank, [6:5] are bits
+                // [6:5] of the bank in the menu only
+                let writable =
+                    if menu {
+                        0x7f
+                    } else {
+                        0x1f & self.rom_mask
+                    };
+
+                self.rom_bank = (self.rom_bank & !writable) | (val as u16 & writable);
+            }
+            0x4000...0x5fff => {
+                self.ram_bank = (self.ram_bank & !3) | (val & 3);
+
+                if menu {
+                    // RAM bank bits [3:2] and ROM bank bits [8:7]
+                    self.ram_bank = (self.ram_bank & 3) | (val & 0xc);
+                    self.rom_bank = (self.rom_bank & 0x7f) | ((val as u16 & 0x30) << 3);
+                }
+            }
+            0x6000...0x7fff => {
+                self.mode = val & 1 != 0;
+
+                if menu {
+                    // Bits [5:2] select which of the ROM bank
+                    // bits [4:1] the game can't change anymore
+                    self.rom_mask = !((val as u16 & 0x3c) >> 1) & 0x1f;
+                }
+            }
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+
+        self.remap();
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        self.banks.read_ram(ram, offset)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        self.banks.write_ram(ram, offset, val);
+    }
+}
+
+/// Return the offset of the menu if `rom` looks like an MMM01
+/// multicart: the header of the last 32KB has an MMM01 type and
+/// a valid Nintendo logo.
+pub fn menu_offset(rom: &[u8]) -> Option<usize> {
+    use cartridge::header::LOGO;
+    use cartridge::offsets;
+
+    if rom.len() < 2 * ROM_BANK_SIZE {
+        return None;
+    }
+
+    let menu = rom.len() - 2 * ROM_BANK_SIZE;
+    let logo = menu + offsets::LOGO;
+
+    match rom[menu + offsets::TYPE] {
+        0x0b...0x0d if &rom[logo..logo + LOGO.len()] == &LOGO[..] => Some(menu),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cartridge::Cartridge;
+    use cartridge::models::tests::{cartridge, banks};
+    use std::path::PathBuf;
+
+    #[test]
+    fn mmm01() {
+        let mut rom = vec![0; 64 * 0x4000];
+
+        for b in 0..64 {
+            rom[b * 0x4000 + 0x200] = b as u8;
+        }
+
+        // The first game is an MBC1 game, the menu is at the end
+        rom[0x147] = 0x01;
+
+        let menu = rom.len() - 0x8000;
+
+        rom[menu + 0x104..menu + 0x104 + 48].copy_from_slice(&::cartridge::header::LOGO);
+        rom[menu + 0x147] = 0x0b;
+        rom[menu + 0x148] = 0x05;
+        rom[menu + 0x149] = 0x03;
+
+        let mut cart = Cartridge::from_rom(rom, PathBuf::from("test.gb")).unwrap();
+
+        cart.init_volatile_ram().unwrap();
+
+        assert!(cart.model_name() == "MMM01");
+        assert!(cart.header().ram_size == 0x03);
+        assert!(banks(&cart) == (62, 63));
+
+        // Select the 16 bank game starting at bank 0x20: bank bit 4
+        // is fixed, then lock
+        cart.set_rom_byte(0x2000, 0x20);
+        cart.set_rom_byte(0x6000, 0x20);
+        assert!(banks(&cart) == (62, 63));
+        cart.set_rom_byte(0x0000, 0x40);
+        assert!(banks(&cart) == (0x20, 0x21));
+
+        // The game can only change the lower 4 bits
+        cart.set_rom_byte(0x2000, 0x1f);
+        assert!(banks(&cart) == (0x20, 0x2f));
+        cart.set_rom_byte(0x4000, 0x30);
+        assert!(banks(&cart) == (0x20, 0x2f));
+        cart.set_rom_byte(0x2000, 0x10);
+        assert!(banks(&cart) == (0x20, 0x21));
+
+        // Locking is permanent
+        cart.set_rom_byte(0x0000, 0x0a);
+        cart.set_rom_byte(0x2000, 0x05);
+        assert!(banks(&cart) == (0x20, 0x25));
+
+        cart.set_ram_byte(0, 0x42);
+        assert!(cart.ram_byte(0) == 0x42);
+
+        // A regular MBC1 ROM isn't mistaken for a multicart
+        assert!(cartridge(0x01, 64, 0, false).model_name() == "MBC1");
+    }
+}
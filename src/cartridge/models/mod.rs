@@ -0,0 +1,84 @@
+//! Built-in cartridge mappers
+
+use super::mapper::{Mapper, Info};
+
+mod mbc0;
+mod mbc1;
+mod mbc2;
+mod mmm01;
+mod mbc3;
+mod mbc6;
+mod mbc7;
+mod pocket_camera;
+mod huc1;
+mod huc3;
+mod tama5;
+
+/// Return the offset of the header describing the cartridge in
+/// `rom`. It's at the beginning except for MMM01 multicarts where it's
+/// the header of the menu, in the last 32KB.
+pub fn header_offset(rom: &[u8]) -> usize {
+    mmm01::menu_offset(rom).unwrap_or(0)
+}
+
+/// Create the mapper of the cartridge described by `info`, `None` if
+/// its type isn't supported
+pub fn new(info: &Info) -> Option<Box<Mapper>> {
+    let mapper: Box<Mapper> =
+        match info.header.cartridge_type {
+            0           => Box::new(mbc0::Mbc0::new(info)),
+            0x01...0x03 => Box::new(mbc1::Mbc1::new(info)),
+            0x05...0x06 => Box::new(mbc2::Mbc2::new(info)),
+            0x0b...0x0d => Box::new(mmm01::Mmm01::new(info)),
+            0x0f...0x13 => Box::new(mbc3::Mbc3::new(info)),
+            0x20        => Box::new(mbc6::Mbc6::new(info)),
+            0x22        => Box::new(mbc7::Mbc7::new(info)),
+            0xfc        => Box::new(pocket_camera::PocketCamera::new(info)),
+            0xfd        => Box::new(tama5::Tama5::new(info)),
+            0xfe        => Box::new(huc3::Huc3::new(info)),
+            0xff        => Box::new(huc1::Huc1::new(info)),
+            _           => return None,
+        };
+
+    Some(mapper)
+}
+
+/// Helpers shared by the tests of the mappers
+#[cfg(test)]
+mod tests {
+    use cartridge::Cartridge;
+    use std::path::PathBuf;
+
+    /// Build a cartridge of `banks` ROM banks, each containing its
+    /// number at offset 0x200
+    pub fn cartridge(kind: u8, banks: usize, ram_size: u8, multicart: bool) -> Cartridge {
+        let mut rom = vec![0; banks * 0x4000];
+
+        for b in 0..banks {
+            rom[b * 0x4000 + 0x200] = b as u8;
+        }
+
+        rom[0x147] = kind;
+        rom[0x148] = match banks { 4 => 0x01, 64 => 0x05, _ => 0x06 };
+        rom[0x149] = ram_size;
+
+        if multicart {
+            for game in 0..4 {
+                let logo = game * 0x40000 + 0x104;
+
+                rom[logo..logo + 48].copy_from_slice(&::cartridge::header::LOGO);
+            }
+        }
+
+        let mut cart = Cartridge::from_rom(rom, PathBuf::from("test.gb")).unwrap();
+
+        cart.init_volatile_ram().unwrap();
+
+        cart
+    }
+
+    /// Return the banks mapped at 0x0000 and 0x4000
+    pub fn banks(cart: &Cartridge) -> (u8, u8) {
+        (cart.rom_byte(0x0200), cart.rom_byte(0x4200))
+    }
+}
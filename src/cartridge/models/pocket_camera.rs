@@ -0,0 +1,118 @@
+//! Pocket Camera memory bank controller and camera registers
+
+use cartridge::mapper::{Mapper, Info, Banks};
+use cartridge::camera::Camera;
+
+/// The Pocket Camera maps either one of its 16 RAM banks or the
+/// camera registers at [0xa000, 0xbfff]
+pub struct PocketCamera {
+    /// The RAM write protection doesn't apply to the camera
+    /// registers
+    banks:         Banks,
+    camera_mapped: bool,
+    camera:        Camera,
+}
+
+impl PocketCamera {
+    pub fn new(info: &Info) -> PocketCamera {
+        PocketCamera {
+            banks:         Banks::new(info),
+            camera_mapped: false,
+            camera:        Camera::new(),
+        }
+    }
+}
+
+impl Mapper for PocketCamera {
+    fn name(&self) -> &'static str {
+        "Pocket Camera"
+    }
+
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        self.banks.ram_banks()
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        match offset {
+            0x0000...0x1fff => self.banks.set_ram_wp(val & 0xf != 0xa),
+            0x2000...0x3fff => self.banks.set_rom_bank(val as usize & 0x3f),
+            0x4000...0x5fff => {
+                self.camera_mapped = val & 0x10 != 0;
+
+                if !self.camera_mapped {
+                    self.banks.set_ram_bank(val as usize & 0xf);
+                }
+            }
+            _ => debug!("Unhandled ROM write: {:04x} {:02x}", offset, val),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        if self.camera_mapped {
+            self.camera.read(offset as u8)
+        } else {
+            self.banks.read_ram(ram, offset)
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        if self.camera_mapped {
+            // The picture always goes to the first RAM bank
+            self.camera.write(offset as u8, val, ram);
+        } else {
+            self.banks.write_ram(ram, offset, val);
+        }
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cartridge::models::tests::{cartridge, banks};
+
+    #[test]
+    fn pocket_camera() {
+        let mut cart = cartridge(0xfc, 64, 0x04, false);
+
+        assert!(cart.model_name() == "Pocket Camera");
+
+        // The camera registers can be written with the RAM disabled
+        cart.set_rom_byte(0x4000, 0x10);
+
+        for reg in 0x06..0x36 {
+            cart.set_ram_byte(reg, 0xff);
+        }
+
+        cart.set_ram_byte(0x0000, 0x03);
+        assert!(cart.ram_byte(0x0000) == 0x02);
+        // The other registers read as 0 and are mirrored
+        assert!(cart.ram_byte(0x0006) == 0x00);
+        assert!(cart.ram_byte(0x0080) == 0x02);
+
+        // Everything is below the thresholds: black picture in bank 0
+        cart.set_rom_byte(0x4000, 0x00);
+        assert!(cart.ram_byte(0x0100) == 0xff && cart.ram_byte(0x0eff) == 0xff);
+        assert!(cart.ram_byte(0x0f00) == 0x00);
+
+        cart.set_ram_byte(0x0000, 0x12);
+        assert!(cart.ram_byte(0x0000) == 0x00);
+
+        cart.set_rom_byte(0x0000, 0x0a);
+        cart.set_rom_byte(0x4000, 0x0f);
+        cart.set_ram_byte(0x0000, 0x34);
+        assert!(cart.ram_byte(0x0000) == 0x34);
+
+        cart.set_rom_byte(0x4000, 0x00);
+        assert!(cart.ram_byte(0x0000) == 0x00);
+
+        cart.set_rom_byte(0x2000, 0x21);
+        assert!(banks(&cart) == (0, 0x21));
+    }
+}
@@ -0,0 +1,345 @@
+//! Bandai TAMA5 controller with a real time clock
+
+use cartridge::mapper::{Mapper, Info, Banks};
+use cartridge::rtc::Clock;
+
+/// The TAMA5 replaces the RAM at [0xa000, 0xbfff] with two
+/// registers: writing to 0xa001 selects one of its 4bit internal
+/// registers which is then accessed through 0xa000. The ROM bank,
+/// its 32 bytes of RAM and the RTC are all accessed through these
+/// registers.
+pub struct Tama5 {
+    banks:     Banks,
+    /// Internal register accessed through 0xa000
+    select:    u8,
+    registers: [u8; 16],
+    /// Result of the last read command
+    value:     u8,
+    clock:     Clock,
+}
+
+impl Tama5 {
+    pub fn new(info: &Info) -> Tama5 {
+        Tama5 {
+            banks:     Banks::new(info),
+            select:    0,
+            registers: [0; 16],
+            value:     0,
+            clock:     Clock::new(),
+        }
+    }
+
+    /// Return the value of the RTC register `reg`. The RTC has
+    /// BCD registers for the seconds, minutes, hours, weekday,
+    /// day, month and year (from 2000). The days of `Clock` are
+    /// counted from 2000-01-01.
+    fn rtc(&mut self, reg: u8) -> u8 {
+        self.clock.update();
+
+        if reg == 0x6 {
+            // 2000-01-01 was a Saturday
+            return ((self.clock.days as u32 + 6) % 7) as u8;
+        }
+
+        let (year, month, day) = date(self.clock.days);
+
+        let (hours, minutes) = (self.clock.minutes / 60, self.clock.minutes % 60);
+
+        let (v, tens) =
+            match reg {
+                // We don't keep track of the seconds
+                0x0 | 0x1 => (0,       false),
+                0x2       => (minutes, false),
+                0x3       => (minutes, true),
+                0x4       => (hours,   false),
+                0x5       => (hours,   true),
+                0x7       => (day,     false),
+                0x8       => (day,     true),
+                0x9       => (month,   false),
+                0xa       => (month,   true),
+                0xb       => (year,    false),
+                0xc       => (year,    true),
+                _         => return 0,
+            };
+
+        if tens {
+            (v / 10) as u8
+        } else {
+            (v % 10) as u8
+        }
+    }
+
+    fn set_rtc(&mut self, reg: u8, val: u8) {
+        self.clock.update();
+
+        let (mut year, mut month, mut day) = date(self.clock.days);
+
+        let (mut hours, mut minutes) = (self.clock.minutes / 60, self.clock.minutes % 60);
+
+        {
+            let (field, tens) =
+                match reg {
+                    0x2 => (&mut minutes, false),
+                    0x3 => (&mut minutes, true),
+                    0x4 => (&mut hours,   false),
+                    0x5 => (&mut hours,   true),
+                    0x7 => (&mut day,     false),
+                    0x8 => (&mut day,     true),
+                    0x9 => (&mut month,   false),
+                    0xa => (&mut month,   true),
+                    0xb => (&mut year,    false),
+                    0xc => (&mut year,    true),
+                    _   => {
+                        debug!("Unhandled TAMA5 RTC write {:x} {:x}", reg, val);
+                        return;
+                    }
+                };
+
+            let val = val as u16;
+
+            *field =
+                if tens {
+                    val * 10 + *field % 10
+                } else {
+                    *field / 10 * 10 + val
+                };
+        }
+
+        let month = month.max(1).min(12);
+        let year  = year.min(99);
+        let day   = day.max(1).min(month_days(year, month));
+
+        self.clock.minutes = hours.min(23) * 60 + minutes.min(59);
+        self.clock.days    = days(year, month, day);
+    }
+
+    /// Execute the command in register 6 on the address in
+    /// registers 6 and 7: bit 0 of register 6 is bit 4 of the
+    /// address, bits [3:1] are the command. Commands 0 and 1
+    /// write and read the RAM, 2 and 3 write and read the RTC.
+    fn command(&mut self, ram: &mut [u8]) {
+        let address = ((self.registers[0x6] & 1) << 4) | self.registers[0x7];
+        let data    = (self.registers[0x5] << 4) | self.registers[0x4];
+
+        match self.registers[0x6] >> 1 {
+            0 =>
+                if let Some(b) = ram.get_mut(address as usize) {
+                    *b = data;
+                },
+            1 => self.value = *ram.get(address as usize).unwrap_or(&0),
+            2 => self.set_rtc(address & 0xf, data & 0xf),
+            3 => self.value = self.rtc(address & 0xf),
+            c => debug!("Unhandled TAMA5 command {:x}", c),
+        }
+    }
+}
+
+fn is_leap(year: u16) -> bool {
+    // Years are between 2000 and 2099
+    year % 4 == 0
+}
+
+fn month_days(year: u16, month: u16) -> u16 {
+    match month {
+        2 => if is_leap(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Convert a number of days since 2000-01-01 to a date, the year
+/// is relative to 2000
+fn date(mut days: u16) -> (u16, u16, u16) {
+    let mut year = 0;
+
+    loop {
+        let len = if is_leap(year) { 366 } else { 365 };
+
+        if days < len {
+            break;
+        }
+
+        days -= len;
+        year += 1;
+    }
+
+    let mut month = 1;
+
+    while days >= month_days(year, month) {
+        days  -= month_days(year, month);
+        month += 1;
+    }
+
+    (year, month, days + 1)
+}
+
+/// Convert a date to a number of days since 2000-01-01
+fn days(year: u16, month: u16, day: u16) -> u16 {
+    let years: u16 = (0..year).map(|y| if is_leap(y) { 366 } else { 365 }).sum();
+    let months: u16 = (1..month).map(|m| month_days(year, m)).sum();
+
+    years + months + day - 1
+}
+
+impl Mapper for Tama5 {
+    fn name(&self) -> &'static str {
+        "TAMA5"
+    }
+
+    /// The TAMA5 has 32 bytes of RAM built in
+    fn ram_banks(&self) -> Option<(usize, usize)> {
+        Some((1, 32))
+    }
+
+    fn read_rom(&self, rom: &[u8], offset: u16) -> u8 {
+        self.banks.read_rom(rom, offset)
+    }
+
+    fn write_rom(&mut self, offset: u16, val: u8) {
+        debug!("Unhandled ROM write: {:04x} {:02x}", offset, val);
+    }
+
+    /// There's no RAM enable register, the TAMA5 registers are
+    /// always accessible
+    fn read_ram(&self, _: &[u8], offset: u16) -> u8 {
+        if offset & 1 != 0 {
+            return 0xff;
+        }
+
+        // Only the low nibble is driven
+        0xf0 |
+        match self.select {
+            // Always ready
+            0xa => 1,
+            0xc => self.value & 0xf,
+            0xd => self.value >> 4,
+            _   => 0xf,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, val: u8) {
+        if offset & 1 != 0 {
+            self.select = val & 0xf;
+            return;
+        }
+
+        let select = self.select as usize;
+
+        self.registers[select] = val & 0xf;
+
+        match select {
+            // ROM bank, low then high nibble
+            0x0 | 0x1 => {
+                let bank = (self.registers[0x1] << 4) | self.registers[0x0];
+
+                self.banks.set_rom_bank(bank as usize & 0x1f);
+            }
+            // Writing the low address nibble runs the command
+            0x7 => self.command(ram),
+            _ => (),
+        }
+    }
+
+    /// The RTC is stored like the HuC3's: the host timestamp
+    /// (64 bits), minutes and days (16 bits each), little endian
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SAVE_SIZE);
+
+        for i in 0..8 {
+            data.push((self.clock.timestamp >> (i * 8)) as u8);
+        }
+
+        for &v in [self.clock.minutes, self.clock.days].iter() {
+            data.push(v as u8);
+            data.push((v >> 8) as u8);
+        }
+
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if data.len() != SAVE_SIZE {
+            return;
+        }
+
+        let u16_at = |i: usize| data[i] as u16 | (data[i + 1] as u16) << 8;
+
+        self.clock.timestamp = (0..8).fold(0, |t, i| t | (data[i] as u64) << (i * 8));
+        self.clock.minutes   = u16_at(8);
+        self.clock.days      = u16_at(10);
+    }
+}
+
+/// Size of the RTC data in the save file
+const SAVE_SIZE: usize = 12;
+
+#[cfg(test)]
+mod tests {
+    use cartridge::Cartridge;
+    use cartridge::models::tests::{cartridge, banks};
+
+    /// Write `val` to the TAMA5 register `reg`
+    fn tama5_write(cart: &mut Cartridge, reg: u8, val: u8) {
+        cart.set_ram_byte(1, reg);
+        cart.set_ram_byte(0, val);
+    }
+
+    /// Read the TAMA5 register `reg`
+    fn tama5_read(cart: &mut Cartridge, reg: u8) -> u8 {
+        cart.set_ram_byte(1, reg);
+        cart.ram_byte(0)
+    }
+
+    /// Run the TAMA5 command `command` on `address`, return the value
+    /// read
+    fn tama5_command(cart: &mut Cartridge, command: u8, address: u8, val: u8) -> u8 {
+        tama5_write(cart, 0x4, val & 0xf);
+        tama5_write(cart, 0x5, val >> 4);
+        tama5_write(cart, 0x6, (command << 1) | (address >> 4));
+        tama5_write(cart, 0x7, address & 0xf);
+
+        (tama5_read(cart, 0xd) & 0xf) << 4 | (tama5_read(cart, 0xc) & 0xf)
+    }
+
+    #[test]
+    fn tama5() {
+        let mut cart = cartridge(0xfd, 64, 0, false);
+
+        assert!(cart.model_name() == "TAMA5");
+
+        assert!(tama5_read(&mut cart, 0xa) == 0xf1);
+
+        tama5_write(&mut cart, 0x0, 0x3);
+        tama5_write(&mut cart, 0x1, 0x1);
+        assert!(banks(&cart) == (0, 0x13));
+
+        // RAM write then read
+        tama5_command(&mut cart, 0, 0x13, 0x24);
+        assert!(cart.ram[0x13] == 0x24);
+        assert!(tama5_command(&mut cart, 1, 0x13, 0) == 0x24);
+
+        // Set the clock to 2024-02-29 12:00
+        for &(reg, val) in [(0xc, 2), (0xb, 4), (0xa, 0), (0x9, 2),
+                            (0x8, 2), (0x7, 9), (0x5, 1), (0x4, 2)].iter() {
+            tama5_command(&mut cart, 2, reg, val);
+        }
+
+        let rtc = |cart: &mut Cartridge, reg| tama5_command(cart, 3, reg, 0);
+
+        assert!(rtc(&mut cart, 0x5) == 1 && rtc(&mut cart, 0x4) == 2);
+        assert!(rtc(&mut cart, 0x8) == 2 && rtc(&mut cart, 0x7) == 9);
+        assert!(rtc(&mut cart, 0xa) == 0 && rtc(&mut cart, 0x9) == 2);
+        assert!(rtc(&mut cart, 0xc) == 2 && rtc(&mut cart, 0xb) == 4);
+        // Thursday
+        assert!(rtc(&mut cart, 0x6) == 4);
+
+        // The clock is saved
+        let data = cart.mapper.save_data();
+
+        let mut cart = cartridge(0xfd, 64, 0, false);
+
+        cart.mapper.load_save_data(&data);
+
+        assert!(tama5_command(&mut cart, 3, 0x8, 0) == 2);
+    }
+}
@@ -0,0 +1,395 @@
+//! Soft-patching of ROM images. Translations and hacks are usually
+//! distributed as IPS, UPS or BPS patches which are applied in memory
+//! so the original ROM file is never modified.
+
+use crc::crc32_update;
+
+/// Extensions of the patch files looked up next to the ROM, in order
+/// of preference
+pub static EXTENSIONS: [&'static str; 3] = ["bps", "ups", "ips"];
+
+/// Apply `patch` to `rom`, the format is detected from its header
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        ips(patch, rom)
+    } else if patch.starts_with(b"UPS1") {
+        ups(patch, rom)
+    } else if patch.starts_with(b"BPS1") {
+        bps(patch, rom)
+    } else {
+        Err("unknown patch format".to_string())
+    }
+}
+
+/// IPS patches are a list of (offset, data) records, with run-length
+/// encoding for repeated bytes. There's no checksum.
+fn ips(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = rom.to_vec();
+    let mut r = Reader::new(patch, 5);
+
+    loop {
+        let offset = try!(r.be(3));
+
+        if offset == IPS_EOF {
+            break;
+        }
+
+        let len = try!(r.be(2));
+
+        let data =
+            if len == 0 {
+                // Run-length encoded record
+                let len = try!(r.be(2));
+                let val = try!(r.byte());
+
+                vec![val; len]
+            } else {
+                try!(r.bytes(len)).to_vec()
+            };
+
+        if out.len() < offset + data.len() {
+            out.resize(offset + data.len(), 0);
+        }
+
+        out[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // Some patches truncate the ROM
+    if r.remaining() >= 3 {
+        let len = try!(r.be(3));
+
+        out.truncate(len);
+    }
+
+    Ok(out)
+}
+
+/// UPS patches XOR the ROM with runs of bytes separated by the
+/// distance to the next difference
+fn ups(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    let end = try!(check_footer(patch, rom));
+
+    let mut r = Reader::new(&patch[..end], 4);
+
+    let source_size = try!(r.number());
+    let target_size = try!(r.number());
+
+    if source_size != rom.len() {
+        return Err(format!("the patch expects a {} bytes ROM", source_size));
+    }
+
+    let mut out = rom.to_vec();
+
+    out.resize(target_size, 0);
+
+    let mut pos = 0;
+
+    while r.remaining() > 0 {
+        pos += try!(r.number());
+
+        loop {
+            let x = try!(r.byte());
+
+            if x == 0 {
+                pos += 1;
+                break;
+            }
+
+            if pos >= target_size {
+                return Err("patch writes past the end of the ROM".to_string());
+            }
+
+            out[pos] ^= x;
+            pos += 1;
+        }
+    }
+
+    check_target(patch, &out).map(|_| out)
+}
+
+/// BPS patches build the new ROM using copies from the original ROM,
+/// from the patch itself or from the data already generated
+fn bps(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    let end = try!(check_footer(patch, rom));
+
+    let mut r = Reader::new(&patch[..end], 4);
+
+    let source_size   = try!(r.number());
+    let target_size   = try!(r.number());
+    let metadata_size = try!(r.number());
+
+    if source_size != rom.len() {
+        return Err(format!("the patch expects a {} bytes ROM", source_size));
+    }
+
+    try!(r.bytes(metadata_size));
+
+    let mut out: Vec<u8> = Vec::with_capacity(target_size);
+
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+
+    let invalid = || "invalid patch action".to_string();
+
+    while r.remaining() > 0 {
+        let action = try!(r.number());
+        let len    = (action >> 2) + 1;
+
+        if out.len() + len > target_size {
+            return Err("patch writes past the end of the ROM".to_string());
+        }
+
+        match action & 3 {
+            // Source read: copy from the same offset in the ROM
+            0 => {
+                let start = out.len();
+
+                match rom.get(start..start + len) {
+                    Some(data) => out.extend_from_slice(data),
+                    None       => return Err(invalid()),
+                }
+            }
+            // Target read: copy from the patch
+            1 => out.extend_from_slice(try!(r.bytes(len))),
+            // Source copy
+            2 => {
+                source_offset = try!(relative(source_offset, try!(r.number())));
+
+                match rom.get(source_offset..source_offset + len) {
+                    Some(data) => out.extend_from_slice(data),
+                    None       => return Err(invalid()),
+                }
+
+                source_offset += len;
+            }
+            // Target copy, the source and destination can overlap so
+            // it's done one byte at a time
+            _ => {
+                target_offset = try!(relative(target_offset, try!(r.number())));
+
+                if target_offset >= out.len() {
+                    return Err(invalid());
+                }
+
+                for _ in 0..len {
+                    let b = out[target_offset];
+
+                    out.push(b);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size {
+        return Err("patch output is truncated".to_string());
+    }
+
+    check_target(patch, &out).map(|_| out)
+}
+
+/// Apply a signed BPS offset to `base`. The sign is in the LSB.
+fn relative(base: usize, offset: usize) -> Result<usize, String> {
+    let delta = offset >> 1;
+
+    if offset & 1 == 0 {
+        Ok(base + delta)
+    } else if delta <= base {
+        Ok(base - delta)
+    } else {
+        Err("invalid patch action".to_string())
+    }
+}
+
+/// Check the CRCs of the patch and of the source ROM stored in the
+/// last 12 bytes of UPS and BPS patches. Returns the length of the
+/// patch without its footer.
+fn check_footer(patch: &[u8], rom: &[u8]) -> Result<usize, String> {
+    if patch.len() < 4 + 12 {
+        return Err("truncated patch".to_string());
+    }
+
+    let end = patch.len() - 12;
+
+    if crc32_update(0, &patch[..patch.len() - 4]) != le32(&patch[end + 8..]) {
+        return Err("the patch is corrupted".to_string());
+    }
+
+    if crc32_update(0, rom) != le32(&patch[end..]) {
+        return Err("the patch doesn't match this ROM (bad CRC)".to_string());
+    }
+
+    Ok(end)
+}
+
+/// Check the CRC of the patched ROM
+fn check_target(patch: &[u8], out: &[u8]) -> Result<(), String> {
+    let end = patch.len() - 12;
+
+    if crc32_update(0, out) != le32(&patch[end + 4..]) {
+        return Err("bad CRC after patching".to_string());
+    }
+
+    Ok(())
+}
+
+fn le32(data: &[u8]) -> u32 {
+    data[0] as u32 |
+    (data[1] as u32) << 8 |
+    (data[2] as u32) << 16 |
+    (data[3] as u32) << 24
+}
+
+/// Cursor over the patch data
+struct Reader<'a> {
+    data: &'a [u8],
+    pos:  usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Create a reader starting after the `skip` bytes of the magic
+    /// number
+    fn new(data: &'a [u8], skip: usize) -> Reader<'a> {
+        Reader {
+            data: data,
+            pos:  skip,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.remaining() < len {
+            return Err("truncated patch".to_string());
+        }
+
+        let data = &self.data[self.pos..self.pos + len];
+
+        self.pos += len;
+
+        Ok(data)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    /// Big endian number of `len` bytes, used by IPS
+    fn be(&mut self, len: usize) -> Result<usize, String> {
+        let bytes = try!(self.bytes(len));
+
+        Ok(bytes.iter().fold(0, |n, &b| n << 8 | b as usize))
+    }
+
+    /// Variable length number used by UPS and BPS. Each byte holds 7
+    /// bits, the MSB marks the last byte.
+    fn number(&mut self) -> Result<usize, String> {
+        let mut n: usize     = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let b = try!(self.byte()) as usize;
+
+            n += (b & 0x7f) * shift;
+
+            if b & 0x80 != 0 {
+                return Ok(n);
+            }
+
+            shift <<= 7;
+            n += shift;
+
+            if shift > 1 << 28 {
+                return Err("invalid number in the patch".to_string());
+            }
+        }
+    }
+}
+
+/// IPS record offset marking the end of the patch ("EOF")
+const IPS_EOF: usize = 0x454f46;
+
+#[cfg(test)]
+mod tests {
+    use super::apply;
+    use crc::crc32_update;
+
+    fn push32(v: &mut Vec<u8>, n: u32) {
+        for i in 0..4 {
+            v.push((n >> (i * 8)) as u8);
+        }
+    }
+
+    /// Append the UPS/BPS footer to `patch`
+    fn footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        push32(&mut patch, crc32_update(0, source));
+        push32(&mut patch, crc32_update(0, target));
+
+        let crc = crc32_update(0, &patch);
+
+        push32(&mut patch, crc);
+
+        patch
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = b"PATCH".to_vec();
+
+        patch.extend_from_slice(&[0, 0, 2, 0, 3, b'a', b'b', b'c']);
+        // RLE record extending the ROM
+        patch.extend_from_slice(&[0, 0, 6, 0, 0, 0, 4, b'z']);
+        patch.extend_from_slice(b"EOF");
+
+        assert!(apply(&patch, &[0; 8]) == Ok(b"\0\0abc\0zzzz".to_vec()));
+
+        // Truncation
+        patch.extend_from_slice(&[0, 0, 5]);
+
+        assert!(apply(&patch, &[0; 8]) == Ok(b"\0\0abc".to_vec()));
+
+        assert!(apply(&patch[..10], &[0; 8]).is_err());
+    }
+
+    #[test]
+    fn ups() {
+        let mut patch = b"UPS1".to_vec();
+
+        // Sizes then two hunks: 'C' ^ 'X' at 2 and 'E' at 4
+        patch.extend_from_slice(&[0x84, 0x85, 0x82, b'C' ^ b'X', 0, 0x80, b'E', 0]);
+
+        let patch = footer(patch, b"ABCD", b"ABXDE");
+
+        assert!(apply(&patch, b"ABCD") == Ok(b"ABXDE".to_vec()));
+        // Wrong ROM
+        assert!(apply(&patch, b"ABCE").is_err());
+
+        let mut corrupted = patch.clone();
+        corrupted[6] ^= 1;
+
+        assert!(apply(&corrupted, b"ABCD").is_err());
+    }
+
+    #[test]
+    fn bps() {
+        let mut patch = b"BPS1".to_vec();
+
+        // Sizes and metadata length
+        patch.extend_from_slice(&[0x84, 0x8c, 0x80]);
+        // Source read, 4 bytes
+        patch.push(0x8c);
+        // Target copy, 4 bytes from offset 0
+        patch.extend_from_slice(&[0x8f, 0x80]);
+        // Target read, 2 bytes
+        patch.extend_from_slice(&[0x85, b'x', b'y']);
+        // Source copy, 2 bytes from offset 2
+        patch.extend_from_slice(&[0x86, 0x84]);
+
+        let patch = footer(patch, b"ABCD", b"ABCDABCDxyCD");
+
+        assert!(apply(&patch, b"ABCD") == Ok(b"ABCDABCDxyCD".to_vec()));
+        assert!(apply(&patch, b"ABCDE").is_err());
+    }
+}
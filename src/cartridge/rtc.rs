@@ -0,0 +1,81 @@
+//! Real time clock of the cartridges counting minutes and days. It
+//! follows the host clock, even while the emulator isn't running.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Clock {
+    /// Minutes since midnight
+    pub minutes:   u16,
+    pub days:      u16,
+    /// Host time matching `minutes` and `days`, in seconds since the
+    /// UNIX epoch
+    pub timestamp: u64,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            minutes:   0,
+            days:      0,
+            timestamp: now(),
+        }
+    }
+
+    /// Catch up with the host clock
+    pub fn update(&mut self) {
+        self.advance(now());
+    }
+
+    /// Advance the clock up to the host time `now`
+    fn advance(&mut self, now: u64) {
+        if now < self.timestamp {
+            // The host clock went backwards, there's not much we can
+            // do besides starting over from there
+            self.timestamp = now;
+            return;
+        }
+
+        let elapsed = (now - self.timestamp) / 60;
+
+        // Keep the remaining seconds for the next update
+        self.timestamp += elapsed * 60;
+
+        let minutes = self.minutes as u64 + elapsed;
+
+        self.minutes = (minutes % MINUTES_PER_DAY) as u16;
+        self.days    = self.days.wrapping_add((minutes / MINUTES_PER_DAY) as u16);
+    }
+}
+
+fn now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d)  => d.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+#[cfg(test)]
+mod tests {
+    use super::Clock;
+
+    #[test]
+    fn advance() {
+        let mut clock = Clock {
+            minutes:   1438,
+            days:      3,
+            timestamp: 1000,
+        };
+
+        clock.advance(1059);
+        assert!(clock.minutes == 1438 && clock.timestamp == 1000);
+
+        // The spare seconds are kept for the next update
+        clock.advance(1119);
+        assert!(clock.minutes == 1439 && clock.timestamp == 1060);
+
+        clock.advance(1060 + 60 * 60);
+        assert!(clock.minutes == 59 && clock.days == 4);
+    }
+}
@@ -0,0 +1,267 @@
+//! Commands running a game without any user interface, as fast as
+//! possible. Useful for automation and to check test ROMs.
+
+use std::fs::{File, create_dir_all};
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+use cli::{Error, HeadlessOptions, TestOptions};
+use cli::{load_cartridge, load_settings, select_palette, check_header};
+use cpu::Cpu;
+use gpu::{Gpu, SCREEN_WIDTH, SCREEN_HEIGHT, CYCLES_PER_FRAME};
+use io::Interconnect;
+use spu::{Spu, SampleBuffer};
+use ui::dummy::{DummyDisplay, DummyController};
+
+/// Run the game for a fixed number of frames then save the requested
+/// images
+pub fn headless(options: &HeadlessOptions) -> Result<(), Error> {
+    let mut cart = try!(load_cartridge(&options.rom));
+
+    let bootable = check_header(cart.header());
+
+    try!(cart.init_volatile_ram().map_err(|e| Error::Load(format!("{}", e))));
+
+    let title = cart.name();
+
+    let settings = try!(load_settings(&cart, None, &[]));
+
+    let mut palettes = settings.palettes;
+
+    let palette = options.palette.as_ref().or(settings.video.palette.as_ref());
+
+    try!(select_palette(&mut palettes, palette.map(|p| &p[..])));
+
+    let game = title.unwrap_or(String::new());
+
+    let mut display = DummyDisplay;
+    let controller  = DummyController::new();
+
+    let gpu = Gpu::new(&mut display);
+
+    let (spu, audio_channel) = Spu::new();
+
+    drain_audio(audio_channel);
+
+    let mut inter = Interconnect::new(cart, gpu, spu, controller.buttons());
+
+    try!(options.machine.apply(&mut inter));
+
+    let mut cpu = Cpu::new(inter);
+
+    if !bootable {
+        cpu.skip_bootrom(options.machine.model);
+    }
+
+    let duration = options.frames as u64 * CYCLES_PER_FRAME as u64;
+    let mut cycles = 0;
+
+    while cycles < duration {
+        cycles += cpu.run_next_instruction() as u64;
+    }
+
+    let gpu = cpu.interconnect().gpu();
+
+    if let Some(ref path) = options.screenshot {
+        let rgb = ::capture::frame_to_rgb(gpu.last_frame(), palettes.current(), 1);
+
+        try!(save_png(path, &rgb)
+             .map_err(|e| Error::Runtime(format!("Couldn't save {}: {}",
+                                                 path.display(), e))));
+
+        println!("Saved {}", path.display());
+    }
+
+    if let Some(ref dir) = options.dump_views {
+        let paths = try!(create_dir_all(dir)
+                         .and_then(|_| {
+                             ::ui::viewer::save_views(dir,
+                                                      &game,
+                                                      &gpu.video_memory(),
+                                                      palettes.current())
+                         })
+                         .map_err(|e| Error::Runtime(format!("Couldn't save the \
+                                                              views: {}", e))));
+
+        for p in paths {
+            println!("Saved {}", p.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a test ROM until it reports its result. Two conventions are
+/// supported: the Mooneye GB tests load a Fibonacci sequence (or
+/// 0x42 on failure) in the registers before executing `LD B, B`,
+/// Blargg's tests print their results on the serial link and store
+/// them in the cartridge RAM.
+pub fn test(options: &TestOptions) -> Result<(), Error> {
+    let mut cart = try!(load_cartridge(&options.rom));
+
+    let bootable = check_header(cart.header());
+
+    try!(cart.init_volatile_ram().map_err(|e| Error::Load(format!("{}", e))));
+
+    let mut display = DummyDisplay;
+    let controller  = DummyController::new();
+
+    let gpu = Gpu::new(&mut display);
+
+    let (spu, audio_channel) = Spu::new();
+
+    drain_audio(audio_channel);
+
+    let mut inter = Interconnect::new(cart, gpu, spu, controller.buttons());
+
+    try!(options.machine.apply(&mut inter));
+
+    inter.set_serial_capture(true);
+
+    let mut cpu = Cpu::new(inter);
+
+    if !bootable {
+        cpu.skip_bootrom(options.machine.model);
+    }
+
+    let timeout = options.timeout as u64 * ::SYSCLK_FREQ as u64;
+    let mut cycles = 0;
+    let mut next_check = 0;
+    let mut serial = String::new();
+
+    while cycles < timeout {
+        cycles += cpu.run_next_instruction() as u64;
+
+        if cpu.at_breakpoint() {
+            match cpu.general_registers() {
+                [3, 5, 8, 13, 21, 34] => {
+                    println!("Passed");
+                    return Ok(());
+                }
+                [0x42, 0x42, 0x42, 0x42, 0x42, 0x42] =>
+                    return Err(Error::TestFailed("failure reported in the \
+                                                  registers".to_string())),
+                // Not a result, the test may use the breakpoint for
+                // something else
+                _ => (),
+            }
+        }
+
+        // Checking the output after each instruction would slow
+        // things down for no good reason
+        if cycles < next_check {
+            continue;
+        }
+
+        next_check = cycles + CYCLES_PER_FRAME as u64;
+
+        let output = cpu.interconnect_mut().take_serial_output();
+
+        if !output.is_empty() {
+            let text: String = output.iter().map(|&b| b as char).collect();
+
+            print!("{}", text);
+
+            serial.push_str(&text);
+
+            match serial_result(&serial) {
+                Some(true)  => return Ok(()),
+                Some(false) => return Err(Error::TestFailed(last_line(&serial))),
+                None        => (),
+            }
+        }
+
+        if let Some(result) = memory_result(cpu.interconnect()) {
+            if result.is_ok() {
+                println!("Passed");
+            }
+
+            return result;
+        }
+    }
+
+    Err(Error::Timeout)
+}
+
+/// Consume the audio samples nobody is going to play
+pub fn drain_audio(channel: Receiver<SampleBuffer>) {
+    ::std::thread::spawn(move || {
+        while let Ok(_) = channel.recv() {
+        }
+    });
+}
+
+fn save_png(path: &Path, rgb: &[u8]) -> ::std::io::Result<()> {
+    let mut file = BufWriter::new(try!(File::create(path)));
+
+    ::capture::png::write_png(&mut file, SCREEN_WIDTH, SCREEN_HEIGHT, rgb)
+}
+
+/// Look for the final "Passed" or "Failed" line of Blargg's tests in
+/// the serial output
+fn serial_result(serial: &str) -> Option<bool> {
+    if !serial.ends_with('\n') {
+        return None;
+    }
+
+    let line = last_line(serial);
+
+    if line.starts_with("Passed") {
+        Some(true)
+    } else if line.starts_with("Failed") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn last_line(text: &str) -> String {
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .last()
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+/// Blargg's tests store their status at 0xa000 once the signature
+/// at 0xa001 is written: 0x80 while running, then 0 on success or an
+/// error code. A description follows at 0xa004.
+fn memory_result(inter: &Interconnect) -> Option<Result<(), Error>> {
+    let signature = [0xde, 0xb0, 0x61];
+
+    if (0..3).any(|i| inter.fetch_byte(0xa001 + i) != signature[i as usize]) {
+        return None;
+    }
+
+    match inter.fetch_byte(0xa000) {
+        0x80 => None,
+        0    => Some(Ok(())),
+        code => {
+            let text: String = (0xa004..0xc000)
+                .map(|a| inter.fetch_byte(a))
+                .take_while(|&b| b != 0)
+                .map(|b| b as char)
+                .collect();
+
+            Some(Err(Error::TestFailed(format!("result code {}: {}",
+                                               code, last_line(&text)))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serial_result;
+
+    #[test]
+    fn serial() {
+        assert!(serial_result("cpu_instrs\n\n01:ok  02:ok\n") == None);
+        assert!(serial_result("cpu_instrs\n\n01:ok  02:ok\n\nPassed all tests\n") ==
+                Some(true));
+        assert!(serial_result("02-interrupts\n\nEI\nFailed #2\n") == Some(false));
+        // Incomplete line
+        assert!(serial_result("02-interrupts\n\nPass") == None);
+    }
+}
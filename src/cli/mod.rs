@@ -0,0 +1,744 @@
+//! Command line interface: parsing of the subcommands and their
+//! options, and the process exit codes.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::slice::Iter;
+use std::str::FromStr;
+
+use cartridge::{self, Cartridge, CartridgeHeader};
+use config::settings::Settings;
+use io::Interconnect;
+use io::bootrom::{self, Model};
+use ui::scaling::Scaling;
+use ui::ghosting::Ghosting;
+use ui::palette::Palettes;
+
+pub mod headless;
+pub mod rom;
+
+/// The command completed successfully
+pub const EXIT_SUCCESS: i32 = 0;
+/// The emulator stopped on an error or a test ROM reported a failure
+pub const EXIT_FAILURE: i32 = 1;
+/// Invalid command line
+pub const EXIT_USAGE:   i32 = 2;
+/// The ROM, the bootrom or the configuration couldn't be loaded
+pub const EXIT_LOAD:    i32 = 3;
+/// A test ROM didn't report its result in time
+pub const EXIT_TIMEOUT: i32 = 4;
+
+/// Reasons for a command to end with a non-zero exit code
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid command line
+    Usage(String),
+    /// Couldn't load one of the files needed to start
+    Load(String),
+    /// Error while the emulator was running
+    Runtime(String),
+    /// A test ROM reported a failure
+    TestFailed(String),
+    /// A test ROM didn't complete in time
+    Timeout,
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Usage(_)      => EXIT_USAGE,
+            Error::Load(_)       => EXIT_LOAD,
+            Error::Runtime(_)    => EXIT_FAILURE,
+            Error::TestFailed(_) => EXIT_FAILURE,
+            Error::Timeout       => EXIT_TIMEOUT,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usage(ref e)      => write!(f, "{}", e),
+            Error::Load(ref e)       => write!(f, "{}", e),
+            Error::Runtime(ref e)    => write!(f, "{}", e),
+            Error::TestFailed(ref e) => write!(f, "Test failed: {}", e),
+            Error::Timeout           => write!(f, "Test timed out"),
+        }
+    }
+}
+
+pub enum Command {
+    /// Play a game
+    Run(RunOptions),
+    /// Print the cartridge header
+    Info(RomOptions),
+    /// Run a game without any display, sound nor input
+    Headless(HeadlessOptions),
+    /// Disassemble part of a ROM
+    Disasm(DisasmOptions),
+    /// Run a test ROM and check its result
+    Test(TestOptions),
+    /// Print the usage
+    Help,
+}
+
+/// ROM to load, shared by all the commands
+pub struct RomOptions {
+    /// ROM file or archive, `<archive>:<entry>` selects a ROM in a
+    /// zip archive
+    pub path:  PathBuf,
+    /// Patch replacing the one found next to the ROM
+    pub patch: Option<PathBuf>,
+}
+
+/// Emulated hardware, shared by all the commands running a game
+#[derive(Clone,Debug)]
+pub struct MachineOptions {
+    pub model:             Model,
+    /// Bootrom dump replacing the built-in bootrom of `model`
+    pub bootrom:           Option<PathBuf>,
+    /// See `Interconnect::set_video_memory_locking`
+    pub lock_video_memory: bool,
+    /// Image file or directory of images seen by the Pocket Camera
+    pub camera:            Option<PathBuf>,
+}
+
+pub struct RunOptions {
+    pub rom:        RomOptions,
+    pub machine:    MachineOptions,
+    /// Palette name, overrides the default palette for the game
+    pub palette:    Option<String>,
+    /// Index of the upscaling filter in `ui::filter`
+    pub filter:     Option<usize>,
+    pub ghosting:   Ghosting,
+    pub scale:      Option<u32>,
+    pub scaling:    Option<Scaling>,
+    pub fullscreen: bool,
+    /// Don't open the audio device
+    pub mute:       bool,
+    /// Emulation speed relative to the real hardware
+    pub speed:      f32,
+    /// Record the audio and video output to `<file>.y4m` and
+    /// `<file>.wav`
+    pub record:     Option<PathBuf>,
+    /// Configuration file replacing the default one
+    pub config:     Option<PathBuf>,
+    /// `key=value` settings overrides
+    pub overrides:  Vec<String>,
+}
+
+pub struct HeadlessOptions {
+    pub rom:        RomOptions,
+    pub machine:    MachineOptions,
+    /// Number of frames to emulate
+    pub frames:     u32,
+    pub palette:    Option<String>,
+    /// Save the last frame as a PNG image
+    pub screenshot: Option<PathBuf>,
+    /// Save the video memory views in this directory
+    pub dump_views: Option<PathBuf>,
+}
+
+pub struct TestOptions {
+    pub rom:     RomOptions,
+    pub machine: MachineOptions,
+    /// Emulated time after which the test is considered stuck, in
+    /// seconds
+    pub timeout: u32,
+}
+
+pub struct DisasmOptions {
+    pub rom:   RomOptions,
+    /// Offset of the first instruction in the ROM image
+    pub start: usize,
+    /// Number of instructions to disassemble
+    pub count: usize,
+}
+
+/// Parse the command line arguments `args` (without the program
+/// name). `run` is assumed if the first argument isn't a command.
+pub fn parse(args: &[String]) -> Result<Command, Error> {
+    let command =
+        match args.first() {
+            Some(c) => &c[..],
+            None    => return Err(Error::Usage("Missing ROM file".to_string())),
+        };
+
+    let rest = &args[1..];
+
+    match command {
+        "run"      => parse_run(rest),
+        "info"     => parse_info(rest),
+        "headless" => parse_headless(rest),
+        "disasm"   => parse_disasm(rest),
+        "test"     => parse_test(rest),
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        _          => parse_run(args),
+    }
+}
+
+/// Return the usage message for `program`
+pub fn usage(program: &str) -> String {
+    format!("Usage: {} [<command>] <rom-file> [<options>]
+
+<rom-file> can be a zip or gzip archive, use <archive>:<name> to pick a
+ROM in a zip file containing several games.
+
+Commands:
+  run        Play the game (default)
+  info       Print the cartridge header
+  headless   Run without display, sound nor input
+  disasm     Disassemble part of the ROM
+  test       Run a test ROM and report its result
+  help       Print this message
+
+Options for all commands:
+  --patch <file>            Apply an IPS, UPS or BPS patch, by default
+                            <rom>.bps, .ups or .ips if it exists
+
+Options for run, headless and test:
+  --model <dmg|sgb>         Console model, selects the bootrom
+  --bootrom <file>          Use a 256 byte bootrom dump instead
+  --no-vram-lock            Don't restrict VRAM and OAM accesses
+  --camera <file|dir>       Pocket Camera input: a PNG, PGM or PPM image
+                            or a directory of images, one per picture
+
+Options for run:
+  --palette <name>          Color palette
+  --scale <n>               Initial window size
+  --scaling <integer|fit>   Screen scaling mode
+  --fullscreen              Start in fullscreen mode
+  --filter <name>           Upscaling filter
+  --ghosting <curve>        LCD ghosting response curve
+  --mute                    Disable the sound
+  --speed <factor>          Emulation speed, 1 is the real hardware
+  --record <file>           Record to <file>.y4m and <file>.wav
+  --config <file>           Configuration file
+  --set <key>=<value>       Override a setting, can be repeated
+
+Options for headless:
+  --frames <n>              Number of frames to run (default 600)
+  --palette <name>          Color palette for the images
+  --screenshot <file>       Save the last frame as a PNG image
+  --dump-views <dir>        Save the video memory views
+
+Options for test:
+  --timeout <seconds>       Emulated time limit (default 120)
+
+Options for disasm:
+  --start <offset>          ROM offset or <bank>:<address> (default 0x100)
+  --count <n>               Number of instructions (default 32)
+
+Exit codes: 0 on success, 1 on error or test failure, 2 for an invalid
+command line, 3 if a file couldn't be loaded, 4 if a test timed out.",
+            program)
+}
+
+fn parse_run(args: &[String]) -> Result<Command, Error> {
+    let mut o = RunOptions {
+        rom:        RomOptions::new(),
+        machine:    MachineOptions::new(),
+        palette:    None,
+        filter:     None,
+        ghosting:   Ghosting::off(),
+        scale:      None,
+        scaling:    None,
+        fullscreen: false,
+        mute:       false,
+        speed:      1.,
+        record:     None,
+        config:     None,
+        overrides:  Vec::new(),
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if try!(o.rom.parse(arg, &mut args)) || try!(o.machine.parse(arg, &mut args)) {
+            continue;
+        }
+
+        match &arg[..] {
+            "--palette" => o.palette = Some(try!(value(arg, &mut args)).to_string()),
+            "--scale" => {
+                let scale: u32 = try!(number(arg, &mut args));
+
+                if scale < 1 || scale > 16 {
+                    return Err(invalid(arg, &scale.to_string()));
+                }
+
+                o.scale = Some(scale);
+            }
+            "--scaling" => {
+                let s = try!(value(arg, &mut args));
+
+                o.scaling = Some(try!(Scaling::from_name(s).ok_or(invalid(arg, s))));
+            }
+            "--fullscreen" => o.fullscreen = true,
+            "--filter" => {
+                let f = try!(value(arg, &mut args));
+
+                o.filter = Some(try!(::ui::filter::find(f).ok_or(invalid(arg, f))));
+            }
+            "--ghosting" => {
+                let c = try!(value(arg, &mut args));
+
+                o.ghosting = try!(Ghosting::parse(c)
+                                  .map_err(|e| Error::Usage(format!("{}: {}", arg, e))));
+            }
+            "--mute" => o.mute = true,
+            "--speed" => {
+                o.speed = try!(number(arg, &mut args));
+
+                if !(o.speed > 0.) {
+                    return Err(invalid(arg, &o.speed.to_string()));
+                }
+            }
+            "--record" => o.record = Some(PathBuf::from(try!(value(arg, &mut args)))),
+            "--config" => o.config = Some(PathBuf::from(try!(value(arg, &mut args)))),
+            "--set" => o.overrides.push(try!(value(arg, &mut args)).to_string()),
+            _ => return Err(unknown(arg)),
+        }
+    }
+
+    try!(o.rom.check());
+
+    Ok(Command::Run(o))
+}
+
+fn parse_info(args: &[String]) -> Result<Command, Error> {
+    let mut rom = RomOptions::new();
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !try!(rom.parse(arg, &mut args)) {
+            return Err(unknown(arg));
+        }
+    }
+
+    try!(rom.check());
+
+    Ok(Command::Info(rom))
+}
+
+fn parse_headless(args: &[String]) -> Result<Command, Error> {
+    let mut rom = RomOptions::new();
+    let mut machine = MachineOptions::new();
+    let mut frames = 600;
+    let mut palette = None;
+    let mut screenshot = None;
+    let mut dump_views = None;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if try!(rom.parse(arg, &mut args)) || try!(machine.parse(arg, &mut args)) {
+            continue;
+        }
+
+        match &arg[..] {
+            "--frames" => frames = try!(number(arg, &mut args)),
+            "--palette" => palette = Some(try!(value(arg, &mut args)).to_string()),
+            "--screenshot" =>
+                screenshot = Some(PathBuf::from(try!(value(arg, &mut args)))),
+            "--dump-views" =>
+                dump_views = Some(PathBuf::from(try!(value(arg, &mut args)))),
+            _ => return Err(unknown(arg)),
+        }
+    }
+
+    try!(rom.check());
+
+    Ok(Command::Headless(HeadlessOptions {
+        rom:        rom,
+        machine:    machine,
+        frames:     frames,
+        palette:    palette,
+        screenshot: screenshot,
+        dump_views: dump_views,
+    }))
+}
+
+fn parse_test(args: &[String]) -> Result<Command, Error> {
+    let mut rom = RomOptions::new();
+    let mut machine = MachineOptions::new();
+    let mut timeout = 120;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if try!(rom.parse(arg, &mut args)) || try!(machine.parse(arg, &mut args)) {
+            continue;
+        }
+
+        match &arg[..] {
+            "--timeout" => timeout = try!(number(arg, &mut args)),
+            _ => return Err(unknown(arg)),
+        }
+    }
+
+    try!(rom.check());
+
+    Ok(Command::Test(TestOptions {
+        rom:     rom,
+        machine: machine,
+        timeout: timeout,
+    }))
+}
+
+fn parse_disasm(args: &[String]) -> Result<Command, Error> {
+    let mut rom = RomOptions::new();
+    let mut start = 0x100;
+    let mut count = 32;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if try!(rom.parse(arg, &mut args)) {
+            continue;
+        }
+
+        match &arg[..] {
+            "--start" => {
+                let s = try!(value(arg, &mut args));
+
+                start = try!(parse_offset(s).ok_or(invalid(arg, s)));
+            }
+            "--count" => {
+                let c = try!(value(arg, &mut args));
+
+                count = try!(parse_number(c).ok_or(invalid(arg, c)));
+            }
+            _ => return Err(unknown(arg)),
+        }
+    }
+
+    try!(rom.check());
+
+    Ok(Command::Disasm(DisasmOptions {
+        rom:   rom,
+        start: start,
+        count: count,
+    }))
+}
+
+impl RomOptions {
+    pub fn new() -> RomOptions {
+        RomOptions {
+            path:  PathBuf::new(),
+            patch: None,
+        }
+    }
+
+    /// Parse `arg` if it's the ROM path or one of the ROM options,
+    /// taking its value from `args`. Returns `false` if `arg` is
+    /// unrelated.
+    fn parse(&mut self, arg: &str, args: &mut Iter<String>) -> Result<bool, Error> {
+        match arg {
+            "--patch" => self.patch = Some(PathBuf::from(try!(value(arg, args)))),
+            _ if arg.starts_with("-") => return Ok(false),
+            _ => {
+                if !self.path.as_os_str().is_empty() {
+                    return Err(Error::Usage(format!("Unexpected argument '{}'", arg)));
+                }
+
+                self.path = PathBuf::from(arg);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Make sure the ROM path was given
+    fn check(&self) -> Result<(), Error> {
+        if self.path.as_os_str().is_empty() {
+            Err(Error::Usage("Missing ROM file".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl MachineOptions {
+    pub fn new() -> MachineOptions {
+        MachineOptions {
+            model:             Model::default(),
+            bootrom:           None,
+            lock_video_memory: true,
+            camera:            None,
+        }
+    }
+
+    /// Parse `arg` if it's one of the machine options, taking its
+    /// value from `args`. Returns `false` if `arg` is unrelated.
+    fn parse(&mut self, arg: &str, args: &mut Iter<String>) -> Result<bool, Error> {
+        match arg {
+            "--model" => {
+                let m = try!(value(arg, args));
+
+                self.model = try!(Model::from_name(m).ok_or(invalid(arg, m)));
+            }
+            "--bootrom" => self.bootrom = Some(PathBuf::from(try!(value(arg, args)))),
+            "--no-vram-lock" => self.lock_video_memory = false,
+            "--camera" => self.camera = Some(PathBuf::from(try!(value(arg, args)))),
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Configure `inter` to match these options
+    pub fn apply(&self, inter: &mut Interconnect) -> Result<(), Error> {
+        let bootrom =
+            match self.bootrom {
+                Some(ref path) => try!(bootrom::load(path).map_err(|e| {
+                    Error::Load(format!("Couldn't load bootrom {}: {}",
+                                        path.display(), e))
+                })),
+                None => self.model.bootrom().to_vec(),
+            };
+
+        inter.set_bootrom(bootrom);
+        inter.set_video_memory_locking(self.lock_video_memory);
+
+        if let Some(ref path) = self.camera {
+            try!(inter.cartridge_mut()
+                 .set_camera_input(path)
+                 .map_err(|e| Error::Load(format!("{}", e))));
+        }
+
+        Ok(())
+    }
+}
+
+/// Load and patch the cartridge ROM, the RAM isn't initialized
+pub fn load_cartridge(rom: &RomOptions) -> Result<Cartridge, Error> {
+    let (archive, entry) = split_entry(&rom.path);
+
+    Cartridge::from_path(&archive,
+                         entry.as_ref().map(|e| &e[..]),
+                         rom.patch.as_ref().map(|p| p.as_path()))
+        .map_err(|e| Error::Load(format!("Couldn't load ROM {}: {}",
+                                         rom.path.display(), e)))
+}
+
+/// Load the ROM image described by `rom` without creating the
+/// cartridge. Returns the ROM along with the path used to name the
+/// save file.
+pub fn load_rom(rom: &RomOptions) -> Result<(Vec<u8>, PathBuf), Error> {
+    let (archive, entry) = split_entry(&rom.path);
+
+    cartridge::load_rom(&archive,
+                        entry.as_ref().map(|e| &e[..]),
+                        rom.patch.as_ref().map(|p| p.as_path()))
+        .map_err(|e| Error::Load(format!("Couldn't load ROM {}: {}",
+                                         rom.path.display(), e)))
+}
+
+/// Split `<archive>:<entry>` paths. `path` is returned untouched if
+/// it names an existing file or if the archive doesn't exist.
+fn split_entry(path: &Path) -> (PathBuf, Option<String>) {
+    let unchanged = (PathBuf::from(path), None);
+
+    if path.is_file() {
+        return unchanged;
+    }
+
+    let s =
+        match path.to_str() {
+            Some(s) => s,
+            None    => return unchanged,
+        };
+
+    match s.rfind(':') {
+        Some(i) if Path::new(&s[..i]).is_file() =>
+            (PathBuf::from(&s[..i]), Some(s[i + 1..].to_string())),
+        _ => unchanged,
+    }
+}
+
+/// Warn about the problems found in the cartridge header. Returns
+/// `false` if the bootrom would refuse to start the game, in which
+/// case it should be skipped.
+pub fn check_header(header: &CartridgeHeader) -> bool {
+    if !header.logo_ok {
+        println!("Warning: the Nintendo logo in the header is corrupted");
+    }
+
+    if header.header_checksum != header.header_computed {
+        println!("Warning: bad header checksum 0x{:02x}, expected 0x{:02x}",
+                 header.header_checksum, header.header_computed);
+    }
+
+    if !header.global_checksum_ok() {
+        println!("Warning: bad global checksum 0x{:04x}, expected 0x{:04x}, \
+                  the ROM may be corrupted",
+                 header.global_checksum, header.global_computed);
+    }
+
+    if !header.bootable() {
+        println!("The bootrom would lock up on this header, skipping it");
+    }
+
+    header.bootable()
+}
+
+/// Load the settings for the game in `cart` from the configuration
+/// file `config` (or the default one) and apply the `key=value`
+/// `overrides` on top of them
+pub fn load_settings(cart: &Cartridge,
+                     config: Option<&Path>,
+                     overrides: &[String]) -> Result<Settings, Error> {
+    let config_file = config.map(|c| c.to_path_buf())
+        .or(::config::config_dir().map(|d| d.join("config.toml")));
+
+    let title = cart.name();
+    let title = title.as_ref().map(|n| &n[..]);
+
+    let settings =
+        match config_file {
+            Some(f) => Settings::load(&f, title, cart.title_checksum(), overrides),
+            None    => Ok(Settings::new()),
+        };
+
+    settings.map_err(|e| Error::Load(format!("Couldn't load settings: {}", e)))
+}
+
+/// Select the palette called `name`, if any
+pub fn select_palette(palettes: &mut Palettes,
+                      name: Option<&str>) -> Result<(), Error> {
+    if let Some(name) = name {
+        if !palettes.select(name) {
+            return Err(Error::Usage(format!("Unknown palette '{}'", name)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Return the value of `option`
+fn value<'a>(option: &str, args: &mut Iter<'a, String>) -> Result<&'a str, Error> {
+    args.next()
+        .map(|v| &v[..])
+        .ok_or(Error::Usage(format!("Missing value for {}", option)))
+}
+
+/// Return the value of `option` parsed as a number
+fn number<T: FromStr>(option: &str, args: &mut Iter<String>) -> Result<T, Error> {
+    let v = try!(value(option, args));
+
+    v.parse().map_err(|_| invalid(option, v))
+}
+
+fn invalid(option: &str, value: &str) -> Error {
+    Error::Usage(format!("Invalid value for {}: '{}'", option, value))
+}
+
+fn unknown(option: &str) -> Error {
+    Error::Usage(format!("Unknown option '{}'", option))
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal number
+fn parse_number(s: &str) -> Option<usize> {
+    if s.starts_with("0x") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Parse a ROM offset, either a number or `<bank>:<address>` in
+/// hexadecimal the way debuggers display banked addresses
+fn parse_offset(s: &str) -> Option<usize> {
+    let i =
+        match s.find(':') {
+            Some(i) => i,
+            None    => return parse_number(s),
+        };
+
+    let bank = usize::from_str_radix(&s[..i], 16).ok();
+    let addr = usize::from_str_radix(&s[i + 1..], 16).ok();
+
+    match (bank, addr) {
+        (Some(0), Some(addr)) if addr < 0x4000 => Some(addr),
+        (Some(bank), Some(addr)) if bank > 0 && addr >= 0x4000 && addr < 0x8000 =>
+            Some(bank * 0x4000 + addr - 0x4000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_offset, Command, Error};
+    use io::bootrom::Model;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split(' ').map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn commands() {
+        match parse(&args("game.gb --scale 3 --mute --set video.scaling=fit")) {
+            Ok(Command::Run(o)) => {
+                assert!(o.rom.path.to_str() == Some("game.gb"));
+                assert!(o.scale == Some(3));
+                assert!(o.mute);
+                assert!(o.overrides == vec!["video.scaling=fit".to_string()]);
+            }
+            _ => panic!("run expected"),
+        }
+
+        match parse(&args("test --model sgb --timeout 10 cpu_instrs.gb")) {
+            Ok(Command::Test(o)) => {
+                assert!(o.machine.model == Model::Sgb);
+                assert!(o.timeout == 10);
+            }
+            _ => panic!("test expected"),
+        }
+
+        match parse(&args("headless camera.gb --camera pictures")) {
+            Ok(Command::Headless(o)) =>
+                assert!(o.machine.camera.as_ref().and_then(|p| p.to_str()) == Some("pictures")),
+            _ => panic!("headless expected"),
+        }
+
+        match parse(&args("info --patch fr.ips game.gb")) {
+            Ok(Command::Info(o)) => {
+                assert!(o.path.to_str() == Some("game.gb"));
+                assert!(o.patch.as_ref().and_then(|p| p.to_str()) == Some("fr.ips"));
+            }
+            _ => panic!("info expected"),
+        }
+
+        match parse(&args("disasm game.gb --start 1:4010 --count 0x10")) {
+            Ok(Command::Disasm(o)) => {
+                assert!(o.start == 0x4010);
+                assert!(o.count == 16);
+            }
+            _ => panic!("disasm expected"),
+        }
+
+        let usage = |s| match parse(&args(s)) {
+            Err(Error::Usage(_)) => true,
+            _                    => false,
+        };
+
+        assert!(usage("info"));
+        assert!(usage("info a.gb b.gb"));
+        assert!(usage("run game.gb --scale"));
+        assert!(usage("run game.gb --speed 0"));
+        assert!(usage("headless game.gb --frobnicate"));
+        assert!(usage("run game.gb --model cgb"));
+    }
+
+    #[test]
+    fn offsets() {
+        assert!(parse_offset("0x150") == Some(0x150));
+        assert!(parse_offset("336") == Some(0x150));
+        assert!(parse_offset("0:0150") == Some(0x150));
+        assert!(parse_offset("2:4000") == Some(0x8000));
+        assert!(parse_offset("0:4000") == None);
+        assert!(parse_offset("1:0100") == None);
+    }
+}
@@ -0,0 +1,113 @@
+//! Commands inspecting a ROM without running it
+
+use cli::{Error, DisasmOptions, RomOptions, load_cartridge, load_rom};
+use cartridge::{self, Cartridge};
+use cartridge::header::Licensee;
+
+/// Print the information found in the cartridge header. The header is
+/// printed even if the cartridge itself can't be loaded (unsupported
+/// mapper, bad ROM or RAM size...), the details about the memory
+/// controller and the banks are only given if it can.
+pub fn info(rom: &RomOptions) -> Result<(), Error> {
+    let (data, path) = try!(load_rom(rom));
+
+    let header = try!(cartridge::parse_header(&data).map_err(|e| {
+        Error::Load(format!("Couldn't load ROM {}: {}", rom.path.display(), e))
+    }));
+
+    let cart = Cartridge::from_rom(data, path);
+
+    let licensee =
+        match header.licensee {
+            Licensee::Old(c)     => format!("0x{:02x}", c),
+            Licensee::New(ref c) => format!("\"{}\"", c),
+        };
+
+    let check = |ok| if ok { "OK" } else { "BAD" };
+
+    println!("Title:           {}",
+             header.title.as_ref().map(|t| &t[..]).unwrap_or("<INVALID>"));
+    println!("Title checksum:  0x{:02x}", header.title_checksum);
+
+    match cart {
+        Ok(ref cart) => {
+            let (rambanks, banksize) = cart.parse_ram_banks().unwrap_or((0, 0));
+
+            println!("Type:            0x{:02x} ({})",
+                     header.cartridge_type, cart.model_name());
+            println!("ROM:             {} banks ({}KB, code 0x{:02x})",
+                     cart.rom_banks(), cart.rom_banks() as u32 * 16, header.rom_size);
+            println!("RAM:             {} banks of {}B (code 0x{:02x})",
+                     rambanks, banksize, header.ram_size);
+        }
+        Err(_) => {
+            println!("Type:            0x{:02x}", header.cartridge_type);
+            println!("ROM:             code 0x{:02x}", header.rom_size);
+            println!("RAM:             code 0x{:02x}", header.ram_size);
+        }
+    }
+
+    println!("CGB support:     {:?}", header.cgb);
+    println!("SGB support:     {}", header.sgb);
+    println!("Licensee:        {} ({})",
+             licensee, header.licensee_name().unwrap_or("unknown"));
+    println!("Destination:     {:?}", header.destination);
+    println!("Version:         {}", header.version);
+    println!("Nintendo logo:   {}", check(header.logo_ok));
+    println!("Header checksum: 0x{:02x} ({})",
+             header.header_checksum,
+             check(header.header_checksum == header.header_computed));
+    println!("Global checksum: 0x{:04x} ({})",
+             header.global_checksum, check(header.global_checksum_ok()));
+
+    if let Err(e) = cart {
+        println!("Warning: the cartridge can't be loaded: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Print the disassembly of `options.count` instructions starting at
+/// `options.start` in the ROM image
+pub fn disasm(options: &DisasmOptions) -> Result<(), Error> {
+    let cart = try!(load_cartridge(&options.rom));
+
+    let rom = cart.rom();
+
+    if options.start >= rom.len() {
+        return Err(Error::Usage(format!("Start offset 0x{:x} is past the end \
+                                         of the ROM", options.start)));
+    }
+
+    let mut offset = options.start;
+
+    for _ in 0..options.count {
+        if offset >= rom.len() {
+            break;
+        }
+
+        // Address in the CPU memory map, assuming the bank is mapped
+        let bank = offset / 0x4000;
+        let addr =
+            if bank == 0 {
+                offset
+            } else {
+                0x4000 + offset % 0x4000
+            } as u16;
+
+        let (desc, len) = ::cpu::disassemble(&rom[offset..], addr);
+
+        let end = ::std::cmp::min(offset + len as usize, rom.len());
+
+        let bytes = rom[offset..end].iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        println!("{:02x}:{:04x}  {:<8}  {}", bank, addr, bytes, desc);
+
+        offset += len as usize;
+    }
+
+    Ok(())
+}
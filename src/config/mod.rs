@@ -0,0 +1,47 @@
+//! Configuration files handling
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{Read, ErrorKind};
+
+pub mod toml;
+pub mod settings;
+
+/// Return the directory containing the configuration files:
+/// `$XDG_CONFIG_HOME/gb-rs`, or `$HOME/.config/gb-rs` if the variable
+/// is not set.
+pub fn config_dir() -> Option<PathBuf> {
+    let base =
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(ref d) if !d.is_empty() => PathBuf::from(d),
+            _ => match env::var_os("HOME") {
+                Some(h) => Path::new(&h).join(".config"),
+                None    => return None,
+            },
+        };
+
+    Some(base.join("gb-rs"))
+}
+
+/// Load and parse the TOML file at `path`. Returns `Ok(None)` if the
+/// file doesn't exist.
+pub fn load_toml(path: &Path) -> Result<Option<toml::Table>, String> {
+    let mut file =
+        match File::open(path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+    let mut contents = String::new();
+
+    if let Err(e) = file.read_to_string(&mut contents) {
+        return Err(format!("{}: {}", path.display(), e));
+    }
+
+    match toml::parse(&contents) {
+        Ok(t)  => Ok(Some(t)),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
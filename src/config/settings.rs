@@ -0,0 +1,305 @@
+//! Emulator settings loaded from `config.toml`. Every setting can be
+//! overridden for a given game in a `[games."<title>"]` table (or
+//! `[games."0x<checksum>"]` using the title checksum) and then from
+//! the command line.
+
+use std::path::{Path, PathBuf};
+
+use config::toml::{self, Table, Value};
+use ui::scaling::VideoOptions;
+use ui::bindings::Bindings;
+use ui::palette::Palettes;
+
+pub struct Settings {
+    /// Number of SysClk cycles emulated between two sleeps
+    pub granularity: u32,
+    pub video:       VideoOptions,
+    /// Built-in palettes and the ones defined in `[video.palettes]`
+    pub palettes:    Palettes,
+    pub audio:       AudioOptions,
+    pub bindings:    Bindings,
+    pub paths:       Paths,
+}
+
+/// `[audio]` section
+#[derive(Clone,Copy,Debug)]
+pub struct AudioOptions {
+    /// Output sample rate in Hz
+    pub sample_rate:     u32,
+    /// How often the resampling ratio is adjusted, in seconds
+    pub adjust_interval: u32,
+}
+
+/// `[paths]` section
+#[derive(Clone,Debug)]
+pub struct Paths {
+    /// Directory for the save files, next to the ROM if `None`
+    pub saves:       Option<PathBuf>,
+    /// Directory for the screenshots, GIFs and video memory dumps
+    pub screenshots: PathBuf,
+}
+
+impl Settings {
+    /// Default settings
+    pub fn new() -> Settings {
+        Settings {
+            granularity: 0x10000,
+            video:       VideoOptions::new(),
+            palettes:    Palettes::builtin(),
+            audio:       AudioOptions {
+                sample_rate:     44_100,
+                adjust_interval: 1,
+            },
+            bindings:    Bindings::new(),
+            paths:       Paths {
+                saves:       None,
+                screenshots: PathBuf::from("."),
+            },
+        }
+    }
+
+    /// Load the settings from `path` (if it exists) for the game
+    /// with the given `title` and title `checksum`. `overrides` come
+    /// from the command line, they're `key=value` strings where `key`
+    /// is the dotted path of the setting (e.g. `video.scale=3`).
+    pub fn load(path: &Path,
+                title: Option<&str>,
+                checksum: u8,
+                overrides: &[String]) -> Result<Settings, String> {
+        let mut table =
+            match try!(::config::load_toml(path)) {
+                Some(t) => t,
+                None    => Table::new(),
+            };
+
+        // Per-game settings are merged on top of the global ones
+        for game in game_tables(&table, title, checksum) {
+            merge(&mut table, &game);
+        }
+
+        for o in overrides {
+            merge(&mut table, &try!(parse_override(o)));
+        }
+
+        let mut settings = Settings::new();
+
+        try!(settings.load_table(&table)
+             .map_err(|e| format!("{}: {}", path.display(), e)));
+
+        Ok(settings)
+    }
+
+    fn load_table(&mut self, table: &Table) -> Result<(), String> {
+        if let Some(emulation) = try!(section(table, "emulation")) {
+            if let Some(g) = emulation.get("granularity") {
+                self.granularity =
+                    match g.as_integer() {
+                        Some(g) if g >= 0x100 && g <= 0x400000 => g as u32,
+                        _ => return Err("'granularity' must be an integer \
+                                         between 256 and 4194304".to_string()),
+                    };
+            }
+        }
+
+        if let Some(audio) = try!(section(table, "audio")) {
+            if let Some(r) = audio.get("sample-rate") {
+                self.audio.sample_rate =
+                    match r.as_integer() {
+                        Some(r) if r >= 8000 && r <= 192000 => r as u32,
+                        _ => return Err("'sample-rate' must be an integer \
+                                         between 8000 and 192000".to_string()),
+                    };
+            }
+
+            if let Some(i) = audio.get("adjust-interval") {
+                self.audio.adjust_interval =
+                    match i.as_integer() {
+                        Some(i) if i >= 1 => i as u32,
+                        _ => return Err("'adjust-interval' must be a positive \
+                                         integer".to_string()),
+                    };
+            }
+        }
+
+        if let Some(paths) = try!(section(table, "paths")) {
+            if let Some(p) = paths.get("saves") {
+                let p = try!(p.as_str().ok_or("'saves' must be a string"));
+
+                self.paths.saves = Some(expand_home(p));
+            }
+
+            if let Some(p) = paths.get("screenshots") {
+                let p = try!(p.as_str().ok_or("'screenshots' must be a string"));
+
+                self.paths.screenshots = expand_home(p);
+            }
+        }
+
+        try!(self.video.load_table(table));
+        try!(self.palettes.load_table(table));
+        try!(self.bindings.load_table(table));
+
+        Ok(())
+    }
+}
+
+/// Return the table `name` in `table`, if any
+fn section<'a>(table: &'a Table, name: &str) -> Result<Option<&'a Table>, String> {
+    match table.get(name) {
+        Some(s) => s.as_table()
+            .map(Some)
+            .ok_or(format!("'{}' must be a table", name)),
+        None => Ok(None),
+    }
+}
+
+/// Return the `[games]` tables matching the game with the given
+/// `title` and title `checksum`, in the order they must be merged:
+/// the title's table comes last so that it takes precedence over the
+/// checksum's.
+fn game_tables(table: &Table, title: Option<&str>, checksum: u8) -> Vec<Table> {
+    let games =
+        match table.get("games").and_then(|g| g.as_table()) {
+            Some(g) => g,
+            None    => return Vec::new(),
+        };
+
+    let by_checksum = games.iter()
+        .find(|&(game, _)| is_checksum(game, checksum))
+        .map(|(_, t)| t);
+
+    let by_title = title.and_then(|t| games.get(t));
+
+    by_checksum.into_iter()
+        .chain(by_title.into_iter())
+        .filter_map(|t| t.as_table())
+        .cloned()
+        .collect()
+}
+
+/// Return `true` if `key` is the title `checksum` in hexadecimal
+/// (`0x..`)
+fn is_checksum(key: &str, checksum: u8) -> bool {
+    key.starts_with("0x") && u8::from_str_radix(&key[2..], 16) == Ok(checksum)
+}
+
+/// Recursively merge `over` into `base`, the values in `over` take
+/// precedence
+fn merge(base: &mut Table, over: &Table) {
+    for (key, value) in over {
+        let merged =
+            match (base.get_mut(key), value) {
+                (Some(&mut Value::Table(ref mut b)), &Value::Table(ref o)) => {
+                    merge(b, o);
+                    true
+                }
+                _ => false,
+            };
+
+        if !merged {
+            base.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Parse a `key=value` command line override. The value can be any
+/// TOML value, strings don't need to be quoted.
+fn parse_override(o: &str) -> Result<Table, String> {
+    let (key, value) =
+        match o.find('=') {
+            Some(i) => (&o[..i], &o[i + 1..]),
+            None    => return Err(format!("Invalid setting '{}', \
+                                           expected key=value", o)),
+        };
+
+    match toml::parse(&format!("{} = {}", key, value)) {
+        Ok(t)  => Ok(t),
+        Err(_) => {
+            let quoted = value.replace("\\", "\\\\").replace("\"", "\\\"");
+
+            toml::parse(&format!("{} = \"{}\"", key, quoted))
+                .map_err(|e| format!("Invalid setting '{}': {}", o, e))
+        }
+    }
+}
+
+/// Replace a leading `~` with the home directory
+fn expand_home(path: &str) -> PathBuf {
+    if path.starts_with("~/") {
+        if let Some(home) = ::std::env::var_os("HOME") {
+            return Path::new(&home).join(&path[2..]);
+        }
+    }
+
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Settings, merge, parse_override};
+    use config::toml;
+    use ui::scaling::Scaling;
+
+    #[test]
+    fn overrides() {
+        let mut table = toml::parse("[video]\n\
+                                     scale = 3\n\
+                                     scaling = \"fit\"\n\
+                                     [audio]\n\
+                                     sample-rate = 48000\n\
+                                     [games.TETRIS.video]\n\
+                                     scale = 5\n\
+                                     [games.0x14.audio]\n\
+                                     sample-rate = 22050\n").unwrap();
+
+        let game = table["games"].as_table().unwrap()["TETRIS"]
+            .as_table().unwrap().clone();
+
+        merge(&mut table, &game);
+        merge(&mut table, &parse_override("paths.screenshots=/tmp/shots").unwrap());
+        merge(&mut table, &parse_override("emulation.granularity=0x1000").unwrap());
+
+        let mut settings = Settings::new();
+
+        settings.load_table(&table).unwrap();
+
+        assert!(settings.video.scale == 5);
+        // Not overridden by the game
+        assert!(settings.video.scaling == Scaling::Fit);
+        assert!(settings.audio.sample_rate == 48000);
+        assert!(settings.paths.screenshots.to_str() == Some("/tmp/shots"));
+        assert!(settings.granularity == 0x1000);
+
+        assert!(parse_override("video.scale").is_err());
+    }
+
+    #[test]
+    fn game_match() {
+        let table = toml::parse("[games.TETRIS.video]\n\
+                                 palette = \"dmg\"\n\
+                                 [games.0x14.video]\n\
+                                 palette = \"cgb-up-a\"\n\
+                                 scale = 4\n").unwrap();
+
+        let games = super::game_tables(&table, Some("TETRIS"), 0x14);
+
+        // The title is merged last so it takes precedence
+        assert!(games.len() == 2);
+
+        let mut merged = table.clone();
+
+        for game in &games {
+            merge(&mut merged, game);
+        }
+
+        let mut settings = Settings::new();
+
+        settings.load_table(&merged).unwrap();
+
+        assert!(settings.video.palette == Some("dmg".to_string()));
+        assert!(settings.video.scale == 4);
+
+        assert!(super::game_tables(&table, None, 0x14).len() == 1);
+        assert!(super::game_tables(&table, Some("ZELDA"), 0x15).is_empty());
+    }
+}
@@ -0,0 +1,527 @@
+//! Minimal TOML parser. It only handles the subset of the format used
+//! by our configuration files: tables, strings, integers, floats,
+//! booleans, arrays and inline tables. Arrays of tables and dates are
+//! not supported.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A TOML table, keys are kept sorted
+pub type Table = BTreeMap<String, Value>;
+
+/// Any value that can be found in a TOML document
+#[derive(Clone,Debug,PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _                    => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(i) => Some(i),
+            _                 => None,
+        }
+    }
+
+    /// Return the value as a float. Integers are converted
+    /// implicitely since `2` is a lot more natural than `2.0` in a
+    /// config file.
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Value::Float(f)   => Some(f),
+            Value::Integer(i) => Some(i as f64),
+            _                 => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(b) => Some(b),
+            _                 => None,
+        }
+    }
+
+    pub fn as_slice(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref a) => Some(a),
+            _                   => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match *self {
+            Value::Table(ref t) => Some(t),
+            _                   => None,
+        }
+    }
+}
+
+/// Error encountered while parsing a document
+#[derive(Debug)]
+pub struct ParseError {
+    /// Line where the error occured, starting at 1
+    pub line: usize,
+    /// Description of the error
+    pub desc: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "line {}: {}", self.line, self.desc)
+    }
+}
+
+/// Parse the TOML document `s` and return the top-level table
+pub fn parse(s: &str) -> Result<Table, ParseError> {
+    let mut parser = Parser {
+        input: s.chars().collect(),
+        pos:   0,
+        line:  1,
+    };
+
+    parser.document()
+}
+
+struct Parser {
+    input: Vec<char>,
+    pos:   usize,
+    line:  usize,
+}
+
+impl Parser {
+    fn error<T>(&self, desc: String) -> Result<T, ParseError> {
+        Err(ParseError { line: self.line, desc: desc })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+
+        if let Some(c) = c {
+            self.pos += 1;
+
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
+
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => self.error(format!("expected '{}', found '{}'",
+                                          expected, c)),
+            None    => self.error(format!("expected '{}', found EOF",
+                                          expected)),
+        }
+    }
+
+    /// Skip spaces and tabs
+    fn skip_blanks(&mut self) {
+        while let Some(c) = self.peek() {
+            if c != ' ' && c != '\t' {
+                break;
+            }
+
+            self.next();
+        }
+    }
+
+    /// Skip blanks, newlines and comments
+    fn skip_all(&mut self) {
+        loop {
+            self.skip_blanks();
+
+            match self.peek() {
+                Some('#')  => self.skip_comment(),
+                Some('\n') | Some('\r') => { self.next(); }
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+
+            self.next();
+        }
+    }
+
+    /// Make sure there's nothing but blanks and comments until the
+    /// end of the line
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_blanks();
+
+        match self.peek() {
+            Some('#') => self.skip_comment(),
+            Some('\n') | Some('\r') | None => (),
+            Some(c) => return self.error(format!("unexpected '{}'", c)),
+        }
+
+        Ok(())
+    }
+
+    fn document(&mut self) -> Result<Table, ParseError> {
+        let mut root = Table::new();
+        // Path of the table currently being filled
+        let mut current = Vec::new();
+
+        loop {
+            self.skip_all();
+
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    self.next();
+
+                    current = try!(self.key_path(']'));
+
+                    self.next();
+
+                    // Make sure the table exists
+                    try!(self.table_at(&mut root, &current));
+                }
+                Some(_) => {
+                    let mut path = try!(self.key_path('='));
+
+                    self.next();
+                    self.skip_blanks();
+
+                    let value = try!(self.value());
+
+                    let key = path.pop().unwrap();
+
+                    let mut full_path = current.clone();
+                    full_path.extend(path.into_iter());
+
+                    let table = try!(self.table_at(&mut root, &full_path));
+
+                    if table.contains_key(&key) {
+                        return self.error(format!("duplicate key '{}'", key));
+                    }
+
+                    table.insert(key, value);
+                }
+            }
+
+            try!(self.end_of_line());
+        }
+
+        Ok(root)
+    }
+
+    /// Return the table at `path` within `root`, creating it if
+    /// necessary
+    fn table_at<'a>(&self,
+                    root: &'a mut Table,
+                    path: &[String]) -> Result<&'a mut Table, ParseError> {
+        let mut table = root;
+
+        for key in path {
+            let entry = table.entry(key.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+
+            table = match *entry {
+                Value::Table(ref mut t) => t,
+                _ => return self.error(format!("'{}' is not a table", key)),
+            };
+        }
+
+        Ok(table)
+    }
+
+    /// Parse a dotted key path up to (but not including) `end`
+    fn key_path(&mut self, end: char) -> Result<Vec<String>, ParseError> {
+        let mut path = Vec::new();
+
+        loop {
+            self.skip_blanks();
+
+            path.push(try!(self.key()));
+
+            self.skip_blanks();
+
+            match self.peek() {
+                Some('.') => { self.next(); }
+                Some(c) if c == end => return Ok(path),
+                Some(c) => return self.error(format!("unexpected '{}' in key",
+                                                     c)),
+                None => return self.error("unexpected EOF in key".to_string()),
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some('"')  => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let mut key = String::new();
+
+                while let Some(c) = self.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+
+                    key.push(c);
+                    self.next();
+                }
+
+                if key.is_empty() {
+                    return self.error("empty key".to_string());
+                }
+
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"')  => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[')  => self.array(),
+            Some('{')  => self.inline_table(),
+            Some(_)    => self.scalar(),
+            None       => self.error("expected value, found EOF".to_string()),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        try!(self.expect('"'));
+
+        let mut s = String::new();
+
+        loop {
+            let c = match self.next() {
+                Some('"')  => return Ok(s),
+                Some('\n') | None =>
+                    return self.error("unterminated string".to_string()),
+                Some('\\') => match self.next() {
+                    Some('n')  => '\n',
+                    Some('t')  => '\t',
+                    Some('r')  => '\r',
+                    Some('"')  => '"',
+                    Some('\\') => '\\',
+                    Some(c)    =>
+                        return self.error(format!("invalid escape '\\{}'", c)),
+                    None       =>
+                        return self.error("unterminated string".to_string()),
+                },
+                Some(c) => c,
+            };
+
+            s.push(c);
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        try!(self.expect('\''));
+
+        let mut s = String::new();
+
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(s),
+                Some('\n') | None =>
+                    return self.error("unterminated string".to_string()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        try!(self.expect('['));
+
+        let mut array = Vec::new();
+
+        loop {
+            self.skip_all();
+
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(array));
+            }
+
+            array.push(try!(self.value()));
+
+            self.skip_all();
+
+            match self.next() {
+                Some(',') => (),
+                Some(']') => return Ok(Value::Array(array)),
+                Some(c)   =>
+                    return self.error(format!("unexpected '{}' in array", c)),
+                None      =>
+                    return self.error("unterminated array".to_string()),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, ParseError> {
+        try!(self.expect('{'));
+
+        let mut table = Table::new();
+
+        loop {
+            self.skip_blanks();
+
+            if self.peek() == Some('}') {
+                self.next();
+                return Ok(Value::Table(table));
+            }
+
+            let key = try!(self.key());
+
+            self.skip_blanks();
+            try!(self.expect('='));
+            self.skip_blanks();
+
+            let value = try!(self.value());
+
+            if table.insert(key.clone(), value).is_some() {
+                return self.error(format!("duplicate key '{}'", key));
+            }
+
+            self.skip_blanks();
+
+            match self.next() {
+                Some(',') => (),
+                Some('}') => return Ok(Value::Table(table)),
+                Some(c)   =>
+                    return self.error(format!("unexpected '{}' in table", c)),
+                None      =>
+                    return self.error("unterminated table".to_string()),
+            }
+        }
+    }
+
+    /// Parse a boolean or a number
+    fn scalar(&mut self) -> Result<Value, ParseError> {
+        let mut token = String::new();
+
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '.' ||
+                 c == '+' || c == '-') {
+                break;
+            }
+
+            token.push(c);
+            self.next();
+        }
+
+        if token == "true" {
+            return Ok(Value::Boolean(true));
+        }
+
+        if token == "false" {
+            return Ok(Value::Boolean(false));
+        }
+
+        // Underscores can be used as digit separators
+        let number: String = token.chars().filter(|&c| c != '_').collect();
+
+        let (negative, digits) =
+            if number.starts_with('-') {
+                (true, &number[1..])
+            } else if number.starts_with('+') {
+                (false, &number[1..])
+            } else {
+                (false, &number[..])
+            };
+
+        let value =
+            if digits.starts_with("0x") {
+                i64::from_str_radix(&digits[2..], 16).ok().map(Value::Integer)
+            } else if digits.contains('.') ||
+                      (digits.contains('e') || digits.contains('E')) {
+                number.parse::<f64>().ok().map(Value::Float)
+            } else {
+                number.parse::<i64>().ok().map(Value::Integer)
+            };
+
+        match value {
+            Some(Value::Integer(i)) if negative && digits.starts_with("0x") =>
+                Ok(Value::Integer(-i)),
+            Some(v) => Ok(v),
+            None    => self.error(format!("invalid value '{}'", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    #[test]
+    fn scalars() {
+        let t = parse("a = 1\n\
+                       b = -0x10 # comment\n\
+                       c = 2.5\n\
+                       d = true\n\
+                       e = \"str \\\"quoted\\\"\"\n\
+                       f = 'C:\\path'\n\
+                       g = 1_000\n").unwrap();
+
+        assert!(t["a"] == Value::Integer(1));
+        assert!(t["b"] == Value::Integer(-16));
+        assert!(t["c"] == Value::Float(2.5));
+        assert!(t["d"] == Value::Boolean(true));
+        assert!(t["e"].as_str() == Some("str \"quoted\""));
+        assert!(t["f"].as_str() == Some("C:\\path"));
+        assert!(t["g"] == Value::Integer(1000));
+    }
+
+    #[test]
+    fn tables() {
+        let t = parse("top = 1\n\
+                       [video]\n\
+                       scale = 3\n\
+                       [games.\"POKEMON RED\"]\n\
+                       palette = \"dmg\"\n\
+                       keys = { a = \"x\", b = \"z\" }\n\
+                       colors = [ \"#ffffff\",\n\
+                                  \"#000000\", ]\n").unwrap();
+
+        assert!(t["top"].as_integer() == Some(1));
+
+        let video = t["video"].as_table().unwrap();
+        assert!(video["scale"].as_integer() == Some(3));
+
+        let game = t["games"].as_table().unwrap()["POKEMON RED"]
+            .as_table().unwrap();
+
+        assert!(game["palette"].as_str() == Some("dmg"));
+        assert!(game["keys"].as_table().unwrap()["b"].as_str() == Some("z"));
+        assert!(game["colors"].as_slice().unwrap().len() == 2);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("a = 1\na = 2\n").unwrap_err().line == 2);
+        assert!(parse("a = \"unterminated\n").is_err());
+        assert!(parse("a = [1, 2\n").is_err());
+        assert!(parse("a = 1 b = 2\n").is_err());
+        assert!(parse("a = 1\n[a]\n").is_err());
+    }
+}
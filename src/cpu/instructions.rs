@@ -23,6 +23,73 @@ pub fn next_instruction(cpu: &mut Cpu) -> fn (&mut Cpu) {
     instruction
 }
 
+/// Disassemble the instruction at the beginning of `code`, `addr`
+/// being its address in the CPU memory map. Returns the description
+/// of the instruction with its operands and its length in
+/// bytes. Operands past the end of `code` are read as 0.
+pub fn disassemble(code: &[u8], addr: u16) -> (String, u16) {
+    let byte = |i: u16| code.get(i as usize).cloned().unwrap_or(0);
+
+    let op = byte(0);
+
+    let (desc, mut len) =
+        match op {
+            0xcb => (bitops::OPCODES[byte(1) as usize].1, 2),
+            // STOP is followed by an unused byte
+            0x10 => (OPCODES[op as usize].1, 2),
+            _    => (OPCODES[op as usize].1, 1),
+        };
+
+    let mut out = String::with_capacity(desc.len() + 4);
+    let mut token = String::new();
+
+    // Add a dummy separator to flush the last token
+    for c in desc.chars().chain(Some(' ').into_iter()) {
+        if c.is_alphanumeric() {
+            token.push(c);
+            continue;
+        }
+
+        match &token[..] {
+            "N" => {
+                out.push_str(&format!("0x{:02x}", byte(len)));
+                len += 1;
+            }
+            "NN" => {
+                let nn = byte(len) as u16 | ((byte(len + 1) as u16) << 8);
+
+                out.push_str(&format!("0x{:04x}", nn));
+                len += 2;
+            }
+            "SN" => {
+                let sn = byte(len) as i8;
+
+                len += 1;
+
+                if desc.starts_with("JR") {
+                    // Display the target address of relative jumps
+                    let target = addr.wrapping_add(len).wrapping_add(sn as u16);
+
+                    out.push_str(&format!("0x{:04x}", target));
+                } else if sn < 0 {
+                    out.push_str(&format!("-0x{:02x}", -(sn as i16)));
+                } else {
+                    out.push_str(&format!("+0x{:02x}", sn));
+                }
+            }
+            t => out.push_str(t),
+        }
+
+        token.clear();
+        out.push(c);
+    }
+
+    // Remove the dummy separator
+    out.pop();
+
+    (out, len)
+}
+
 /// Array containing tuples `(delay, instruction, desc)`.
 ///
 /// `delay` is an `u32` describing how many machine cycles an
@@ -4961,3 +5028,22 @@ mod bitops {
         cpu.store_byte(hl, r);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+
+    #[test]
+    fn disasm() {
+        assert!(disassemble(&[0x00], 0x100) == ("NOP".to_string(), 1));
+        assert!(disassemble(&[0xc3, 0x50, 0x01], 0x101) ==
+                ("JP 0x0150".to_string(), 3));
+        assert!(disassemble(&[0xe0, 0x40], 0) == ("LDH [0x40], A".to_string(), 2));
+        assert!(disassemble(&[0x20, 0xfe], 0x200) ==
+                ("JR NZ, 0x0200".to_string(), 2));
+        assert!(disassemble(&[0xe8, 0xfc], 0) == ("ADD SP, -0x04".to_string(), 2));
+        assert!(disassemble(&[0xcb, 0x7c], 0) == ("BIT H, 7".to_string(), 2));
+        // Truncated operand
+        assert!(disassemble(&[0x3e], 0) == ("LD A, 0x00".to_string(), 2));
+    }
+}
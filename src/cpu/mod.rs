@@ -2,9 +2,12 @@
 
 use std::fmt::{Debug, Formatter, Error};
 use io::{Interconnect, Interrupt};
+use io::bootrom::{Model, BOOT_WRITES};
 
 use cpu::instructions::next_instruction;
 
+pub use cpu::instructions::disassemble;
+
 mod instructions;
 
 /// CPU state.
@@ -495,6 +498,44 @@ impl<'a> Cpu<'a> {
     pub fn reset(&mut self) {
         self.set_pc(0);
     }
+
+    /// Jump directly to the game, leaving the registers the way the
+    /// bootrom of `model` does. Must be called before running the
+    /// first instruction.
+    pub fn skip_bootrom(&mut self, model: Model) {
+        for &(addr, val) in BOOT_WRITES.iter() {
+            self.inter.store_byte(addr, val);
+        }
+
+        let regs = model.boot_registers();
+
+        self.set_af(regs[0]);
+        self.set_bc(regs[1]);
+        self.set_de(regs[2]);
+        self.set_hl(regs[3]);
+        self.set_sp(0xfffe);
+        self.set_pc(0x100);
+    }
+
+    /// Return `true` if the next instruction is `LD B, B`. Test ROMs
+    /// use it as a software breakpoint to signal that they're done.
+    pub fn at_breakpoint(&self) -> bool {
+        !self.halted && self.inter.fetch_byte(self.pc()) == 0x40
+    }
+
+    /// Return the values of the `B`, `C`, `D`, `E`, `H` and `L`
+    /// registers, in that order
+    pub fn general_registers(&self) -> [u8; 6] {
+        [self.b(), self.c(), self.d(), self.e(), self.h(), self.l()]
+    }
+
+    pub fn interconnect(&self) -> &Interconnect<'a> {
+        &self.inter
+    }
+
+    pub fn interconnect_mut(&mut self) -> &mut Interconnect<'a> {
+        &mut self.inter
+    }
 }
 
 impl<'a> Debug for Cpu<'a> {
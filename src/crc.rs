@@ -0,0 +1,32 @@
+//! CRC-32 checksum shared by the PNG encoder and the zip, gzip, patch
+//! and image decoders
+
+/// Update the CRC-32 (as used by PNG, zip and gzip) `crc` with
+/// `data`. Start with a `crc` of 0.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+
+    for &b in data {
+        crc ^= b as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn crc32() {
+        assert!(super::crc32_update(0, b"") == 0);
+        assert!(super::crc32_update(0, b"123456789") == 0xcbf43926);
+        assert!(super::crc32_update(0, b"IEND") == 0xae426082);
+    }
+}
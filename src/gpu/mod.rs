@@ -3,12 +3,22 @@
 use ui::Display;
 use gpu::sprite::Sprite;
 
-mod sprite;
+pub use gpu::video_memory::VideoMemory;
+
+pub mod sprite;
+mod video_memory;
 
 /// GPU state.
 pub struct Gpu<'a> {
     /// Emulator Display
     display: &'a mut (Display + 'a),
+    /// Frame currently being rendered. It's handed to the `display`
+    /// once complete.
+    frame: Frame,
+    /// Copy of the last complete frame
+    last_frame: Frame,
+    /// SysClk ticks since power on, used to timestamp the frames
+    cycles: u64,
     /// Current line. [0,143] is active video, [144,153] is blanking.
     line: u8,
     /// Counter for the horizontal period
@@ -67,8 +77,31 @@ pub struct Gpu<'a> {
     wy: u8,
     /// Sprites displayed on each line. Contains an index into OAM or
     /// None. There can't be more than 10 sprites displayed on each
-    /// line.
-    line_cache: [[Option<u8>; 10]; 144],
+    /// line unless `sprite_limit` is disabled.
+    line_cache: [[Option<u8>; 40]; 144],
+    /// Debugging switch: draw the background
+    show_bg: bool,
+    /// Debugging switch: draw the window
+    show_window: bool,
+    /// Debugging switch: draw the sprites
+    show_sprites: bool,
+    /// Debugging switch: enforce the 10 sprites per line limit
+    sprite_limit: bool,
+}
+
+/// Rendering layers that can be hidden for debugging. This only
+/// affects what's drawn, the game still reads back the LCDC value it
+/// wrote.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Layer {
+    /// Background
+    Background,
+    /// Window
+    Window,
+    /// Sprites
+    Sprites,
+    /// Not a layer per se: the limit of 10 sprites per line
+    SpriteLimit,
 }
 
 /// Current GPU mode
@@ -124,6 +157,9 @@ impl<'a> Gpu<'a> {
               oam:                    [Sprite::new(); 40],
               vram:                   [0xca; 0x2000],
               display:                display,
+              frame:                  Frame::new(),
+              last_frame:             Frame::new(),
+              cycles:                 0,
               enabled:                false,
               window_tile_map:        TileMap::Low,
               window_enabled:         false,
@@ -146,13 +182,18 @@ impl<'a> Gpu<'a> {
               scx:                    0,
               wx:                     0,
               wy:                     0,
-              line_cache:             [[None; 10]; 144],
+              line_cache:             [[None; 40]; 144],
+              show_bg:                true,
+              show_window:            true,
+              show_sprites:           true,
+              sprite_limit:           true,
         }
     }
 
     /// Called at each tick of the system clock. Move the emulated
     /// state one step forward.
     pub fn step(&mut self) {
+        self.cycles += 1;
 
         if !self.enabled {
             return;
@@ -184,7 +225,16 @@ impl<'a> Gpu<'a> {
                             // We're entering vertical blanking, we're
                             // done drawing the current frame
                             self.it_vblank = true;
-                            self.display.flip();
+                            self.frame.time = self.cycles;
+                            self.display.flip(&self.frame);
+
+                            if self.display.wants_video_memory() {
+                                let mem = self.video_memory();
+
+                                self.display.video_memory(&mem);
+                            }
+
+                            self.last_frame.clone_from(&self.frame);
                             Mode::VBlank
                         } else {
                             Mode::Prelude
@@ -225,6 +275,52 @@ impl<'a> Gpu<'a> {
         self.mode
     }
 
+    /// Return the last frame completely rendered. It doesn't change
+    /// while the LCD is disabled.
+    pub fn last_frame(&self) -> &Frame {
+        &self.last_frame
+    }
+
+    /// Toggle the debugging switch for `layer`. Returns the new
+    /// state of the switch.
+    pub fn toggle_layer(&mut self, layer: Layer) -> bool {
+        let state = match layer {
+            Layer::Background  => &mut self.show_bg,
+            Layer::Window      => &mut self.show_window,
+            Layer::Sprites     => &mut self.show_sprites,
+            Layer::SpriteLimit => &mut self.sprite_limit,
+        };
+
+        *state = !*state;
+
+        let state = *state;
+
+        if layer == Layer::SpriteLimit {
+            self.rebuild_line_cache();
+        }
+
+        state
+    }
+
+    /// Return a copy of the video memory for the debugging views
+    pub fn video_memory(&self) -> VideoMemory {
+        VideoMemory::new(self)
+    }
+
+    /// Return `true` if the CPU is allowed to access VRAM. It's
+    /// locked while the GPU fetches the pixel data for the current
+    /// line.
+    pub fn vram_accessible(&self) -> bool {
+        !self.enabled || self.mode != Mode::Active
+    }
+
+    /// Return `true` if the CPU is allowed to access OAM. It's locked
+    /// during the sprite lookup and while the line is being drawn.
+    pub fn oam_accessible(&self) -> bool {
+        !self.enabled ||
+            (self.mode != Mode::Prelude && self.mode != Mode::Active)
+    }
+
     /// Handle reconfig through LCDC register
     pub fn set_lcdc(&mut self, lcdc: u8) {
 
@@ -662,7 +758,7 @@ impl<'a> Gpu<'a> {
     /// expensive.
     fn rebuild_line_cache(&mut self) {
         // Clear the cache
-        self.line_cache = [[None; 10]; 144];
+        self.line_cache = [[None; 40]; 144];
 
         // Rebuild it
         for i in 0..self.oam.len() {
@@ -685,7 +781,7 @@ impl<'a> Gpu<'a> {
 
             let y = y as usize;
 
-            let l = self.line_cache[y].len();
+            let l = if self.sprite_limit { 10 } else { 40 };
 
             if self.line_cache[y][l - 1].is_some() {
                 // We reached the sprite limit for that line, we can
@@ -735,25 +831,31 @@ impl<'a> Gpu<'a> {
     fn render_pixel(&mut self, x: u8, y: u8) {
         let bg_col =
             // Window is always on top of background
-            if self.window_enabled && self.in_window(x, y) {
+            if self.window_enabled && self.show_window && self.in_window(x, y) {
                 self.window_color(x, y)
-            } else if self.bg_enabled {
+            } else if self.bg_enabled && self.show_bg {
                 self.background_color(x, y)
             } else {
                 // No background or window
                 AlphaColor { color: Color::White, opaque: false }
             };
 
-        let col = if self.sprites_enabled {
+        let (col, source) = if self.sprites_enabled && self.show_sprites {
             self.render_sprite(x, y, bg_col)
         } else {
-            bg_col.color
+            (bg_col.color, Source::Bgp)
         };
 
-        self.display.set_pixel(x as u32, y as u32, col);
+        self.frame.set_pixel(x as u32, y as u32, col, source);
     }
 
-    fn render_sprite(&self, x: u8, y: u8, bg_col: AlphaColor) -> Color {
+    /// Return the color of the pixel at (`x`, `y`) once the sprites
+    /// are drawn over the background along with the palette used to
+    /// compute it.
+    fn render_sprite(&self,
+                     x: u8,
+                     y: u8,
+                     bg_col: AlphaColor) -> (Color, Source) {
 
         for &entry in self.line_cache[y as usize].iter() {
             match entry {
@@ -810,19 +912,18 @@ impl<'a> Gpu<'a> {
                         // Pixel is not transparent, compute the color
                         // and return that
 
-                        let palette = match sprite.palette() {
-                            sprite::Palette::Obp0 => self.obp0,
-                            sprite::Palette::Obp1 => self.obp1,
+                        let (palette, source) = match sprite.palette() {
+                            sprite::Palette::Obp0 => (self.obp0, Source::Obp0),
+                            sprite::Palette::Obp1 => (self.obp1, Source::Obp1),
                         };
 
-
-                        return palette.transform(pix);
+                        return (palette.transform(pix), source);
                     }
                 }
             }
         }
 
-        bg_col.color
+        (bg_col.color, Source::Bgp)
     }
 
 }
@@ -849,6 +950,79 @@ impl Color {
     }
 }
 
+/// Screen width in pixels
+pub const SCREEN_WIDTH:  u32 = 160;
+/// Screen height in pixels
+pub const SCREEN_HEIGHT: u32 = 144;
+/// Number of SysClk ticks per frame while the LCD is enabled
+pub const CYCLES_PER_FRAME: u32 =
+    timings::HTOTAL as u32 * timings::VTOTAL as u32;
+
+/// Palette register used to compute the color of a pixel. Frontends
+/// can use it to colorize the background and sprites differently.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub enum Source {
+    /// Background or window pixel
+    Bgp,
+    /// Sprite pixel using OBP0
+    Obp0,
+    /// Sprite pixel using OBP1
+    Obp1,
+}
+
+/// Full screen image as rendered by the GPU. Pixels are stored as
+/// shades of grey, it's up to the frontend to convert them into
+/// whatever it needs.
+#[derive(Clone)]
+pub struct Frame {
+    /// Pixels in row-major order, (0, 0) is top left.
+    pixels:  Vec<Color>,
+    /// Palette used by each pixel, in the same order as `pixels`
+    sources: Vec<Source>,
+    /// SysClk ticks since power on when the frame was completed
+    time:    u64,
+}
+
+impl Frame {
+    /// Create a new frame with all pixels set to white
+    pub fn new() -> Frame {
+        let size = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+
+        Frame {
+            pixels:  vec![Color::White; size],
+            sources: vec![Source::Bgp; size],
+            time:    0,
+        }
+    }
+
+    /// Return the emulated time at which the frame was completed, in
+    /// SysClk ticks since power on. Frames don't come at a regular
+    /// pace when the LCD is turned off.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Return the color of the pixel at (`x`, `y`). (0, 0) is top
+    /// left.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * SCREEN_WIDTH + x) as usize]
+    }
+
+    /// Return the palette used by the pixel at (`x`, `y`)
+    pub fn source(&self, x: u32, y: u32) -> Source {
+        self.sources[(y * SCREEN_WIDTH + x) as usize]
+    }
+
+    /// Set the color of the pixel at (`x`, `y`) along with the
+    /// palette it comes from
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color, source: Source) {
+        let i = (y * SCREEN_WIDTH + x) as usize;
+
+        self.pixels[i]  = color;
+        self.sources[i] = source;
+    }
+}
+
 /// Palette description
 #[derive(Clone,Copy)]
 struct Palette {
@@ -1038,4 +1212,67 @@ mod tests {
         assert!(gpu.mode() == super::Mode::Active);
         assert!(gpu.it_vblank == true);
     }
+
+    /// Make sure VRAM and OAM are only locked when the GPU uses them
+    #[test]
+    fn video_memory_locking() {
+        let mut d = ::ui::dummy::DummyDisplay;
+        let mut gpu = super::Gpu::new(&mut d);
+
+        // Everything is accessible while the LCD is off
+        assert!(gpu.vram_accessible());
+        assert!(gpu.oam_accessible());
+
+        gpu.set_lcdc(0x80);
+
+        // Line 0 starts in the prelude
+        gpu.step();
+        assert!(gpu.mode() == super::Mode::Prelude);
+        assert!(gpu.vram_accessible());
+        assert!(!gpu.oam_accessible());
+
+        while gpu.mode() != super::Mode::Active {
+            gpu.step();
+        }
+
+        assert!(!gpu.vram_accessible());
+        assert!(!gpu.oam_accessible());
+
+        while gpu.mode() != super::Mode::HBlank {
+            gpu.step();
+        }
+
+        assert!(gpu.vram_accessible());
+        assert!(gpu.oam_accessible());
+    }
+
+    /// The debugging switches must not be visible to the game
+    #[test]
+    fn layer_toggles() {
+        use super::Layer;
+
+        let mut d = ::ui::dummy::DummyDisplay;
+        let mut gpu = super::Gpu::new(&mut d);
+
+        gpu.set_lcdc(0xff);
+
+        // Put 12 sprites on the first line
+        for i in 0..12 {
+            gpu.set_oam(i * 4, 16);
+            gpu.set_oam(i * 4 + 1, 8 + i as u8 * 8);
+        }
+
+        assert!(gpu.line_cache[0].iter().filter(|s| s.is_some()).count() == 10);
+
+        assert!(!gpu.toggle_layer(Layer::Background));
+        assert!(!gpu.toggle_layer(Layer::Window));
+        assert!(!gpu.toggle_layer(Layer::Sprites));
+        assert!(!gpu.toggle_layer(Layer::SpriteLimit));
+
+        assert!(gpu.lcdc() == 0xff);
+        assert!(gpu.line_cache[0].iter().filter(|s| s.is_some()).count() == 12);
+
+        assert!(gpu.toggle_layer(Layer::SpriteLimit));
+        assert!(gpu.line_cache[0].iter().filter(|s| s.is_some()).count() == 10);
+    }
 }
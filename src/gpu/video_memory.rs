@@ -0,0 +1,147 @@
+//! Snapshot of the video memory used by the debugging views
+
+use gpu::{Color, Source, Palette, TileMap, TileSet, SpriteSize};
+use gpu::sprite::{self, Sprite};
+
+/// Copy of the VRAM, OAM and the registers needed to interpret them.
+/// It's taken at VBlank so it's consistent with the frame that has
+/// just been displayed.
+#[derive(Clone)]
+pub struct VideoMemory {
+    vram:            Vec<u8>,
+    oam:             [Sprite; 40],
+    bg_tile_map:     TileMap,
+    window_tile_map: TileMap,
+    bg_win_tile_set: TileSet,
+    sprite_size:     SpriteSize,
+    bgp:             Palette,
+    obp0:            Palette,
+    obp1:            Palette,
+    scx:             u8,
+    scy:             u8,
+    wx:              u8,
+    wy:              u8,
+}
+
+impl VideoMemory {
+    pub fn new(gpu: &super::Gpu) -> VideoMemory {
+        VideoMemory {
+            vram:            gpu.vram.to_vec(),
+            oam:             gpu.oam,
+            bg_tile_map:     gpu.bg_tile_map,
+            window_tile_map: gpu.window_tile_map,
+            bg_win_tile_set: gpu.bg_win_tile_set,
+            sprite_size:     gpu.sprite_size,
+            bgp:             gpu.bgp,
+            obp0:            gpu.obp0,
+            obp1:            gpu.obp1,
+            scx:             gpu.scx,
+            scy:             gpu.scy,
+            wx:              gpu.wx,
+            wy:              gpu.wy,
+        }
+    }
+
+    /// Color of pixel (`x`, `y`) of the `tile`th tile in VRAM (there
+    /// are 384 of them) through the background palette.
+    pub fn tile_pixel(&self, tile: usize, x: u8, y: u8) -> Color {
+        let raw = self.raw_pixel(tile as u16 * 16, x, y);
+
+        self.bgp.transform(raw)
+    }
+
+    /// Color of pixel (`x`, `y`) of the 256x256 pixel background
+    /// map. `high` selects the map at 0x9c00 instead of 0x9800.
+    pub fn map_pixel(&self, high: bool, x: u8, y: u8) -> Color {
+        let map = if high { TileMap::High } else { TileMap::Low };
+
+        let addr = map.base() + (y as u16 / 8) * 32 + (x as u16 / 8);
+        let tile = self.vram[addr as usize];
+
+        let raw = self.raw_pixel(self.bg_win_tile_set.tile_addr(tile),
+                                 x % 8,
+                                 y % 8);
+
+        self.bgp.transform(raw)
+    }
+
+    /// Return `true` if the background uses the high tile map
+    pub fn bg_map_high(&self) -> bool {
+        match self.bg_tile_map {
+            TileMap::Low  => false,
+            TileMap::High => true,
+        }
+    }
+
+    /// Return `true` if the window uses the high tile map
+    pub fn window_map_high(&self) -> bool {
+        match self.window_tile_map {
+            TileMap::Low  => false,
+            TileMap::High => true,
+        }
+    }
+
+    pub fn sprites(&self) -> &[Sprite] {
+        &self.oam
+    }
+
+    /// Height of the sprites in pixels, 8 or 16
+    pub fn sprite_height(&self) -> u8 {
+        self.sprite_size.height() as u8
+    }
+
+    /// Color and palette of pixel (`x`, `y`) of `sprite`, taking the
+    /// flips into account. Returns `None` if the pixel is
+    /// transparent.
+    pub fn sprite_pixel(&self, sprite: &Sprite, x: u8, y: u8) -> Option<(Color, Source)> {
+        let height = self.sprite_height();
+
+        let tile = match self.sprite_size {
+            SpriteSize::Sz8x8  => sprite.tile(),
+            SpriteSize::Sz8x16 => sprite.tile() & 0xfe,
+        };
+
+        let x = if sprite.x_flip() { 7 - x } else { x };
+        let y = if sprite.y_flip() { height - 1 - y } else { y };
+
+        let raw = self.raw_pixel(TileSet::Set1.tile_addr(tile), x, y);
+
+        if raw == Color::White {
+            return None;
+        }
+
+        Some(match sprite.palette() {
+            sprite::Palette::Obp0 => (self.obp0.transform(raw), Source::Obp0),
+            sprite::Palette::Obp1 => (self.obp1.transform(raw), Source::Obp1),
+        })
+    }
+
+    pub fn scx(&self) -> u8 {
+        self.scx
+    }
+
+    pub fn scy(&self) -> u8 {
+        self.scy
+    }
+
+    pub fn wx(&self) -> u8 {
+        self.wx
+    }
+
+    pub fn wy(&self) -> u8 {
+        self.wy
+    }
+
+    /// Color of pixel (`x`, `y`) of the tile at VRAM offset `addr`
+    /// before going through the palette. `y` can go past the first
+    /// tile for 8x16 sprites.
+    fn raw_pixel(&self, addr: u16, x: u8, y: u8) -> Color {
+        let addr = (addr + 2 * y as u16) as usize;
+        let x    = 7 - x;
+
+        let lsb = (self.vram[addr]     >> x) & 1;
+        let msb = (self.vram[addr + 1] >> x) & 1;
+
+        Color::from_u8(msb << 1 | lsb)
+    }
+}
@@ -1,12 +1,92 @@
 //! The GameBoy ROM that gets mapped at address 0 at startup. It's not
 //! accessible during normal game execution.
 
+use std::fs::File;
+use std::io::{Read, Result, Error, ErrorKind};
+use std::path::Path;
+
+/// Console model. Only the original Game Boy hardware is emulated,
+/// the model just selects the built-in bootrom.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Model {
+    /// Original Game Boy
+    Dmg,
+    /// Super Game Boy
+    Sgb,
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Option<Model> {
+        match name {
+            "dmg" => Some(Model::Dmg),
+            "sgb" => Some(Model::Sgb),
+            _     => None,
+        }
+    }
+
+    /// Return the built-in bootrom for this model
+    pub fn bootrom(self) -> &'static [u8; 0x100] {
+        match self {
+            Model::Dmg => &DMG_BOOTROM,
+            Model::Sgb => &SGB_BOOTROM,
+        }
+    }
+
+    /// Values of the `AF`, `BC`, `DE` and `HL` registers when the
+    /// bootrom jumps to the game
+    pub fn boot_registers(self) -> [u16; 4] {
+        match self {
+            Model::Dmg => [0x01b0, 0x0013, 0x00d8, 0x014d],
+            Model::Sgb => [0x0100, 0x0014, 0x0000, 0xc060],
+        }
+    }
+}
+
+/// Register writes done by the bootrom that matter to the game,
+/// replayed when it's skipped. The last one unmaps the bootrom.
+pub static BOOT_WRITES: [(u16, u8); 8] = [
+    (0xff26, 0x80), // NR52: sound on
+    (0xff11, 0x80), // NR11
+    (0xff12, 0xf3), // NR12
+    (0xff25, 0xf3), // NR51
+    (0xff24, 0x77), // NR50
+    (0xff47, 0xfc), // BGP
+    (0xff40, 0x91), // LCDC: LCD and background on
+    (0xff50, 0x01), // Unmap the bootrom
+];
+
+impl Default for Model {
+    /// Model used when none is specified, depends on the
+    /// `sgb_bootrom` feature
+    fn default() -> Model {
+        if cfg!(feature="sgb_bootrom") {
+            Model::Sgb
+        } else {
+            Model::Dmg
+        }
+    }
+}
+
+/// Load a bootrom dump from `path`, it must be exactly 256 bytes long
+pub fn load(path: &Path) -> Result<Vec<u8>> {
+    let mut rom = Vec::new();
+
+    try!(try!(File::open(path)).read_to_end(&mut rom));
+
+    if rom.len() != 0x100 {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              format!("bootrom must be 256 bytes long, got {}",
+                                      rom.len())));
+    }
+
+    Ok(rom)
+}
+
 /// Original GameBoy bootrom. It scrolls the NINTENDO logo down the
 /// screen and emits the signature two notes when it reaches the
 /// middle. It also checks that the cartridge ROM header is correct
 /// and deadlocks if that check fails.
-#[cfg(not(feature="sgb_bootrom"))]
-pub static BOOTROM: [u8; 0x100] = [
+pub static DMG_BOOTROM: [u8; 0x100] = [
     // init_stack:
     0x31, 0xFE, 0xFF, // LD     SP 0xfffe
     0xAF,             // XOR    A A
@@ -191,8 +271,7 @@ pub static BOOTROM: [u8; 0x100] = [
 /// Super GameBoy bootrom, lifted from
 /// http://www.its.caltech.edu/~costis/sgb_hack/. It doesn't scroll
 /// the logo down the screen so it's faster than the original GB.
-#[cfg(feature="sgb_bootrom")]
-pub static BOOTROM: [u8; 0x100] = [
+pub static SGB_BOOTROM: [u8; 0x100] = [
     // init_stack:
     0x31, 0xFE, 0xFF, // LD     SP 0xfffe
 
@@ -11,8 +11,7 @@ use self::io_map::{NR3_RAM_START, NR3_RAM_END};
 pub mod ram;
 pub mod timer;
 pub mod buttons;
-
-mod bootrom;
+pub mod bootrom;
 
 /// Interconnect struct used by the CPU and GPU to access the ROM, RAM
 /// and registers
@@ -40,7 +39,15 @@ pub struct Interconnect<'a> {
     /// The game boy starts up mapping the bootrom at address [0,
     /// 0xff]. The last thing the bootrom does is writing 0x01 to
     /// UNMAP_BOOTROM to remove itself from the memory map.
-    bootrom:    bool,
+    bootrom:    Option<Vec<u8>>,
+    /// Bytes sent through the serial link, if captured
+    serial:     Option<Vec<u8>>,
+    /// Serial transfer data
+    sb:         u8,
+    /// If `true` the CPU can't access VRAM and OAM while they're in
+    /// use by the GPU or the DMA. Can be disabled to debug broken
+    /// homebrews that don't wait for the right GPU mode.
+    lock_video_memory: bool,
 }
 
 impl<'a> Interconnect<'a> {
@@ -69,39 +76,103 @@ impl<'a> Interconnect<'a> {
                        dma_src:    0,
                        dma_idx:    map::range_size(map::OAM),
                        buttons:    buttons,
-                       bootrom:    true,
+                       bootrom:    Some(bootrom::Model::default()
+                                            .bootrom()
+                                            .to_vec()),
+                       serial:     None,
+                       sb:         0,
+                       lock_video_memory: true,
         }
     }
 
+    /// Replace the bootrom, must be called before running the
+    /// first instruction
+    pub fn set_bootrom(&mut self, rom: Vec<u8>) {
+        self.bootrom = Some(rom);
+    }
+
+    /// Start or stop keeping a copy of the bytes sent through the
+    /// serial link. Test ROMs use it to report their results.
+    pub fn set_serial_capture(&mut self, enable: bool) {
+        self.serial = if enable { Some(Vec::new()) } else { None };
+    }
+
+    /// Return the bytes sent through the serial link since the last
+    /// call. Always empty if capture is disabled.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        match self.serial {
+            Some(ref mut serial) => ::std::mem::replace(serial, Vec::new()),
+            None                 => Vec::new(),
+        }
+    }
+
+    /// Enable or disable the VRAM and OAM access restrictions
+    pub fn set_video_memory_locking(&mut self, lock: bool) {
+        self.lock_video_memory = lock;
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    pub fn gpu(&self) -> &Gpu<'a> {
+        &self.gpu
+    }
+
+    pub fn gpu_mut(&mut self) -> &mut Gpu<'a> {
+        &mut self.gpu
+    }
+
+    pub fn spu_mut(&mut self) -> &mut Spu {
+        &mut self.spu
+    }
+
     pub fn step(&mut self) {
         self.gpu.step();
         self.spu.step();
         self.dma_step();
         self.timer.step();
+        self.cartridge.step();
     }
 
     pub fn dma_step(&mut self) {
-        let end = map::range_size(map::OAM);
-
-        if self.dma_idx >= end {
+        if !self.dma_active() {
             // No dma transfer in progress
             return;
         }
 
-        let b = self.fetch_byte(self.dma_src);
+        // The DMA isn't subject to the CPU's VRAM and OAM locking
+        let b = self.fetch_byte_raw(self.dma_src);
         self.gpu.set_oam(self.dma_idx, b);
 
         self.dma_src += 1;
         self.dma_idx += 1;
     }
 
-    /// Get byte from peripheral mapped at `addr`
+    /// Get byte from peripheral mapped at `addr` as seen by the CPU
     pub fn fetch_byte(&self, addr: u16) -> u8 {
+        if map::in_range(addr, map::VRAM).is_some() && !self.vram_accessible() {
+            debug!("Read from locked VRAM {:04x}", addr);
+            return 0xff;
+        }
 
+        if map::in_range(addr, map::OAM).is_some() && !self.oam_accessible() {
+            debug!("Read from locked OAM {:04x}", addr);
+            return 0xff;
+        }
+
+        self.fetch_byte_raw(addr)
+    }
+
+    /// Get byte from peripheral mapped at `addr` regardless of the
+    /// VRAM and OAM locking, used by the DMA
+    fn fetch_byte_raw(&self, addr: u16) -> u8 {
         if let Some(off) = map::in_range(addr, map::ROM) {
-            if self.bootrom && off < 0x100 {
-                // Bootrom is still mapped, read from it
-                return bootrom::BOOTROM[off as usize];
+            if let Some(ref bootrom) = self.bootrom {
+                if off < 0x100 {
+                    // Bootrom is still mapped, read from it
+                    return bootrom[off as usize];
+                }
             }
 
             return self.cartridge.rom_byte(off);
@@ -150,6 +221,11 @@ impl<'a> Interconnect<'a> {
         }
 
         if let Some(off) = map::in_range(addr, map::VRAM) {
+            if !self.vram_accessible() {
+                debug!("Write to locked VRAM {:04x}: {:02x}", addr, val);
+                return;
+            }
+
             return self.gpu.set_vram(off, val);
         }
 
@@ -166,6 +242,11 @@ impl<'a> Interconnect<'a> {
         }
 
         if let Some(off) = map::in_range(addr, map::OAM) {
+            if !self.oam_accessible() {
+                debug!("Write to locked OAM {:04x}: {:02x}", addr, val);
+                return;
+            }
+
             return self.gpu.set_oam(off, val);
         }
 
@@ -181,10 +262,10 @@ impl<'a> Interconnect<'a> {
             return self.it_enabled = Interrupts::from_register(val);
         }
 
-        if self.bootrom && addr == map::UNMAP_BOOTROM {
+        if self.bootrom.is_some() && addr == map::UNMAP_BOOTROM {
             if val == 1 {
                 // Unmap bootrom
-                self.bootrom = false;
+                self.bootrom = None;
             }
             return;
         }
@@ -192,6 +273,19 @@ impl<'a> Interconnect<'a> {
         debug!("Write to unmapped memory {:04x}: {:02x}", addr, val);
     }
 
+    /// Return `true` if the CPU is currently allowed to access VRAM
+    fn vram_accessible(&self) -> bool {
+        !self.lock_video_memory || self.gpu.vram_accessible()
+    }
+
+    /// Return `true` if the CPU is currently allowed to access
+    /// OAM. It's locked by the GPU in modes 2 and 3 and during DMA
+    /// transfers.
+    fn oam_accessible(&self) -> bool {
+        !self.lock_video_memory ||
+            (self.gpu.oam_accessible() && !self.dma_active())
+    }
+
     /// Return the highest priority active interrupt after
     /// acknowledging it. If no interrupt is pending return `None`.
     pub fn next_interrupt_ack(&mut self) -> Option<Interrupt> {
@@ -308,10 +402,18 @@ impl<'a> Interconnect<'a> {
             // Controller input
             io_map::INPUT    => self.buttons.set_input(val),
             // Seral link
-            io_map::SB       =>
-                debug!("unhandled write to serial data: 0x{:02x}", val),
-            io_map::SC       =>
-                debug!("unhandled write to serial control: 0x{:02x}", val),
+            io_map::SB       => self.sb = val,
+            io_map::SC       => {
+                // There's nothing connected to the link port but we
+                // keep what the game sends using the internal clock
+                if val & 0x81 == 0x81 {
+                    if let Some(ref mut serial) = self.serial {
+                        serial.push(self.sb);
+                    }
+                }
+
+                debug!("unhandled write to serial control: 0x{:02x}", val);
+            }
             // Timers
             io_map::DIV      => self.timer.reset_div(),
             io_map::TIMA     => self.timer.set_counter(val),
@@ -381,6 +483,11 @@ impl<'a> Interconnect<'a> {
         (self.dma_src >> 8) as u8
     }
 
+    /// Return `true` if an OAM DMA transfer is in progress
+    fn dma_active(&self) -> bool {
+        self.dma_idx < map::range_size(map::OAM)
+    }
+
     /// Start a new transfer from (`src` << 8) into OAM
     fn start_dma(&mut self, src: u8) {
         self.dma_idx = 0;
@@ -580,3 +687,55 @@ mod io_map {
     /// Window X position + 7
     pub const LCD_WX:        u16 = 0x4b;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Interconnect;
+    use cartridge::Cartridge;
+    use gpu::{Gpu, Mode};
+    use spu::Spu;
+    use ui::dummy::{DummyDisplay, DummyController};
+
+    /// The OAM DMA must copy VRAM even while the CPU can't access it
+    #[test]
+    fn dma_from_locked_vram() {
+        let mut display    = DummyDisplay;
+        let controller     = DummyController::new();
+        let (spu, _audio)  = Spu::new();
+        let cart           = Cartridge::from_vec(vec![0; 0x8000]);
+
+        let mut inter = Interconnect::new(cart,
+                                          Gpu::new(&mut display),
+                                          spu,
+                                          controller.buttons());
+
+        for i in 0..0xa0 {
+            inter.store_byte(0x8000 + i, i as u8);
+        }
+
+        // Enable the LCD and wait for the GPU to draw a line
+        inter.store_byte(0xff40, 0x91);
+
+        while inter.gpu().mode() != Mode::Active {
+            inter.step();
+        }
+
+        assert!(inter.fetch_byte(0x8001) == 0xff);
+
+        inter.store_byte(0xff46, 0x80);
+
+        for _ in 0..0xa0 {
+            inter.step();
+        }
+
+        // Disable the LCD to read OAM back
+        inter.store_byte(0xff40, 0x00);
+
+        for i in 0..0xa0 {
+            // Only the upper nibble of the sprite flags is stored
+            let expected = if i % 4 == 3 { i as u8 & 0xf0 } else { i as u8 };
+
+            assert!(inter.fetch_byte(0xfe00 + i) == expected);
+        }
+    }
+}
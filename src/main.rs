@@ -20,8 +20,8 @@ extern crate num;
 extern crate test;
 
 use std::sync::mpsc::channel;
+use std::io::Write;
 use ui::Audio;
-use std::path::Path;
 
 mod cpu;
 mod io;
@@ -30,55 +30,167 @@ mod ui;
 mod cartridge;
 mod spu;
 mod resampler;
+mod config;
+mod capture;
+mod cli;
+mod crc;
 
 #[allow(dead_code)]
 fn main() {
     let argv: Vec<_> = std::env::args().collect();
 
-    if argv.len() < 2 {
-        println!("Usage: {} <rom-file>", argv[0]);
-        return;
+    let code =
+        match cli::parse(&argv[1..]) {
+            Ok(command) => match run_command(command, &argv[0]) {
+                Ok(())  => cli::EXIT_SUCCESS,
+                Err(e)  => {
+                    report(&format!("{}", e));
+                    e.exit_code()
+                }
+            },
+            Err(e) => {
+                report(&format!("{}\nTry '{} help' for more information.",
+                                e, argv[0]));
+                e.exit_code()
+            }
+        };
+
+    // Everything has been dropped (and the game saved) by now
+    std::process::exit(code);
+}
+
+fn run_command(command: cli::Command, program: &str) -> Result<(), cli::Error> {
+    match command {
+        cli::Command::Run(o)      => run(o),
+        cli::Command::Info(rom)   => cli::rom::info(&rom),
+        cli::Command::Headless(o) => cli::headless::headless(&o),
+        cli::Command::Disasm(o)   => cli::rom::disasm(&o),
+        cli::Command::Test(o)     => cli::headless::test(&o),
+        cli::Command::Help        => {
+            println!("{}", cli::usage(program));
+            Ok(())
+        }
     }
+}
 
-    let rompath = Path::new(&argv[1]);
+/// Print an error message on stderr
+fn report(msg: &str) {
+    let _ = writeln!(&mut std::io::stderr(), "{}", msg);
+}
 
-    let cart = match cartridge::Cartridge::from_path(&rompath) {
-        Ok(r)  => r,
-        Err(e) => panic!("Failed to load ROM: {}", e),
-    };
+/// Play a game with the SDL2 interface
+fn run(options: cli::RunOptions) -> Result<(), cli::Error> {
+    let mut cart = try!(cli::load_cartridge(&options.rom));
+
+    let bootable = cli::check_header(cart.header());
 
     println!("Loaded ROM {:?}", cart);
 
-    let sdl2 = ui::sdl2::Context::new();
+    let settings = try!(cli::load_settings(&cart,
+                                           options.config.as_ref().map(|c| c.as_path()),
+                                           &options.overrides));
+
+    try!(cart.init_ram(settings.paths.saves.as_ref().map(|p| p.as_path()))
+         .map_err(|e| cli::Error::Load(format!("Couldn't open save file: {}", e))));
+
+    let mut video = settings.video;
+
+    // The command line overrides the config file
+    if options.palette.is_some() {
+        video.palette = options.palette.clone();
+    }
+
+    if let Some(scale) = options.scale {
+        video.scale = scale;
+    }
+
+    if let Some(scaling) = options.scaling {
+        video.scaling = scaling;
+    }
+
+    if options.fullscreen {
+        video.fullscreen = true;
+    }
+
+    let sdl2 = ui::sdl2::Context::new(&settings.bindings);
+
+    let mut palettes = settings.palettes;
+
+    try!(cli::select_palette(&mut palettes,
+                             video.palette.as_ref().map(|p| &p[..])));
+
+    let game = cart.name().unwrap_or(String::new());
+
+    let mut display = sdl2.new_display(&video, palettes, game);
+
+    if let Some(f) = options.filter {
+        display.set_filter(f);
+    }
+
+    display.set_ghosting(options.ghosting);
 
-    let mut display = sdl2.new_display(1);
+    display.set_output_dir(&settings.paths.screenshots);
 
     let gpu = gpu::Gpu::new(&mut display);
 
     let (spu, audio_channel) = spu::Spu::new();
 
-    let mut audio = ui::sdl2::Audio::new(audio_channel);
+    let mut audio =
+        if options.mute {
+            cli::headless::drain_audio(audio_channel);
+            None
+        } else {
+            let audio = ui::sdl2::Audio::new(audio_channel,
+                                             settings.audio.sample_rate);
 
-    audio.start();
+            audio.start();
 
-    let inter = io::Interconnect::new(cart, gpu, spu, sdl2.buttons());
+            Some(audio)
+        };
+
+    let mut inter = io::Interconnect::new(cart, gpu, spu, sdl2.buttons());
+
+    try!(options.machine.apply(&mut inter));
 
     let mut cpu = cpu::Cpu::new(inter);
 
+    if !bootable {
+        cpu.skip_bootrom(options.machine.model);
+    }
+
+    let mut recorder =
+        match options.record {
+            Some(ref base) => {
+                let r = capture::recorder::Recorder::new(base);
+
+                Some(try!(r.map_err(|e| {
+                    cli::Error::Runtime(format!("Couldn't start recording: {}", e))
+                })))
+            }
+            None => None,
+        };
+
+    if recorder.is_some() {
+        cpu.interconnect_mut().spu_mut().set_capture(true);
+    }
+
     // In order to synchronize the emulation speed with the wall clock
     // we need to wait at some point so that we don't go too
     // fast. Waiting between each cycle would mean a storm of syscalls
     // back and forth between the kernel and us, so instead we execute
-    // instructions in batches of GRANULARITY cycles and then sleep
-    // for a while. If the GRANULARITY value is too low we'll go to
-    // sleep very often which will have poor performance. If it's too
-    // high it might look like the emulation is stuttering.
+    // instructions in batches of `granularity` cycles and then sleep
+    // for a while. If the granularity is too low we'll go to sleep
+    // very often which will have poor performance. If it's too high
+    // it might look like the emulation is stuttering.
+    let granularity = settings.granularity as i64;
 
-    let batch_duration_ns = GRANULARITY * (1_000_000_000 /
+    let batch_duration_ns = granularity * (1_000_000_000 /
                                            SYSCLK_FREQ);
 
     // No sub-ms precision in stable rust sleep for now...
-    let batch_duration_ms = (batch_duration_ns / 1_000_000) as u32;
+    let batch_duration_ms = (batch_duration_ns as f32 /
+                             options.speed /
+                             1_000_000.) as u32;
 
     let (tick_tx, tick_rx) = channel();
 
@@ -98,50 +210,82 @@ fn main() {
 
     let mut cycles = 0;
 
-    loop {
-        while cycles < GRANULARITY {
+    'emulation: loop {
+        while cycles < granularity {
             // The actual emulator takes place here!
-            cycles += cpu.run_next_instruction() as i64;
+            let c = cpu.run_next_instruction();
+
+            cycles += c as i64;
+
+            if let Some(ref mut r) = recorder {
+                if let Err(e) = r.step(c as u32, &sdl2.screen().borrow()) {
+                    return Err(cli::Error::Runtime(format!("Recording failed: {}", e)));
+                }
+            }
         }
 
-        cycles -= GRANULARITY;
+        cycles -= granularity;
+
+        if let Some(ref mut r) = recorder {
+            let samples = cpu.interconnect_mut().spu_mut().take_captured();
+
+            if let Err(e) = r.push_samples(&samples) {
+                return Err(cli::Error::Runtime(format!("Recording failed: {}", e)));
+            }
+        }
 
         // Update controller status
-        match sdl2.update_buttons() {
-            ui::Event::PowerOff => break,
-            ui::Event::None     => (),
+        for event in sdl2.update_buttons() {
+            match event {
+                ui::Event::PowerOff => break 'emulation,
+                ui::Event::ToggleLayer(layer) => {
+                    let shown = cpu.interconnect_mut()
+                        .gpu_mut()
+                        .toggle_layer(layer);
+
+                    println!("{:?}: {}", layer, if shown { "on" } else { "off" });
+                }
+            }
+        }
+
+        // The accelerometer follows the tilt given by the controller
+        if let Some(a) = cpu.interconnect_mut().cartridge_mut().accelerometer() {
+            a.set_tilt(sdl2.tilt().get());
         }
 
         // Sleep until next batch cycle
         if let Err(e) = tick_rx.recv() {
-             panic!("Timer died: {:?}", e);
+            return Err(cli::Error::Runtime(format!("Timer died: {:?}", e)));
         }
 
-        audio_adjust_count += GRANULARITY;
+        audio_adjust_count += granularity;
 
-        if audio_adjust_count >= SYSCLK_FREQ * AUDIO_ADJUST_SEC {
+        if audio_adjust_count >= SYSCLK_FREQ * settings.audio.adjust_interval as i64 {
             // Retrieve the number of samples generated since the last
             // adjustment
             let s = spu::samples_per_steps(audio_adjust_count as u32);
 
-            audio.adjust_resampling(s);
+            if let Some(ref mut audio) = audio {
+                audio.adjust_resampling(s);
+            }
 
             audio_adjust_count = 0;
         }
     }
-}
 
-/// Number of instructions executed between sleeps (i.e. giving the
-/// hand back to the scheduler). Low values increase CPU usage and can
-/// result in poor performance, high values will cause stuttering.
-const GRANULARITY:      i64 = 0x10000;
+    if let Some(r) = recorder {
+        match r.finish() {
+            Ok(frames) => println!("Recorded {} frames", frames),
+            Err(e)     => println!("Couldn't finish recording: {}", e),
+        }
+    }
+
+    Ok(())
+}
 
 /// Gameboy sysclk frequency: 4.19Mhz
 const SYSCLK_FREQ:      i64 = 0x400000;
 
-/// How often should we adjust the audio resampling rate. In seconds.
-const AUDIO_ADJUST_SEC: i64 = 1;
-
 #[cfg(test)]
 mod benchmark {
     use test::Bencher;
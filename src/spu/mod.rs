@@ -38,6 +38,8 @@ pub struct Spu {
     so1:      SoundOutput,
     /// Sound Output 2
     so2:      SoundOutput,
+    /// Copy of the generated samples kept for recording, if enabled
+    capture:  Option<Vec<Sample>>,
 }
 
 impl Spu {
@@ -57,6 +59,7 @@ impl Spu {
             sound4:   LfsrWave::new(),
             so1:      SoundOutput::new(),
             so2:      SoundOutput::new(),
+            capture:  None,
         };
 
         (spu , rx)
@@ -64,15 +67,15 @@ impl Spu {
 
     pub fn step(&mut self) {
 
-        if !self.enabled {
-            return
+        // Keep sampling while the sound circuit is disabled so that
+        // the output stream stays in sync with the emulated time.
+        if self.enabled {
+            self.sound1.step();
+            self.sound2.step();
+            self.sound3.step();
+            self.sound4.step();
         }
 
-        self.sound1.step();
-        self.sound2.step();
-        self.sound3.step();
-        self.sound4.step();
-
         if self.divider == 0 {
             self.divider = SAMPLER_DIVIDER;
 
@@ -83,6 +86,11 @@ impl Spu {
     }
 
     fn sample(&mut self) {
+        if !self.enabled {
+            self.output_sample(0);
+            return;
+        }
+
         let sounds =
                 [self.sound1.sample(),
                  self.sound2.sample(),
@@ -102,6 +110,10 @@ impl Spu {
     /// asynchronous channel.
     fn output_sample(&mut self, sample: Sample) {
 
+        if let Some(ref mut capture) = self.capture {
+            capture.push(sample);
+        }
+
         self.buffer[self.position] = sample;
 
         self.position += 1;
@@ -121,6 +133,21 @@ impl Spu {
         }
     }
 
+    /// Start or stop keeping a copy of the generated samples for
+    /// recording
+    pub fn set_capture(&mut self, enable: bool) {
+        self.capture = if enable { Some(Vec::new()) } else { None };
+    }
+
+    /// Return the samples generated since the last call. Always
+    /// empty if capture is disabled.
+    pub fn take_captured(&mut self) -> Vec<Sample> {
+        match self.capture {
+            Some(ref mut capture) => ::std::mem::replace(capture, Vec::new()),
+            None                  => Vec::new(),
+        }
+    }
+
     /// Retreive sound 1 sweep function
     pub fn nr10(&self) -> u8 {
         let sweep = self.sound1.sweep();
@@ -738,7 +765,7 @@ pub const SAMPLES_PER_BUFFER: usize = 0x200;
 /// 44.1kHz which is a reasonable default. The resulting audio stream
 /// will have to be resampled by the audio backend to the target
 /// frequency.
-const SAMPLER_DIVIDER: u32 = 95;
+pub const SAMPLER_DIVIDER: u32 = 95;
 
 pub const SAMPLE_RATE: u32 = ::SYSCLK_FREQ as u32 / SAMPLER_DIVIDER;
 
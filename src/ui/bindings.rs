@@ -0,0 +1,534 @@
+//! Input bindings: which keyboard keys, controller buttons and
+//! controller axes map to the Game Boy buttons and to the emulator
+//! hotkeys. The input names are only validated here, converting them
+//! to the frontend's types is left to the frontend.
+//!
+//! This is also where the turbo buttons and the input macros are
+//! configured.
+
+use config::toml::{Table, Value};
+use ui::{Buttons, ButtonState};
+
+/// Anything an input can be bound to
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Action {
+    A,
+    B,
+    Start,
+    Select,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// A pressed and released repeatedly while held
+    TurboA,
+    /// B pressed and released repeatedly while held
+    TurboB,
+    /// Tilt the console for the cartridges with an accelerometer
+    TiltUp,
+    TiltDown,
+    TiltLeft,
+    TiltRight,
+    /// Play the macro at this index in `Bindings::macros`
+    Macro(usize),
+    Quit,
+    NextPalette,
+    NextFilter,
+    ToggleBackground,
+    ToggleWindow,
+    ToggleSprites,
+    SpriteLimit,
+    ToggleViewer,
+    DumpViews,
+    ToggleGif,
+    ToggleFullscreen,
+    NextScaling,
+    Screenshot,
+    ScaledScreenshot,
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::A                => "a",
+            Action::B                => "b",
+            Action::Start            => "start",
+            Action::Select           => "select",
+            Action::Up               => "up",
+            Action::Down             => "down",
+            Action::Left             => "left",
+            Action::Right            => "right",
+            Action::TurboA           => "turbo-a",
+            Action::TurboB           => "turbo-b",
+            Action::TiltUp           => "tilt-up",
+            Action::TiltDown         => "tilt-down",
+            Action::TiltLeft         => "tilt-left",
+            Action::TiltRight        => "tilt-right",
+            Action::Macro(_)         => "macro",
+            Action::Quit             => "quit",
+            Action::NextPalette      => "next-palette",
+            Action::NextFilter       => "next-filter",
+            Action::ToggleBackground => "toggle-background",
+            Action::ToggleWindow     => "toggle-window",
+            Action::ToggleSprites    => "toggle-sprites",
+            Action::SpriteLimit      => "sprite-limit",
+            Action::ToggleViewer     => "toggle-viewer",
+            Action::DumpViews        => "dump-views",
+            Action::ToggleGif        => "toggle-gif",
+            Action::ToggleFullscreen => "toggle-fullscreen",
+            Action::NextScaling      => "next-scaling",
+            Action::Screenshot       => "screenshot",
+            Action::ScaledScreenshot => "scaled-screenshot",
+        }
+    }
+
+    /// Return the action called `name`. Macros aren't included since
+    /// their names are only known by `Bindings`.
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().cloned().find(|a| a.name() == name)
+    }
+
+    /// Return `true` if the action is a Game Boy button (i.e. it's
+    /// held down instead of being triggered once)
+    pub fn is_button(self) -> bool {
+        match self {
+            Action::A | Action::B | Action::Start | Action::Select |
+            Action::Up | Action::Down | Action::Left | Action::Right |
+            Action::TurboA | Action::TurboB => true,
+            _ => false,
+        }
+    }
+
+    /// Return `true` if the action tilts the console. Like the
+    /// buttons they're held down.
+    pub fn is_tilt(self) -> bool {
+        match self {
+            Action::TiltUp | Action::TiltDown |
+            Action::TiltLeft | Action::TiltRight => true,
+            _ => false,
+        }
+    }
+}
+
+/// Physical input
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum Input {
+    /// Keyboard key, using SDL's key names. If `shift` is true the
+    /// binding only matches when shift is held.
+    Key { name: String, shift: bool },
+    /// Controller button, using SDL's controller mapping names
+    /// ("a", "start", "dpup"...)
+    Button(&'static str),
+    /// Controller axis, using SDL's controller mapping names
+    /// ("leftx", "lefttrigger"...), along with the direction: `true`
+    /// for positive values.
+    Axis(&'static str, bool),
+}
+
+impl Input {
+    /// Parse an input description: `pad:<button>` for a controller
+    /// button, `pad:+<axis>` or `pad:-<axis>` for a controller axis
+    /// and the key name (optionally prefixed by `Shift+`) for a
+    /// keyboard key.
+    pub fn parse(s: &str) -> Result<Input, String> {
+        if s.starts_with("pad:") {
+            let pad = &s[4..];
+
+            let (dir, axis) =
+                if pad.starts_with("+") {
+                    (Some(true), &pad[1..])
+                } else if pad.starts_with("-") {
+                    (Some(false), &pad[1..])
+                } else {
+                    (None, pad)
+                };
+
+            return match dir {
+                Some(dir) =>
+                    match AXES.iter().find(|&&a| a == axis) {
+                        Some(a) => Ok(Input::Axis(a, dir)),
+                        None    => Err(format!("Unknown controller axis \"{}\"", axis)),
+                    },
+                None =>
+                    match BUTTONS.iter().find(|&&b| b == pad) {
+                        Some(b) => Ok(Input::Button(b)),
+                        None    => Err(format!("Unknown controller button \"{}\"", pad)),
+                    },
+            };
+        }
+
+        let (shift, name) =
+            if s.starts_with("Shift+") && s.len() > 6 {
+                (true, &s[6..])
+            } else {
+                (false, s)
+            };
+
+        if name.is_empty() {
+            return Err("Empty key name".to_string());
+        }
+
+        Ok(Input::Key { name: name.to_string(), shift: shift })
+    }
+}
+
+/// Named sequence of button states, one per frame
+#[derive(Clone,Debug)]
+pub struct Macro {
+    name:   String,
+    frames: Vec<Buttons>,
+}
+
+impl Macro {
+    /// Parse a macro definition: each step is a list of buttons
+    /// separated by `+` (or an empty string for no button), held for
+    /// one frame or for `n` frames if the step ends with `*n`. For
+    /// instance `["down", "down+right", "right", "a*4", "*10"]`.
+    pub fn parse(name: &str, steps: &[Value]) -> Result<Macro, String> {
+        let mut frames = Vec::new();
+
+        for step in steps {
+            let step =
+                try!(step.as_str()
+                     .ok_or(format!("Invalid step in macro \"{}\"", name)));
+
+            let (buttons, count) =
+                match step.find('*') {
+                    Some(i) => {
+                        let count = &step[i + 1..];
+
+                        match count.trim().parse::<u32>() {
+                            Ok(n) => (&step[..i], n),
+                            Err(_) => return Err(format!("Invalid frame count \"{}\"",
+                                                         count)),
+                        }
+                    }
+                    None => (step, 1),
+                };
+
+            let mut state = Buttons::new(ButtonState::Up);
+
+            for button in buttons.split('+').map(|b| b.trim()).filter(|b| !b.is_empty()) {
+                let down = ButtonState::Down;
+
+                match button {
+                    "a"      => state.a      = down,
+                    "b"      => state.b      = down,
+                    "start"  => state.start  = down,
+                    "select" => state.select = down,
+                    "up"     => state.up     = down,
+                    "down"   => state.down   = down,
+                    "left"   => state.left   = down,
+                    "right"  => state.right  = down,
+                    _ => return Err(format!("Unknown button \"{}\" in macro \"{}\"",
+                                            button, name)),
+                }
+            }
+
+            for _ in 0..count {
+                frames.push(state);
+            }
+        }
+
+        Ok(Macro {
+            name:   name.to_string(),
+            frames: frames,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// State of the buttons for each frame
+    pub fn frames(&self) -> &[Buttons] {
+        &self.frames
+    }
+}
+
+/// List of input bindings. The same input can be bound to several
+/// actions and vice-versa.
+#[derive(Clone,Debug)]
+pub struct Bindings {
+    bindings:   Vec<(Input, Action)>,
+    macros:     Vec<Macro>,
+    /// Number of frames the turbo buttons stay pressed, then
+    /// released
+    turbo_rate: u32,
+}
+
+impl Bindings {
+    /// Default bindings
+    pub fn new() -> Bindings {
+        let mut bindings = Vec::new();
+
+        for &(action, inputs) in DEFAULTS {
+            for input in inputs {
+                let input = Input::parse(input).unwrap();
+
+                bindings.push((input, Action::from_name(action).unwrap()));
+            }
+        }
+
+        Bindings {
+            bindings:   bindings,
+            macros:     Vec::new(),
+            turbo_rate: 2,
+        }
+    }
+
+    pub fn bindings(&self) -> &[(Input, Action)] {
+        &self.bindings
+    }
+
+    pub fn macros(&self) -> &[Macro] {
+        &self.macros
+    }
+
+    pub fn turbo_rate(&self) -> u32 {
+        self.turbo_rate
+    }
+
+    /// Load the `[input]` section of the configuration `table`. The
+    /// actions listed in `[input.bindings]` replace the default
+    /// bindings, the others are left untouched.
+    pub fn load_table(&mut self, table: &Table) -> Result<(), String> {
+        let input =
+            match table.get("input") {
+                Some(i) => try!(i.as_table().ok_or("'input' must be a table")),
+                None    => return Ok(()),
+            };
+
+        if let Some(rate) = input.get("turbo-rate") {
+            self.turbo_rate =
+                match rate.as_integer() {
+                    Some(r) if r >= 1 && r <= 60 => r as u32,
+                    _ => return Err("'turbo-rate' must be an integer between 1 and 60"
+                                    .to_string()),
+                };
+        }
+
+        // Load the macros first since the bindings refer to them
+        if let Some(macros) = input.get("macros") {
+            let macros =
+                try!(macros.as_table().ok_or("'input.macros' must be a table"));
+
+            for (name, steps) in macros {
+                let steps =
+                    try!(steps.as_slice()
+                         .ok_or(format!("Macro \"{}\" must be an array", name)));
+
+                let m = try!(Macro::parse(name, steps));
+
+                match self.macros.iter().position(|m| m.name == *name) {
+                    Some(i) => self.macros[i] = m,
+                    None    => self.macros.push(m),
+                }
+            }
+        }
+
+        let bindings =
+            match input.get("bindings") {
+                Some(b) => try!(b.as_table()
+                                .ok_or("'input.bindings' must be a table")),
+                None    => return Ok(()),
+            };
+
+        for (name, inputs) in bindings {
+            let action =
+                if name.starts_with("macro:") {
+                    let m = &name[6..];
+
+                    match self.macros.iter().position(|mac| mac.name == m) {
+                        Some(i) => Action::Macro(i),
+                        None    => return Err(format!("Unknown macro \"{}\"", m)),
+                    }
+                } else {
+                    try!(Action::from_name(name)
+                         .ok_or(format!("Unknown action \"{}\"", name)))
+                };
+
+            // A single input doesn't need to be put in an array
+            let inputs =
+                match *inputs {
+                    Value::String(_) => vec![inputs.clone()],
+                    Value::Array(ref a) => a.clone(),
+                    _ => return Err(format!("Invalid bindings for \"{}\"", name)),
+                };
+
+            self.bindings.retain(|&(_, a)| a != action);
+
+            for input in inputs {
+                let input =
+                    try!(input.as_str()
+                         .ok_or(format!("Invalid bindings for \"{}\"", name)));
+
+                self.bindings.push((try!(Input::parse(input)), action));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const ACTIONS: [Action; 28] = [
+    Action::A,
+    Action::B,
+    Action::Start,
+    Action::Select,
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::TurboA,
+    Action::TurboB,
+    Action::TiltUp,
+    Action::TiltDown,
+    Action::TiltLeft,
+    Action::TiltRight,
+    Action::Quit,
+    Action::NextPalette,
+    Action::NextFilter,
+    Action::ToggleBackground,
+    Action::ToggleWindow,
+    Action::ToggleSprites,
+    Action::SpriteLimit,
+    Action::ToggleViewer,
+    Action::DumpViews,
+    Action::ToggleGif,
+    Action::ToggleFullscreen,
+    Action::NextScaling,
+    Action::Screenshot,
+    Action::ScaledScreenshot,
+];
+
+/// Controller buttons, named like in SDL's controller mappings
+const BUTTONS: [&'static str; 15] = [
+    "a", "b", "x", "y", "back", "guide", "start", "leftstick",
+    "rightstick", "leftshoulder", "rightshoulder", "dpup", "dpdown",
+    "dpleft", "dpright",
+];
+
+/// Controller axes, named like in SDL's controller mappings
+const AXES: [&'static str; 6] = [
+    "leftx", "lefty", "rightx", "righty", "lefttrigger", "righttrigger",
+];
+
+const DEFAULTS: &'static [(&'static str, &'static [&'static str])] = &[
+    ("a",                 &["Left Alt", "pad:a"]),
+    ("b",                 &["Left Ctrl", "pad:b"]),
+    ("start",             &["Return", "pad:start"]),
+    ("select",            &["Right Shift", "pad:back"]),
+    ("up",                &["Up", "pad:dpup", "pad:-lefty"]),
+    ("down",              &["Down", "pad:dpdown", "pad:+lefty"]),
+    ("left",              &["Left", "pad:dpleft", "pad:-leftx"]),
+    ("right",             &["Right", "pad:dpright", "pad:+leftx"]),
+    ("turbo-a",           &["pad:x"]),
+    ("turbo-b",           &["pad:y"]),
+    ("tilt-up",           &["Keypad 8", "pad:-righty"]),
+    ("tilt-down",         &["Keypad 2", "pad:+righty"]),
+    ("tilt-left",         &["Keypad 4", "pad:-rightx"]),
+    ("tilt-right",        &["Keypad 6", "pad:+rightx"]),
+    ("quit",              &["Escape"]),
+    ("next-palette",      &["F2"]),
+    ("next-filter",       &["F3"]),
+    ("toggle-background", &["F5"]),
+    ("toggle-window",     &["F6"]),
+    ("toggle-sprites",    &["F7"]),
+    ("sprite-limit",      &["F8"]),
+    ("toggle-viewer",     &["F9"]),
+    ("dump-views",        &["Shift+F9"]),
+    ("toggle-gif",        &["F10"]),
+    ("toggle-fullscreen", &["F11"]),
+    ("next-scaling",      &["Shift+F11"]),
+    ("screenshot",        &["F12"]),
+    ("scaled-screenshot", &["Shift+F12"]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{Bindings, Input, Action};
+    use config::toml;
+
+    #[test]
+    fn parse_input() {
+        assert!(Input::parse("Left Alt") ==
+                Ok(Input::Key { name: "Left Alt".to_string(), shift: false }));
+        assert!(Input::parse("Shift+F12") ==
+                Ok(Input::Key { name: "F12".to_string(), shift: true }));
+        assert!(Input::parse("pad:dpup") == Ok(Input::Button("dpup")));
+        assert!(Input::parse("pad:-lefty") == Ok(Input::Axis("lefty", false)));
+        assert!(Input::parse("pad:+righttrigger") ==
+                Ok(Input::Axis("righttrigger", true)));
+        assert!(Input::parse("pad:turbo").is_err());
+        assert!(Input::parse("pad:+start").is_err());
+        assert!(Input::parse("").is_err());
+    }
+
+    #[test]
+    fn load() {
+        let mut bindings = Bindings::new();
+
+        let config = toml::parse("[input.bindings]\n\
+                                  a    = [\"X\", \"pad:b\"]\n\
+                                  quit = \"Q\"\n").unwrap();
+
+        bindings.load_table(&config).unwrap();
+
+        let inputs = |action| {
+            bindings.bindings().iter()
+                .filter(|&&(_, a)| a == action)
+                .map(|&(ref i, _)| i.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(inputs(Action::A) == vec![Input::parse("X").unwrap(),
+                                          Input::Button("b")]);
+        assert!(inputs(Action::Quit) == vec![Input::parse("Q").unwrap()]);
+        // Untouched
+        assert!(inputs(Action::B) == vec![Input::parse("Left Ctrl").unwrap(),
+                                          Input::Button("b")]);
+
+        let config = toml::parse("[input.bindings]\njump = \"X\"\n").unwrap();
+
+        assert!(bindings.load_table(&config).is_err());
+    }
+
+    #[test]
+    fn macros() {
+        let mut bindings = Bindings::new();
+
+        let config = toml::parse("[input]\n\
+                                  turbo-rate = 3\n\
+                                  [input.macros]\n\
+                                  fireball = [\"down\", \"down + right\", \"right+a*2\", \"*3\"]\n\
+                                  [input.bindings]\n\
+                                  \"macro:fireball\" = \"F4\"\n").unwrap();
+
+        bindings.load_table(&config).unwrap();
+
+        assert!(bindings.turbo_rate() == 3);
+
+        let m = &bindings.macros()[0];
+
+        assert!(m.name() == "fireball");
+        assert!(m.frames().len() == 7);
+        assert!(m.frames()[1].down.is_down() && m.frames()[1].right.is_down());
+        assert!(m.frames()[3].a.is_down() && !m.frames()[3].down.is_down());
+        assert!(!m.frames()[4].a.is_down() && !m.frames()[6].right.is_down());
+
+        assert!(bindings.bindings().iter()
+                .any(|&(ref i, a)| a == Action::Macro(0) &&
+                     *i == Input::parse("F4").unwrap()));
+
+        let config = toml::parse("[input.bindings]\n\
+                                  \"macro:nope\" = \"F4\"\n").unwrap();
+
+        assert!(bindings.load_table(&config).is_err());
+
+        let config = toml::parse("[input.macros]\n\
+                                  bad = [\"a+z\"]\n").unwrap();
+
+        assert!(bindings.load_table(&config).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Maxim Stepin's HQ2x and HQ3x filters.
+//!
+//! Each pixel is compared with its eight neighbours using thresholds
+//! in YUV space, which gives an 8 bit pattern. The original code has
+//! a 256-case switch per filter, here (like in FFmpeg's `vf_hqx`) the
+//! cases are expressed as pattern masks for one corner and the other
+//! corners are obtained by mirroring or rotating the neighbourhood.
+
+use ui::palette::Rgb;
+use ui::filter::{Filter, Source, Target};
+
+pub struct Hqx {
+    scale: u32,
+}
+
+impl Hqx {
+    /// Create a new hqx filter, `scale` must be 2 or 3
+    pub fn new(scale: u32) -> Hqx {
+        assert!(scale == 2 || scale == 3);
+
+        Hqx {
+            scale: scale,
+        }
+    }
+}
+
+impl Filter for Hqx {
+    fn name(&self) -> &'static str {
+        match self.scale {
+            2 => "hq2x",
+            _ => "hq3x",
+        }
+    }
+
+    fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    fn apply(&self, src: &[Rgb], width: u32, height: u32) -> Vec<Rgb> {
+        let src = Source::new(src, width, height);
+        let s = self.scale;
+        let mut out = Target::new(width * s, height * s);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (xi, yi) = (x as i32, y as i32);
+
+                // w[0] w[1] w[2]
+                // w[3] w[4] w[5]
+                // w[6] w[7] w[8]
+                let mut w = [[0; 3]; 9];
+
+                for i in 0..9 {
+                    w[i] = src.get(xi + (i as i32 % 3) - 1, yi + (i as i32 / 3) - 1);
+                }
+
+                let (ox, oy) = (x * s, y * s);
+
+                if s == 2 {
+                    for &(dx, dy, ref perm) in HQ2X_CORNERS.iter() {
+                        let n = Neighbours::new(&w, perm);
+
+                        out.set(ox + dx, oy + dy, hq2x_corner(&n));
+                    }
+                } else {
+                    for &(corner, edge, ref perm) in HQ3X_SIDES.iter() {
+                        let n = Neighbours::new(&w, perm);
+
+                        out.set(ox + corner.0, oy + corner.1, hq3x_corner(&n));
+                        out.set(ox + edge.0,   oy + edge.1,   hq3x_edge(&n));
+                    }
+
+                    out.set(ox + 1, oy + 1, w[4]);
+                }
+            }
+        }
+
+        out.pixels
+    }
+}
+
+/// Position of each output sub-pixel of HQ2x and the permutation of
+/// the neighbourhood that turns it into the top left one
+static HQ2X_CORNERS: [(u32, u32, [usize; 9]); 4] = [
+    (0, 0, [0, 1, 2, 3, 4, 5, 6, 7, 8]),
+    (1, 0, [2, 1, 0, 5, 4, 3, 8, 7, 6]),
+    (0, 1, [6, 7, 8, 3, 4, 5, 0, 1, 2]),
+    (1, 1, [8, 7, 6, 5, 4, 3, 2, 1, 0]),
+    ];
+
+/// Position of a corner and of the following edge sub-pixel (going
+/// clockwise) of HQ3x and the rotation of the neighbourhood that
+/// turns them into the top left corner and the top edge
+static HQ3X_SIDES: [((u32, u32), (u32, u32), [usize; 9]); 4] = [
+    ((0, 0), (1, 0), [0, 1, 2, 3, 4, 5, 6, 7, 8]),
+    ((2, 0), (2, 1), [2, 5, 8, 1, 4, 7, 0, 3, 6]),
+    ((0, 2), (0, 1), [6, 3, 0, 7, 4, 1, 8, 5, 2]),
+    ((2, 2), (1, 2), [8, 7, 6, 5, 4, 3, 2, 1, 0]),
+    ];
+
+/// Neighbourhood of a pixel seen from the top left corner
+struct Neighbours {
+    w:       [Rgb; 9],
+    /// Bit `n` is set if the `n`th neighbour (skipping the center)
+    /// is different from the center
+    pattern: u8,
+}
+
+impl Neighbours {
+    fn new(w: &[Rgb; 9], perm: &[usize; 9]) -> Neighbours {
+        let mut n = [[0; 3]; 9];
+
+        for (i, &p) in perm.iter().enumerate() {
+            n[i] = w[p];
+        }
+
+        let mut pattern = 0;
+
+        for (bit, &i) in [0, 1, 2, 3, 5, 6, 7, 8].iter().enumerate() {
+            if different(n[4], n[i]) {
+                pattern |= 1 << bit;
+            }
+        }
+
+        Neighbours {
+            w:       n,
+            pattern: pattern,
+        }
+    }
+
+    /// Return true if the pattern matches any of the `(mask, value)`
+    /// pairs in `cases`
+    fn is(&self, cases: &[(u8, u8)]) -> bool {
+        cases.iter().any(|&(m, r)| self.pattern & m == r)
+    }
+
+    fn different(&self, a: usize, b: usize) -> bool {
+        different(self.w[a], self.w[b])
+    }
+}
+
+/// Top left sub-pixel of HQ2x
+fn hq2x_corner(n: &Neighbours) -> Rgb {
+    let w = &n.w;
+
+    if n.is(&[(0xbf, 0x37), (0xdb, 0x13)]) && n.different(1, 5) {
+        interp(&[(w[4], 3), (w[3], 1)], 2)
+    } else if n.is(&[(0xdb, 0x49), (0xef, 0x6d)]) && n.different(7, 3) {
+        interp(&[(w[4], 3), (w[1], 1)], 2)
+    } else if n.is(&[(0x0b, 0x0b), (0xfe, 0x4a), (0xfe, 0x1a)]) &&
+        n.different(3, 1) {
+        w[4]
+    } else if n.is(&[(0x6f, 0x2a), (0x5b, 0x0a), (0xbf, 0x3a),
+                     (0xdf, 0x5a), (0x9f, 0x8a), (0xcf, 0x8a),
+                     (0xef, 0x4e), (0x3f, 0x0e), (0xfb, 0x5a),
+                     (0xbb, 0x8a), (0x7f, 0x5a), (0xaf, 0x8a),
+                     (0xeb, 0x8a)]) &&
+        n.different(3, 1) {
+        interp(&[(w[4], 3), (w[0], 1)], 2)
+    } else if n.is(&[(0x0b, 0x08)]) {
+        interp(&[(w[4], 2), (w[0], 1), (w[1], 1)], 2)
+    } else if n.is(&[(0x0b, 0x02)]) {
+        interp(&[(w[4], 2), (w[0], 1), (w[3], 1)], 2)
+    } else if n.is(&[(0x2f, 0x2f)]) {
+        interp(&[(w[4], 14), (w[3], 1), (w[1], 1)], 4)
+    } else if n.is(&[(0xbf, 0x37), (0xdb, 0x13)]) {
+        interp(&[(w[4], 5), (w[1], 2), (w[3], 1)], 3)
+    } else if n.is(&[(0xdb, 0x49), (0xef, 0x6d)]) {
+        interp(&[(w[4], 5), (w[3], 2), (w[1], 1)], 3)
+    } else if n.is(&[(0x1b, 0x03), (0x4f, 0x43), (0x8b, 0x83),
+                     (0x6b, 0x43)]) {
+        interp(&[(w[4], 3), (w[3], 1)], 2)
+    } else if n.is(&[(0x4b, 0x09), (0x8b, 0x89), (0x1f, 0x19),
+                     (0x3b, 0x19)]) {
+        interp(&[(w[4], 3), (w[1], 1)], 2)
+    } else if n.is(&[(0x7e, 0x2a), (0xef, 0xab), (0xbf, 0x8f),
+                     (0x7e, 0x0e)]) {
+        interp(&[(w[4], 2), (w[3], 3), (w[1], 3)], 3)
+    } else if n.is(&[(0xfb, 0x6a), (0x6f, 0x6e), (0x3f, 0x3e),
+                     (0xfb, 0xfa), (0xdf, 0xde), (0xdf, 0x1e)]) {
+        interp(&[(w[4], 3), (w[0], 1)], 2)
+    } else if n.is(&[(0x0a, 0x00), (0x4f, 0x4b), (0x9f, 0x1b),
+                     (0x2f, 0x0b), (0xbe, 0x0a), (0xee, 0x0a),
+                     (0x7e, 0x0a), (0xeb, 0x4b), (0x3b, 0x1b)]) {
+        interp(&[(w[4], 2), (w[3], 1), (w[1], 1)], 2)
+    } else {
+        interp(&[(w[4], 6), (w[3], 1), (w[1], 1)], 3)
+    }
+}
+
+/// Top left sub-pixel of HQ3x
+fn hq3x_corner(n: &Neighbours) -> Rgb {
+    let w = &n.w;
+
+    if n.is(&[(0xbf, 0x37), (0xdb, 0x13)]) && n.different(1, 5) {
+        interp(&[(w[4], 3), (w[3], 1)], 2)
+    } else if n.is(&[(0xdb, 0x49), (0xef, 0x6d)]) && n.different(7, 3) {
+        interp(&[(w[4], 3), (w[1], 1)], 2)
+    } else if n.is(&[(0x0b, 0x0b), (0xfe, 0x4a), (0xfe, 0x1a)]) &&
+        n.different(3, 1) {
+        w[4]
+    } else if n.is(&[(0x6f, 0x2a), (0x5b, 0x0a), (0xbf, 0x3a),
+                     (0xdf, 0x5a), (0x9f, 0x8a), (0xcf, 0x8a),
+                     (0xef, 0x4e), (0x3f, 0x0e), (0xfb, 0x5a),
+                     (0xbb, 0x8a), (0x7f, 0x5a), (0xaf, 0x8a),
+                     (0xeb, 0x8a)]) &&
+        n.different(3, 1) {
+        interp(&[(w[4], 3), (w[0], 1)], 2)
+    } else if n.is(&[(0x4b, 0x09), (0x8b, 0x89), (0x1f, 0x19),
+                     (0x3b, 0x19)]) {
+        interp(&[(w[4], 3), (w[1], 1)], 2)
+    } else if n.is(&[(0x1b, 0x03), (0x4f, 0x43), (0x8b, 0x83),
+                     (0x6b, 0x43)]) {
+        interp(&[(w[4], 3), (w[3], 1)], 2)
+    } else if n.is(&[(0x7e, 0x2a), (0xef, 0xab), (0xbf, 0x8f),
+                     (0x7e, 0x0e)]) {
+        interp(&[(w[3], 1), (w[1], 1)], 1)
+    } else if n.is(&[(0x4f, 0x4b), (0x9f, 0x1b), (0x2f, 0x0b),
+                     (0xbe, 0x0a), (0xee, 0x0a), (0x7e, 0x0a),
+                     (0xeb, 0x4b), (0x3b, 0x1b)]) {
+        interp(&[(w[4], 2), (w[3], 7), (w[1], 7)], 4)
+    } else if n.is(&[(0x0b, 0x08), (0xf9, 0x68), (0xf3, 0x62),
+                     (0x6d, 0x6c), (0x67, 0x66), (0x3d, 0x3c),
+                     (0x37, 0x36), (0xf9, 0xf8), (0xdd, 0xdc),
+                     (0xf3, 0xf2), (0xd7, 0xd6), (0xdd, 0x1c),
+                     (0xd7, 0x16), (0x0b, 0x02)]) {
+        interp(&[(w[4], 3), (w[0], 1)], 2)
+    } else {
+        interp(&[(w[4], 2), (w[3], 1), (w[1], 1)], 2)
+    }
+}
+
+/// Top middle sub-pixel of HQ3x
+fn hq3x_edge(n: &Neighbours) -> Rgb {
+    let w = &n.w;
+
+    if n.is(&[(0xfe, 0xde), (0x9e, 0x16), (0xda, 0x12), (0x17, 0x16),
+              (0x5b, 0x12), (0xbb, 0x12)]) &&
+        n.different(1, 5) {
+        w[4]
+    } else if n.is(&[(0x0f, 0x0b), (0x5e, 0x0a), (0xfb, 0x7b),
+                     (0x3b, 0x0b), (0xbe, 0x0a), (0x7a, 0x0a)]) &&
+        n.different(3, 1) {
+        w[4]
+    } else if n.is(&[(0xbf, 0x8f), (0x7e, 0x0e), (0xbf, 0x37),
+                     (0xdb, 0x13)]) {
+        interp(&[(w[1], 3), (w[4], 1)], 2)
+    } else if n.is(&[(0x02, 0x00), (0x7c, 0x28), (0xed, 0xa9),
+                     (0xf5, 0xb4), (0xd9, 0x90)]) {
+        interp(&[(w[4], 3), (w[1], 1)], 2)
+    } else if n.is(&[(0x4f, 0x4b), (0xfb, 0x7b), (0xfe, 0x7e),
+                     (0x9f, 0x1b), (0x2f, 0x0b), (0xbe, 0x0a),
+                     (0x7e, 0x0a), (0xfb, 0x4b), (0xfb, 0xdb),
+                     (0xfe, 0xde), (0xfe, 0x56), (0x57, 0x56),
+                     (0x97, 0x16), (0x3f, 0x1e), (0xdb, 0x12),
+                     (0xbb, 0x12)]) {
+        interp(&[(w[4], 7), (w[1], 1)], 3)
+    } else {
+        w[4]
+    }
+}
+
+/// Weighted sum of `colors` divided by `1 << shift`, the weights must
+/// add up to that. Like the reference implementation the result is
+/// truncated.
+fn interp(colors: &[(Rgb, u32)], shift: u32) -> Rgb {
+    let mut out = [0; 3];
+
+    for i in 0..3 {
+        let sum = colors.iter().fold(0, |s, &(c, w)| s + c[i] as u32 * w);
+
+        out[i] = (sum >> shift) as u8;
+    }
+
+    out
+}
+
+/// Compare two colors using the hqx YUV thresholds
+fn different(a: Rgb, b: Rgb) -> bool {
+    if a == b {
+        return false;
+    }
+
+    let (ya, ua, va) = yuv(a);
+    let (yb, ub, vb) = yuv(b);
+
+    (ya - yb).abs() > 48 || (ua - ub).abs() > 7 || (va - vb).abs() > 6
+}
+
+fn yuv(c: Rgb) -> (i32, i32, i32) {
+    let (r, g, b) = (c[0] as i32, c[1] as i32, c[2] as i32);
+
+    let y = (299 * r + 587 * g + 114 * b) / 1000;
+    let u = (-169 * r - 331 * g + 500 * b) / 1000 + 128;
+    let v = (500 * r - 419 * g - 81 * b) / 1000 + 128;
+
+    (y, u, v)
+}
@@ -0,0 +1,47 @@
+//! Dot matrix LCD effect: each pixel is drawn as a 3x3 block with
+//! darker borders to mimic the grid between the LCD cells.
+
+use ui::palette::Rgb;
+use ui::filter::{Filter, Source, Target, mix};
+
+pub struct LcdGrid;
+
+impl Filter for LcdGrid {
+    fn name(&self) -> &'static str {
+        "lcd"
+    }
+
+    fn scale(&self) -> u32 {
+        3
+    }
+
+    fn apply(&self, src: &[Rgb], width: u32, height: u32) -> Vec<Rgb> {
+        let src = Source::new(src, width, height);
+        let mut out = Target::new(width * 3, height * 3);
+
+        for y in 0..height {
+            for x in 0..width {
+                let c = src.get(x as i32, y as i32);
+
+                let edge   = mix(&[(c, 3), ([0; 3], 1)]);
+                let corner = mix(&[(c, 1), ([0; 3], 1)]);
+
+                for by in 0..3 {
+                    for bx in 0..3 {
+                        // The gap between the cells is along the
+                        // right and bottom edges of each block
+                        let col = match (bx == 2, by == 2) {
+                            (false, false) => c,
+                            (true,  true)  => corner,
+                            _              => edge,
+                        };
+
+                        out.set(x * 3 + bx, y * 3 + by, col);
+                    }
+                }
+            }
+        }
+
+        out.pixels
+    }
+}
@@ -0,0 +1,257 @@
+//! Software upscaling filters applied to the full frame before it's
+//! displayed. They run on the CPU so they work with any SDL renderer.
+
+use ui::palette::Rgb;
+
+mod scalenx;
+mod hqx;
+mod xbrz;
+mod lcd;
+
+/// Post-processing filter
+pub trait Filter {
+    /// Name used to select the filter
+    fn name(&self) -> &'static str;
+
+    /// Upscaling factor of the filter
+    fn scale(&self) -> u32;
+
+    /// Filter the `width`x`height` image `src`. Returns an image
+    /// `scale()` times larger in both dimensions.
+    fn apply(&self, src: &[Rgb], width: u32, height: u32) -> Vec<Rgb>;
+}
+
+/// Plain nearest neighbour (i.e. no filtering)
+pub struct Nearest;
+
+impl Filter for Nearest {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn scale(&self) -> u32 {
+        1
+    }
+
+    fn apply(&self, src: &[Rgb], _: u32, _: u32) -> Vec<Rgb> {
+        src.to_vec()
+    }
+}
+
+/// Return all the available filters, starting with `Nearest`
+pub fn filters() -> Vec<Box<Filter>> {
+    vec![
+        Box::new(Nearest),
+        Box::new(scalenx::Scale2x),
+        Box::new(scalenx::Scale3x),
+        Box::new(hqx::Hqx::new(2)),
+        Box::new(hqx::Hqx::new(3)),
+        Box::new(xbrz::Xbrz::new(2)),
+        Box::new(xbrz::Xbrz::new(3)),
+        Box::new(lcd::LcdGrid),
+        ]
+}
+
+/// Return the index of the filter called `name` in `filters()`
+pub fn find(name: &str) -> Option<usize> {
+    filters().iter().position(|f| f.name() == name)
+}
+
+/// Accessor for the source image. Coordinates outside of the image
+/// are clamped to the nearest edge.
+struct Source<'a> {
+    pixels: &'a [Rgb],
+    width:  i32,
+    height: i32,
+}
+
+impl<'a> Source<'a> {
+    fn new(pixels: &'a [Rgb], width: u32, height: u32) -> Source<'a> {
+        assert!(pixels.len() == (width * height) as usize);
+
+        Source {
+            pixels: pixels,
+            width:  width as i32,
+            height: height as i32,
+        }
+    }
+
+    fn get(&self, x: i32, y: i32) -> Rgb {
+        let x = ::std::cmp::min(::std::cmp::max(x, 0), self.width - 1);
+        let y = ::std::cmp::min(::std::cmp::max(y, 0), self.height - 1);
+
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+/// Output image
+struct Target {
+    pixels: Vec<Rgb>,
+    width:  u32,
+}
+
+impl Target {
+    fn new(width: u32, height: u32) -> Target {
+        Target {
+            pixels: vec![[0; 3]; (width * height) as usize],
+            width:  width,
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, c: Rgb) {
+        self.pixels[(y * self.width + x) as usize] = c;
+    }
+}
+
+/// Weighted average of `colors`, each with its weight
+fn mix(colors: &[(Rgb, u32)]) -> Rgb {
+    let total = colors.iter().fold(0, |t, &(_, w)| t + w);
+
+    let mut out = [0; 3];
+
+    for i in 0..3 {
+        let sum = colors.iter().fold(0, |s, &(c, w)| s + c[i] as u32 * w);
+
+        // Round to nearest
+        out[i] = ((sum + total / 2) / total) as u8;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use ui::palette::Rgb;
+
+    /// All filters must produce an image of the right size and leave
+    /// flat areas untouched
+    #[test]
+    fn flat_image() {
+        let c: Rgb = [0x12, 0x34, 0x56];
+        let src = vec![c; 10 * 7];
+
+        for f in super::filters() {
+            let s = f.scale();
+            let out = f.apply(&src, 10, 7);
+
+            assert!(out.len() == (10 * s * 7 * s) as usize);
+
+            if f.name() != "lcd" {
+                assert!(out.iter().all(|&p| p == c), "{}", f.name());
+            }
+        }
+    }
+
+    const W: Rgb = [0xff, 0xff, 0xff];
+    const K: Rgb = [0x00, 0x00, 0x00];
+
+    /// 3x3 image with a diagonal edge cutting through the top left
+    /// corner of the middle pixel:
+    ///
+    /// ```text
+    /// W W W
+    /// W K K
+    /// W K K
+    /// ```
+    fn diagonal() -> Vec<Rgb> {
+        vec![W, W, W,
+             W, K, K,
+             W, K, K]
+    }
+
+    /// Run the filter `name` on `diagonal()` and return the output
+    /// block of the middle pixel
+    fn middle_block(name: &str) -> Vec<Rgb> {
+        let filters = super::filters();
+        let f = &filters[super::find(name).unwrap()];
+        let s = f.scale();
+
+        let out = f.apply(&diagonal(), 3, 3);
+
+        let mut block = Vec::new();
+
+        for y in s..s * 2 {
+            for x in s..s * 2 {
+                block.push(out[(y * s * 3 + x) as usize]);
+            }
+        }
+
+        block
+    }
+
+    #[test]
+    fn scale2x_diagonal() {
+        // B == D so the top left sub-pixel takes their color, the
+        // others keep E's
+        assert!(middle_block("scale2x") == vec![W, K,
+                                                K, K]);
+    }
+
+    #[test]
+    fn scale3x_diagonal() {
+        // The corner takes B/D's color, and since A, C and G differ
+        // from E the edge also extends to the two adjacent sides
+        assert!(middle_block("scale3x") == vec![W, W, K,
+                                                W, K, K,
+                                                K, K, K]);
+    }
+
+    #[test]
+    fn hq2x_diagonal() {
+        // The cut corner is mostly E with a bit of B and D, the
+        // others keep E's color
+        assert!(middle_block("hq2x") == vec![[0x1f; 3], K,
+                                             K,         K]);
+    }
+
+    #[test]
+    fn hq3x_diagonal() {
+        // The corner is blended more strongly than with HQ2x, the
+        // sides along the edge stay sharp
+        let block = middle_block("hq3x");
+
+        assert!(block[0] == [0x7f; 3]);
+        assert!(block[1..].iter().all(|&p| p == K));
+    }
+
+    #[test]
+    fn xbrz_diagonal() {
+        // The top left corner is blended towards W along the edge,
+        // the bottom right one is inside the flat area
+        for &name in &["xbrz2x", "xbrz3x"] {
+            let block = middle_block(name);
+            let last = block.len() - 1;
+
+            assert!(block[0] != K, "{}", name);
+            assert!(block[last] == K, "{}", name);
+        }
+    }
+
+    #[test]
+    fn lcd_cell() {
+        let block = middle_block("lcd");
+
+        let edge = super::mix(&[(K, 3), ([0; 3], 1)]);
+
+        assert!(block[0] == K && block[4] == K);
+        assert!(block[2] == edge && block[6] == edge);
+
+        // The grid between the cells doesn't depend on the neighbours
+        let filters = super::filters();
+        let f = &filters[super::find("lcd").unwrap()];
+        let out = f.apply(&diagonal(), 3, 3);
+
+        let white_edge = super::mix(&[(W, 3), ([0; 3], 1)]);
+
+        assert!(out[0] == W && out[2] == white_edge);
+    }
+
+    #[test]
+    fn find() {
+        assert!(super::find("none") == Some(0));
+        assert!(super::find("scale2x").is_some());
+        assert!(super::find("xbrz3x").is_some());
+        assert!(super::find("hq2x").is_some());
+        assert!(super::find("bogus").is_none());
+    }
+}
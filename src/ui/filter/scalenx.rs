@@ -0,0 +1,108 @@
+//! Scale2x and Scale3x (also known as AdvMAME2x/3x) pixel art
+//! scalers. They only ever copy source colors, without any blending.
+
+use ui::palette::Rgb;
+use ui::filter::{Filter, Source, Target};
+
+pub struct Scale2x;
+
+impl Filter for Scale2x {
+    fn name(&self) -> &'static str {
+        "scale2x"
+    }
+
+    fn scale(&self) -> u32 {
+        2
+    }
+
+    fn apply(&self, src: &[Rgb], width: u32, height: u32) -> Vec<Rgb> {
+        let src = Source::new(src, width, height);
+        let mut out = Target::new(width * 2, height * 2);
+
+        for y in 0..height {
+            for x in 0..width {
+                //   B
+                // D E F
+                //   H
+                let (xi, yi) = (x as i32, y as i32);
+
+                let b = src.get(xi,     yi - 1);
+                let d = src.get(xi - 1, yi);
+                let e = src.get(xi,     yi);
+                let f = src.get(xi + 1, yi);
+                let h = src.get(xi,     yi + 1);
+
+                let mut o = [e; 4];
+
+                if b != h && d != f {
+                    if d == b { o[0] = d; }
+                    if b == f { o[1] = f; }
+                    if d == h { o[2] = d; }
+                    if h == f { o[3] = f; }
+                }
+
+                out.set(x * 2,     y * 2,     o[0]);
+                out.set(x * 2 + 1, y * 2,     o[1]);
+                out.set(x * 2,     y * 2 + 1, o[2]);
+                out.set(x * 2 + 1, y * 2 + 1, o[3]);
+            }
+        }
+
+        out.pixels
+    }
+}
+
+pub struct Scale3x;
+
+impl Filter for Scale3x {
+    fn name(&self) -> &'static str {
+        "scale3x"
+    }
+
+    fn scale(&self) -> u32 {
+        3
+    }
+
+    fn apply(&self, src: &[Rgb], width: u32, height: u32) -> Vec<Rgb> {
+        let src = Source::new(src, width, height);
+        let mut out = Target::new(width * 3, height * 3);
+
+        for y in 0..height {
+            for x in 0..width {
+                // A B C
+                // D E F
+                // G H I
+                let (xi, yi) = (x as i32, y as i32);
+
+                let a = src.get(xi - 1, yi - 1);
+                let b = src.get(xi,     yi - 1);
+                let c = src.get(xi + 1, yi - 1);
+                let d = src.get(xi - 1, yi);
+                let e = src.get(xi,     yi);
+                let f = src.get(xi + 1, yi);
+                let g = src.get(xi - 1, yi + 1);
+                let h = src.get(xi,     yi + 1);
+                let i = src.get(xi + 1, yi + 1);
+
+                let mut o = [e; 9];
+
+                if b != h && d != f {
+                    if d == b { o[0] = d; }
+                    if (d == b && e != c) || (b == f && e != a) { o[1] = b; }
+                    if b == f { o[2] = f; }
+                    if (d == b && e != g) || (d == h && e != a) { o[3] = d; }
+                    if (b == f && e != i) || (h == f && e != c) { o[5] = f; }
+                    if d == h { o[6] = d; }
+                    if (d == h && e != i) || (h == f && e != g) { o[7] = h; }
+                    if h == f { o[8] = f; }
+                }
+
+                for (n, &c) in o.iter().enumerate() {
+                    out.set(x * 3 + n as u32 % 3, y * 3 + n as u32 / 3, c);
+                }
+            }
+        }
+
+        out.pixels
+    }
+}
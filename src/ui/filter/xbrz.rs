@@ -0,0 +1,375 @@
+//! xBRZ scaler by Zenju, for the 2x and 3x factors.
+//!
+//! The corners between each group of 2x2 pixels are first classified
+//! depending on the direction of the color gradient around them, then
+//! each pixel is upscaled and the corners that need it are blended
+//! along the detected edges. All the rules are written for the bottom
+//! right corner and applied to the others by rotating the kernel.
+
+use ui::palette::Rgb;
+use ui::filter::{Filter, Source, Target};
+
+pub struct Xbrz {
+    scale: u32,
+}
+
+impl Xbrz {
+    /// Create a new xBRZ filter, `scale` must be 2 or 3
+    pub fn new(scale: u32) -> Xbrz {
+        assert!(scale == 2 || scale == 3);
+
+        Xbrz {
+            scale: scale,
+        }
+    }
+}
+
+impl Filter for Xbrz {
+    fn name(&self) -> &'static str {
+        match self.scale {
+            2 => "xbrz2x",
+            _ => "xbrz3x",
+        }
+    }
+
+    fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    fn apply(&self, src: &[Rgb], width: u32, height: u32) -> Vec<Rgb> {
+        let src = Source::new(src, width, height);
+        let s = self.scale as usize;
+        let mut out = Target::new(width * self.scale, height * self.scale);
+
+        let blends = corner_blends(&src, width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (xi, yi) = (x as i32, y as i32);
+
+                let mut ker = [[0; 3]; 9];
+
+                for i in 0..9 {
+                    ker[i] = src.get(xi + (i as i32 % 3) - 1, yi + (i as i32 / 3) - 1);
+                }
+
+                let mut block = Block {
+                    pixels: [ker[4]; 9],
+                    size:   s,
+                };
+
+                let blend = blends[(y * width + x) as usize];
+
+                if blend != 0 {
+                    for rot in 0..4 {
+                        blend_pixel(&ker, &mut block, blend, rot);
+                    }
+                }
+
+                for by in 0..s {
+                    for bx in 0..s {
+                        out.set(x * self.scale + bx as u32,
+                                y * self.scale + by as u32,
+                                block.pixels[by * s + bx]);
+                    }
+                }
+            }
+        }
+
+        out.pixels
+    }
+}
+
+/// Output block of `size`x`size` pixels for a single source pixel
+struct Block {
+    pixels: [Rgb; 9],
+    size:   usize,
+}
+
+impl Block {
+    /// Blend the pixel at (`row`, `col`) of the block rotated `rot`
+    /// times clockwise towards `col` by `m`/`n`
+    fn grad(&mut self, rot: u32, row: usize, col: usize, c: Rgb, m: u32, n: u32) {
+        let (r, c0) = rotate(row, col, self.size, rot);
+
+        let p = &mut self.pixels[r * self.size + c0];
+
+        for i in 0..3 {
+            p[i] = ((c[i] as u32 * m + p[i] as u32 * (n - m)) / n) as u8;
+        }
+    }
+
+    fn set(&mut self, rot: u32, row: usize, col: usize, c: Rgb) {
+        let (r, c0) = rotate(row, col, self.size, rot);
+
+        self.pixels[r * self.size + c0] = c;
+    }
+}
+
+/// Return the coordinates in the original `n`x`n` matrix of the cell
+/// (`row`, `col`) of the matrix rotated `rot` times clockwise
+fn rotate(row: usize, col: usize, n: usize, rot: u32) -> (usize, usize) {
+    let (mut r, mut c) = (row, col);
+
+    for _ in 0..rot {
+        let t = r;
+
+        r = n - 1 - c;
+        c = t;
+    }
+
+    (r, c)
+}
+
+/// Blending applied to a pixel's corner
+const BLEND_NONE:     u8 = 0;
+const BLEND_NORMAL:   u8 = 1;
+const BLEND_DOMINANT: u8 = 2;
+
+/// The blending of the four corners of a pixel is packed in a byte:
+/// top left in bits [1:0], top right in [3:2], bottom right in [5:4]
+/// and bottom left in [7:6].
+fn top_r(b: u8) -> u8 { (b >> 2) & 3 }
+fn bottom_r(b: u8) -> u8 { (b >> 4) & 3 }
+fn bottom_l(b: u8) -> u8 { (b >> 6) & 3 }
+
+/// Rotate the blend info `rot` times clockwise
+fn rotate_blend(b: u8, rot: u32) -> u8 {
+    if rot == 0 {
+        b
+    } else {
+        let shift = rot * 2;
+
+        ((b as u32) << shift | (b as u32) >> (8 - shift)) as u8
+    }
+}
+
+/// Compute the blending of all the pixel corners
+fn corner_blends(src: &Source, width: u32, height: u32) -> Vec<u8> {
+    let (w, h) = (width as i32, height as i32);
+
+    let mut blends = vec![0u8; (width * height) as usize];
+
+    {
+        let mut add = |x: i32, y: i32, blend: u8, shift: u8| {
+            if x >= 0 && x < w && y >= 0 && y < h {
+                blends[(y * w + x) as usize] |= blend << shift;
+            }
+        };
+
+        // Each corner is shared between the 2x2 pixels F, G, J and K
+        // with F at (x, y)
+        for y in -1..h {
+            for x in -1..w {
+                let mut ker = [[0; 3]; 16];
+
+                for i in 0..16 {
+                    ker[i] = src.get(x + (i as i32 % 4) - 1, y + (i as i32 / 4) - 1);
+                }
+
+                let (f, g, j, k) = preprocess_corner(&ker);
+
+                add(x,     y,     f, 4);
+                add(x + 1, y,     g, 6);
+                add(x,     y + 1, j, 2);
+                add(x + 1, y + 1, k, 0);
+            }
+        }
+    }
+
+    blends
+}
+
+/// Classify the corner between F, G, J and K in the 4x4 kernel:
+///
+/// ```text
+/// A B C D
+/// E F G H
+/// I J K L
+/// M N O P
+/// ```
+///
+/// Returns the blending for F, G, J and K.
+fn preprocess_corner(ker: &[Rgb; 16]) -> (u8, u8, u8, u8) {
+    let (b, c) = (ker[1], ker[2]);
+    let (e, f, g, h) = (ker[4], ker[5], ker[6], ker[7]);
+    let (i, j, k, l) = (ker[8], ker[9], ker[10], ker[11]);
+    let (n, o) = (ker[13], ker[14]);
+
+    let mut res = (BLEND_NONE, BLEND_NONE, BLEND_NONE, BLEND_NONE);
+
+    if (f == g && j == k) || (f == j && g == k) {
+        return res;
+    }
+
+    let jg = dist(i, f) + dist(f, c) + dist(n, k) + dist(k, h) +
+        CENTER_DIRECTION_BIAS * dist(j, g);
+    let fk = dist(e, j) + dist(j, o) + dist(b, g) + dist(g, l) +
+        CENTER_DIRECTION_BIAS * dist(f, k);
+
+    if jg < fk {
+        let blend =
+            if DOMINANT_DIRECTION_THRESHOLD * jg < fk {
+                BLEND_DOMINANT
+            } else {
+                BLEND_NORMAL
+            };
+
+        if f != g && f != j {
+            res.0 = blend;
+        }
+
+        if k != j && k != g {
+            res.3 = blend;
+        }
+    } else if fk < jg {
+        let blend =
+            if DOMINANT_DIRECTION_THRESHOLD * fk < jg {
+                BLEND_DOMINANT
+            } else {
+                BLEND_NORMAL
+            };
+
+        if j != f && j != k {
+            res.2 = blend;
+        }
+
+        if g != f && g != k {
+            res.1 = blend;
+        }
+    }
+
+    res
+}
+
+/// Blend the bottom right corner of the pixel in the kernel rotated
+/// `rot` times clockwise:
+///
+/// ```text
+/// A B C
+/// D E F
+/// G H I
+/// ```
+fn blend_pixel(ker: &[Rgb; 9], out: &mut Block, blend: u8, rot: u32) {
+    let blend = rotate_blend(blend, rot);
+
+    if bottom_r(blend) < BLEND_NORMAL {
+        return;
+    }
+
+    let get = |row: usize, col: usize| {
+        let (r, c) = rotate(row, col, 3, rot);
+
+        ker[r * 3 + c]
+    };
+
+    let (b, c) = (get(0, 1), get(0, 2));
+    let (d, e, f) = (get(1, 0), get(1, 1), get(1, 2));
+    let (g, h, i) = (get(2, 0), get(2, 1), get(2, 2));
+
+    let eq = |x: Rgb, y: Rgb| dist(x, y) < EQUAL_COLOR_TOLERANCE;
+
+    let line_blend =
+        if bottom_r(blend) >= BLEND_DOMINANT {
+            true
+        } else if top_r(blend) != BLEND_NONE && !eq(e, g) {
+            // Don't blend if there's already a blending in an adjacent
+            // corner (isolated pixels)
+            false
+        } else if bottom_l(blend) != BLEND_NONE && !eq(e, c) {
+            false
+        } else if !eq(e, i) && eq(g, h) && eq(h, i) && eq(i, f) && eq(f, c) {
+            // No full blending for L-shapes, only blend the corner
+            false
+        } else {
+            true
+        };
+
+    // Blend with the most similar neighbour
+    let px = if dist(e, f) <= dist(e, h) { f } else { h };
+
+    let n = out.size;
+
+    if !line_blend {
+        // Corner only
+        if n == 2 {
+            out.grad(rot, 1, 1, px, 21, 100);
+        } else {
+            out.grad(rot, 2, 2, px, 45, 100);
+        }
+
+        return;
+    }
+
+    let fg = dist(f, g);
+    let hc = dist(h, c);
+
+    let shallow = STEEP_DIRECTION_THRESHOLD * fg <= hc && e != g && d != g;
+    let steep   = STEEP_DIRECTION_THRESHOLD * hc <= fg && e != c && b != c;
+
+    match (shallow, steep, n) {
+        (true, true, 2) => {
+            out.grad(rot, 1, 0, px, 1, 4);
+            out.grad(rot, 0, 1, px, 1, 4);
+            out.grad(rot, 1, 1, px, 5, 6);
+        }
+        (true, true, _) => {
+            out.grad(rot, 2, 0, px, 1, 4);
+            out.grad(rot, 0, 2, px, 1, 4);
+            out.grad(rot, 2, 1, px, 3, 4);
+            out.grad(rot, 1, 2, px, 3, 4);
+            out.set(rot, 2, 2, px);
+        }
+        (true, false, 2) => {
+            out.grad(rot, 1, 0, px, 1, 4);
+            out.grad(rot, 1, 1, px, 3, 4);
+        }
+        (true, false, _) => {
+            out.grad(rot, 2, 0, px, 1, 4);
+            out.grad(rot, 1, 2, px, 1, 4);
+            out.grad(rot, 2, 1, px, 3, 4);
+            out.set(rot, 2, 2, px);
+        }
+        (false, true, 2) => {
+            out.grad(rot, 0, 1, px, 1, 4);
+            out.grad(rot, 1, 1, px, 3, 4);
+        }
+        (false, true, _) => {
+            out.grad(rot, 0, 2, px, 1, 4);
+            out.grad(rot, 2, 1, px, 1, 4);
+            out.grad(rot, 1, 2, px, 3, 4);
+            out.set(rot, 2, 2, px);
+        }
+        (false, false, 2) => {
+            out.grad(rot, 1, 1, px, 1, 2);
+        }
+        (false, false, _) => {
+            out.grad(rot, 1, 2, px, 1, 8);
+            out.grad(rot, 2, 1, px, 1, 8);
+            out.grad(rot, 2, 2, px, 7, 8);
+        }
+    }
+}
+
+/// Perceptual distance between two colors in YCbCr space
+fn dist(a: Rgb, b: Rgb) -> f64 {
+    let r = a[0] as f64 - b[0] as f64;
+    let g = a[1] as f64 - b[1] as f64;
+    let b = a[2] as f64 - b[2] as f64;
+
+    // ITU-R BT.2020 conversion
+    let k_b = 0.0593;
+    let k_r = 0.2627;
+    let k_g = 1. - k_b - k_r;
+
+    let y  = k_r * r + k_g * g + k_b * b;
+    let cb = 0.5 / (1. - k_b) * (b - y);
+    let cr = 0.5 / (1. - k_r) * (r - y);
+
+    (y * y + cb * cb + cr * cr).sqrt()
+}
+
+const EQUAL_COLOR_TOLERANCE:        f64 = 30.;
+const CENTER_DIRECTION_BIAS:        f64 = 4.;
+const DOMINANT_DIRECTION_THRESHOLD: f64 = 3.6;
+const STEEP_DIRECTION_THRESHOLD:    f64 = 2.2;
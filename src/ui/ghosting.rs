@@ -0,0 +1,145 @@
+//! LCD ghosting emulation. The DMG's LCD is slow to react so pixels
+//! fade in and out over several frames, some games rely on it to
+//! make flickering sprites look transparent. Each frame is mixed
+//! with the previous ones following a response curve.
+
+use std::collections::VecDeque;
+
+use ui::palette::Rgb;
+
+#[derive(Clone)]
+pub struct Ghosting {
+    /// Weight of the current frame followed by the weights of the
+    /// previous ones, most recent first
+    curve:   Vec<u32>,
+    /// Previous frames (before blending), most recent first
+    history: VecDeque<Vec<Rgb>>,
+}
+
+impl Ghosting {
+    /// Create a new blending stage using the response `curve`,
+    /// validated by `parse`
+    fn new(curve: Vec<u32>) -> Ghosting {
+        Ghosting {
+            curve:   curve,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// No blending at all
+    pub fn off() -> Ghosting {
+        Ghosting::new(vec![1])
+    }
+
+    /// Parse a response curve, either one of the presets (`off`,
+    /// `mix` or `dmg`) or a list of comma-separated weights starting
+    /// with the current frame.
+    pub fn parse(s: &str) -> Result<Ghosting, String> {
+        let curve =
+            match s {
+                "off" => vec![1],
+                // Even mix of the last two frames
+                "mix" => vec![1, 1],
+                // Rough approximation of the original LCD
+                "dmg" => vec![3, 2, 1],
+                _ => {
+                    let mut curve = Vec::new();
+
+                    for w in s.split(',') {
+                        match w.trim().parse() {
+                            Ok(w)  => curve.push(w),
+                            Err(_) => return Err(format!("Invalid weight \"{}\"", w)),
+                        }
+                    }
+
+                    curve
+                }
+            };
+
+        if curve.iter().all(|&w| w == 0) {
+            return Err(format!("Invalid response curve \"{}\"", s));
+        }
+
+        Ok(Ghosting::new(curve))
+    }
+
+    /// Return `true` if the frames are actually blended
+    pub fn is_enabled(&self) -> bool {
+        self.curve[1..].iter().any(|&w| w != 0)
+    }
+
+    /// Blend `pixels` with the previous frames in place. The frame
+    /// is then added to the history.
+    pub fn apply(&mut self, pixels: &mut [Rgb]) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let current = pixels.to_vec();
+
+        {
+            // Frames older than the history are not available when
+            // we've just started, we only use what we have.
+            let frames: Vec<(&[Rgb], u32)> =
+                Some(&current[..]).into_iter()
+                .chain(self.history.iter().map(|f| &f[..]))
+                .zip(self.curve.iter().cloned())
+                .filter(|&(f, _)| f.len() == pixels.len())
+                .collect();
+
+            let total = frames.iter().fold(0, |t, &(_, w)| t + w);
+
+            if total != 0 {
+                for (i, p) in pixels.iter_mut().enumerate() {
+                    for c in 0..3 {
+                        let sum = frames.iter()
+                            .fold(0, |s, &(f, w)| s + f[i][c] as u32 * w);
+
+                        // Round to nearest
+                        p[c] = ((sum + total / 2) / total) as u8;
+                    }
+                }
+            }
+        }
+
+        self.history.push_front(current);
+        self.history.truncate(self.curve.len() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ghosting;
+
+    #[test]
+    fn blend() {
+        let mut g = Ghosting::parse("mix").unwrap();
+
+        let mut a = vec![[0, 0, 0], [200, 100, 50]];
+        let mut b = vec![[100, 100, 100], [0, 100, 250]];
+
+        // Nothing to mix the first frame with
+        g.apply(&mut a);
+        assert!(a == vec![[0, 0, 0], [200, 100, 50]]);
+
+        g.apply(&mut b);
+        assert!(b == vec![[50, 50, 50], [100, 100, 150]]);
+
+        // The history holds the original frames, not the blended ones
+        let mut c = vec![[100, 100, 100], [0, 100, 250]];
+
+        g.apply(&mut c);
+        assert!(c == vec![[100, 100, 100], [0, 100, 250]]);
+    }
+
+    #[test]
+    fn parse() {
+        assert!(!Ghosting::parse("off").unwrap().is_enabled());
+        assert!(!Ghosting::parse("4,0").unwrap().is_enabled());
+        assert!(Ghosting::parse("dmg").unwrap().is_enabled());
+        assert!(Ghosting::parse(" 2, 1").unwrap().curve == vec![2, 1]);
+        assert!(Ghosting::parse("0,0").is_err());
+        assert!(Ghosting::parse("2,x").is_err());
+        assert!(Ghosting::parse("").is_err());
+    }
+}
@@ -2,15 +2,28 @@
 //! input etc...
 
 pub mod sdl2;
+pub mod palette;
+pub mod viewer;
+pub mod filter;
+pub mod ghosting;
+pub mod scaling;
+pub mod bindings;
 
 /// GB screen. Screen resolution is always 160x144
 pub trait Display {
-    /// Clear the display
-    fn clear(&mut self);
-    /// Paint pixel at (x, y) using `color`. (0, 0) is top left.
-    fn set_pixel(&mut self, x: u32, y: u32, color: ::gpu::Color);
-    /// Current frame is done and can be displayed.
-    fn flip(&mut self);
+    /// Current `frame` is done and can be displayed.
+    fn flip(&mut self, frame: &::gpu::Frame);
+
+    /// Return `true` if the display wants a copy of the video memory
+    /// at each VBlank (for the debugging views).
+    fn wants_video_memory(&self) -> bool {
+        false
+    }
+
+    /// Called at each VBlank after `flip` if `wants_video_memory`
+    /// returned `true`.
+    fn video_memory(&mut self, _mem: &::gpu::VideoMemory) {
+    }
 }
 
 /// Audio interface
@@ -23,11 +36,12 @@ pub trait Audio {
 
 /// Special events that need to be handled synchronously (instead of
 /// waiting for the GB program to come check the INPUT register)
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
 pub enum Event {
-    /// No event
-    None,
     /// Shutdown the emulator
     PowerOff,
+    /// Toggle the display of a rendering layer for debugging
+    ToggleLayer(::gpu::Layer),
 }
 
 /// Description of a button's state
@@ -80,23 +94,36 @@ impl Buttons {
     }
 }
 
-#[cfg(test)]
+/// Tilt of the console measured by the accelerometer of some
+/// cartridges. Each axis goes from -1.0 to 1.0, `x` is positive when
+/// the right side of the console is lower and `y` when the bottom is
+/// lower.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Tilt {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Tilt {
+    /// Console lying flat
+    pub fn level() -> Tilt {
+        Tilt {
+            x: 0.,
+            y: 0.,
+        }
+    }
+}
+
 pub mod dummy {
-    //! Dummy implementations of the user interface for use in tests
-    //! and benchmarks
+    //! Dummy implementations of the user interface for the headless
+    //! mode, tests and benchmarks
 
     use std::cell::Cell;
 
     pub struct DummyDisplay;
 
     impl super::Display for DummyDisplay {
-        fn clear(&mut self) {
-        }
-
-        fn set_pixel(&mut self, _: u32, _: u32, _: ::gpu::Color) {
-        }
-
-        fn flip(&mut self) {
+        fn flip(&mut self, _: &::gpu::Frame) {
         }
     }
 
@@ -0,0 +1,308 @@
+//! Color palettes used to turn the Game Boy shades of grey into RGB
+//! colors.
+//!
+//! Each palette defines separate colors for the background and the
+//! two sprite palettes, like the Game Boy Color does when running
+//! original Game Boy games.
+
+use gpu::{Frame, Color, Source, SCREEN_WIDTH, SCREEN_HEIGHT};
+use config::toml::{Table, Value};
+
+/// 8bit per component RGB color
+pub type Rgb = [u8; 3];
+
+/// Colors for the four shades, from white to black
+pub type Shades = [Rgb; 4];
+
+/// Full screen palette
+#[derive(Clone)]
+pub struct Palette {
+    /// Name used to select the palette
+    name: String,
+    /// Background and window colors
+    bg:   Shades,
+    /// Colors for sprites using OBP0
+    obp0: Shades,
+    /// Colors for sprites using OBP1
+    obp1: Shades,
+}
+
+impl Palette {
+    pub fn new(name: &str, bg: Shades, obp0: Shades, obp1: Shades) -> Palette {
+        Palette {
+            name: name.to_string(),
+            bg:   bg,
+            obp0: obp0,
+            obp1: obp1,
+        }
+    }
+
+    /// Create a palette using the same colors for all layers
+    pub fn uniform(name: &str, shades: Shades) -> Palette {
+        Palette::new(name, shades, shades, shades)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the RGB value of `color` for a pixel coming from
+    /// `source`
+    pub fn rgb(&self, color: Color, source: Source) -> Rgb {
+        let shades = match source {
+            Source::Bgp  => &self.bg,
+            Source::Obp0 => &self.obp0,
+            Source::Obp1 => &self.obp1,
+        };
+
+        shades[color as usize]
+    }
+
+    /// Convert the whole `frame` into RGB, in row-major order
+    pub fn render(&self, frame: &Frame) -> Vec<Rgb> {
+        let mut pixels = Vec::with_capacity((SCREEN_WIDTH * SCREEN_HEIGHT) as usize);
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                pixels.push(self.rgb(frame.pixel(x, y), frame.source(x, y)));
+            }
+        }
+
+        pixels
+    }
+}
+
+/// List of available palettes along with the one currently in use
+pub struct Palettes {
+    palettes: Vec<Palette>,
+    /// Index of the current palette in `palettes`
+    current:  usize,
+}
+
+impl Palettes {
+    /// Return the list of built-in palettes. The first one (plain
+    /// grey) is selected.
+    pub fn builtin() -> Palettes {
+        let grey = [ [0xff, 0xff, 0xff], [0xab, 0xab, 0xab],
+                     [0x55, 0x55, 0x55], [0x00, 0x00, 0x00] ];
+        // Original DMG greenish screen
+        let dmg = [ [0x9b, 0xbc, 0x0f], [0x8b, 0xac, 0x0f],
+                    [0x30, 0x62, 0x30], [0x0f, 0x38, 0x0f] ];
+        // Game Boy Pocket's black and white screen
+        let pocket = [ [0xc4, 0xcf, 0xa1], [0x8b, 0x95, 0x6d],
+                       [0x4d, 0x53, 0x3c], [0x1f, 0x1f, 0x1f] ];
+        // Game Boy Light's backlit screen
+        let light = [ [0x00, 0xb5, 0x81], [0x00, 0x9a, 0x71],
+                      [0x00, 0x69, 0x4a], [0x00, 0x4f, 0x3b] ];
+
+        let mut palettes = vec![
+            Palette::uniform("grey",   grey),
+            Palette::uniform("dmg",    dmg),
+            Palette::uniform("pocket", pocket),
+            Palette::uniform("light",  light),
+            ];
+
+        palettes.extend(cgb_palettes().into_iter());
+
+        Palettes {
+            palettes: palettes,
+            current:  0,
+        }
+    }
+
+    /// Load the user-defined palettes from the `[video.palettes]`
+    /// section of the configuration `table`
+    pub fn load_table(&mut self, table: &Table) -> Result<(), String> {
+        let video =
+            match table.get("video") {
+                Some(v) => try!(v.as_table().ok_or("'video' must be a table")),
+                None    => return Ok(()),
+            };
+
+        if let Some(palettes) = video.get("palettes") {
+            let palettes = try!(palettes.as_table()
+                                .ok_or("'palettes' must be a table"));
+
+            for (name, def) in palettes {
+                let palette = try!(parse_palette(name, def));
+
+                // User palettes override built-in ones with the same
+                // name
+                match self.palettes.iter().position(|p| p.name == *name) {
+                    Some(i) => self.palettes[i] = palette,
+                    None    => self.palettes.push(palette),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Select the palette called `name`. Returns `false` if no such
+    /// palette exists.
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.palettes.iter().position(|p| p.name == name) {
+            Some(i) => {
+                self.current = i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switch to the next palette in the list
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.palettes.len();
+    }
+
+    /// Return the palette currently in use
+    pub fn current(&self) -> &Palette {
+        &self.palettes[self.current]
+    }
+}
+
+/// Parse a user palette definition. It either contains a `colors`
+/// array used for all the layers or separate `bg`, `obp0` and `obp1`
+/// arrays. Missing sprite palettes default to the background colors.
+fn parse_palette(name: &str, def: &Value) -> Result<Palette, String> {
+    let def = try!(def.as_table()
+                   .ok_or(format!("palette {} must be a table", name)));
+
+    let shades = |key: &str| -> Result<Option<Shades>, String> {
+        match def.get(key) {
+            Some(v) => parse_shades(v)
+                .map(Some)
+                .map_err(|e| format!("palette {}.{}: {}", name, key, e)),
+            None    => Ok(None),
+        }
+    };
+
+    if let Some(colors) = try!(shades("colors")) {
+        return Ok(Palette::uniform(name, colors));
+    }
+
+    let bg = match try!(shades("bg")) {
+        Some(bg) => bg,
+        None     => return Err(format!("palette {} has no colors", name)),
+    };
+
+    let obp0 = try!(shades("obp0")).unwrap_or(bg);
+    let obp1 = try!(shades("obp1")).unwrap_or(bg);
+
+    Ok(Palette::new(name, bg, obp0, obp1))
+}
+
+/// Parse an array of four colors, from white to black
+fn parse_shades(v: &Value) -> Result<Shades, String> {
+    let colors = match v.as_slice() {
+        Some(c) if c.len() == 4 => c,
+        _ => return Err("expected an array of 4 colors".to_string()),
+    };
+
+    let mut shades = [[0; 3]; 4];
+
+    for (shade, color) in shades.iter_mut().zip(colors.iter()) {
+        *shade = try!(parse_color(color));
+    }
+
+    Ok(shades)
+}
+
+/// Parse a color, either as a "#rrggbb" string or a 0xrrggbb integer
+fn parse_color(v: &Value) -> Result<Rgb, String> {
+    let rgb =
+        match *v {
+            Value::String(ref s) => {
+                let hex = s.trim_left_matches('#');
+
+                match u32::from_str_radix(hex, 16) {
+                    Ok(c) if hex.len() == 6 => c,
+                    _ => return Err(format!("invalid color '{}'", s)),
+                }
+            }
+            Value::Integer(i) if i >= 0 && i <= 0xffffff => i as u32,
+            _ => return Err(format!("invalid color {:?}", v)),
+        };
+
+    Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Palettes the Game Boy Color lets the user choose by holding a
+/// direction (and optionally A or B) while the logo is displayed
+fn cgb_palettes() -> Vec<Palette> {
+    fn shades(c: [u32; 4]) -> Shades {
+        let mut s = [[0; 3]; 4];
+
+        for i in 0..4 {
+            s[i] = [(c[i] >> 16) as u8, (c[i] >> 8) as u8, c[i] as u8];
+        }
+
+        s
+    }
+
+    let brown      = shades([0xffffff, 0xffad63, 0x843100, 0x000000]);
+    let red        = shades([0xffffff, 0xff8484, 0x943a3a, 0x000000]);
+    let green      = shades([0xffffff, 0x7bff31, 0x008400, 0x000000]);
+    let blue       = shades([0xffffff, 0x63a5ff, 0x0000ff, 0x000000]);
+    let dark_brown = shades([0xffe6c5, 0xce9c84, 0x846b29, 0x5a3108]);
+    let dark_blue  = shades([0xffffff, 0x8c8cde, 0x52528c, 0x000000]);
+    let grey       = shades([0xffffff, 0xa5a5a5, 0x525252, 0x000000]);
+    let pastel     = shades([0xffffa5, 0xff9494, 0x9494ff, 0x000000]);
+    let orange     = shades([0xffffff, 0xffff00, 0xff0000, 0x000000]);
+    let yellow     = shades([0xffffff, 0xffff00, 0x7b4a00, 0x000000]);
+    let bright     = shades([0xffffff, 0x52ff00, 0xff4200, 0x000000]);
+    let dark_green = shades([0xffffff, 0x7bff31, 0x0063c5, 0x000000]);
+    let inverted   = shades([0x000000, 0x008484, 0xffde00, 0xffffff]);
+
+    vec![
+        Palette::uniform("cgb-up",       brown),
+        Palette::new    ("cgb-up-a",     red, green, blue),
+        Palette::new    ("cgb-up-b",     dark_brown, brown, brown),
+        Palette::new    ("cgb-left",     blue, red, green),
+        Palette::new    ("cgb-left-a",   dark_blue, red, brown),
+        Palette::uniform("cgb-left-b",   grey),
+        Palette::uniform("cgb-down",     pastel),
+        Palette::uniform("cgb-down-a",   orange),
+        Palette::new    ("cgb-down-b",   yellow, blue, green),
+        Palette::uniform("cgb-right",    bright),
+        Palette::new    ("cgb-right-a",  dark_green, red, red),
+        Palette::uniform("cgb-right-b",  inverted),
+        ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Palettes;
+    use gpu::{Color, Source};
+    use config::toml;
+
+    #[test]
+    fn user_palettes() {
+        let mut palettes = Palettes::builtin();
+
+        let config = toml::parse(
+            "[video.palettes.mine]\n\
+             bg   = [\"#ffffff\", \"#aaaaaa\", \"#555555\", \"#000000\"]\n\
+             obp1 = [0xff0000, 0xaa0000, 0x550000, 0x000000]\n\
+             [video.palettes.dmg]\n\
+             colors = [0xffffff, 0xaaaaaa, 0x555555, 0x000000]\n").unwrap();
+
+        palettes.load_table(&config).unwrap();
+
+        assert!(palettes.current().name() == "grey");
+
+        assert!(palettes.select("mine"));
+
+        let p = palettes.current();
+
+        assert!(p.name() == "mine");
+        assert!(p.rgb(Color::LightGrey, Source::Obp0) == [0xaa, 0xaa, 0xaa]);
+        assert!(p.rgb(Color::LightGrey, Source::Obp1) == [0xaa, 0x00, 0x00]);
+
+        // User palettes replace the built-in ones with the same name
+        assert!(palettes.select("dmg"));
+        assert!(palettes.current().rgb(Color::White, Source::Bgp) == [0xff; 3]);
+
+        assert!(!palettes.select("bogus"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Placement of the Game Boy screen in a window of arbitrary size.
+//! The 10:9 aspect ratio is always preserved, the unused part of the
+//! window is left black.
+
+use gpu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use config::toml::Table;
+
+/// How the screen is scaled to fit the window
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Scaling {
+    /// Largest integer multiple of the native resolution, keeps the
+    /// pixels perfectly square
+    Integer,
+    /// Largest size with the right aspect ratio
+    Fit,
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Scaling> {
+        match name {
+            "integer" => Some(Scaling::Integer),
+            "fit"     => Some(Scaling::Fit),
+            _         => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scaling::Integer => "integer",
+            Scaling::Fit     => "fit",
+        }
+    }
+
+    pub fn next(self) -> Scaling {
+        match self {
+            Scaling::Integer => Scaling::Fit,
+            Scaling::Fit     => Scaling::Integer,
+        }
+    }
+
+    /// Return the position and size `(x, y, width, height)` of the
+    /// screen centered in a `out_w`x`out_h` window
+    pub fn viewport(self, out_w: u32, out_h: u32) -> (u32, u32, u32, u32) {
+        let (w, h) =
+            match self {
+                Scaling::Integer => {
+                    let scale = ::std::cmp::min(out_w / SCREEN_WIDTH,
+                                                out_h / SCREEN_HEIGHT);
+
+                    // If the window is smaller than the native
+                    // resolution we have no choice but to shrink
+                    if scale == 0 {
+                        return Scaling::Fit.viewport(out_w, out_h);
+                    }
+
+                    (SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale)
+                }
+                Scaling::Fit => {
+                    if out_w * SCREEN_HEIGHT > out_h * SCREEN_WIDTH {
+                        // Window too wide, bars on the sides
+                        (out_h * SCREEN_WIDTH / SCREEN_HEIGHT, out_h)
+                    } else {
+                        // Window too tall, bars at the top and bottom
+                        (out_w, out_w * SCREEN_HEIGHT / SCREEN_WIDTH)
+                    }
+                }
+            };
+
+        ((out_w - w) / 2, (out_h - h) / 2, w, h)
+    }
+}
+
+/// Window settings
+#[derive(Clone,Debug)]
+pub struct VideoOptions {
+    /// Initial size of the window as a multiple of the native
+    /// resolution
+    pub scale:      u32,
+    /// Start in fullscreen mode
+    pub fullscreen: bool,
+    pub scaling:    Scaling,
+    /// Name of the color palette, if `None` the game gets the one
+    /// the Game Boy Color would pick or the first palette
+    pub palette:    Option<String>,
+}
+
+impl VideoOptions {
+    pub fn new() -> VideoOptions {
+        VideoOptions {
+            scale:      2,
+            fullscreen: false,
+            scaling:    Scaling::Integer,
+            palette:    None,
+        }
+    }
+
+    /// Load the `[video]` section of the configuration `table`.
+    /// Missing settings are left untouched.
+    pub fn load_table(&mut self, table: &Table) -> Result<(), String> {
+        let video =
+            match table.get("video") {
+                Some(v) => try!(v.as_table().ok_or("'video' must be a table")),
+                None    => return Ok(()),
+            };
+
+        if let Some(scale) = video.get("scale") {
+            self.scale =
+                match scale.as_integer() {
+                    Some(s) if s >= 1 && s <= 16 => s as u32,
+                    _ => return Err("'scale' must be an integer between 1 and 16"
+                                    .to_string()),
+                };
+        }
+
+        if let Some(fullscreen) = video.get("fullscreen") {
+            self.fullscreen =
+                try!(fullscreen.as_bool().ok_or("'fullscreen' must be a boolean"));
+        }
+
+        if let Some(scaling) = video.get("scaling") {
+            self.scaling =
+                try!(scaling.as_str()
+                     .and_then(Scaling::from_name)
+                     .ok_or("'scaling' must be \"integer\" or \"fit\""));
+        }
+
+        if let Some(palette) = video.get("palette") {
+            let palette = try!(palette.as_str().ok_or("'palette' must be a string"));
+
+            self.palette = Some(palette.to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scaling, VideoOptions};
+    use config::toml;
+
+    #[test]
+    fn viewport() {
+        // Exact fit
+        assert!(Scaling::Integer.viewport(320, 288) == (0, 0, 320, 288));
+        assert!(Scaling::Fit.viewport(320, 288) == (0, 0, 320, 288));
+
+        // 16:9 fullscreen
+        assert!(Scaling::Integer.viewport(1920, 1080) == (400, 36, 1120, 1008));
+        assert!(Scaling::Fit.viewport(1920, 1080) == (360, 0, 1200, 1080));
+
+        // Tall window
+        assert!(Scaling::Fit.viewport(400, 1000) == (0, 320, 400, 360));
+
+        // Smaller than the native resolution
+        assert!(Scaling::Integer.viewport(80, 144) == (0, 36, 80, 72));
+    }
+
+    #[test]
+    fn load() {
+        let mut options = VideoOptions::new();
+
+        let config = toml::parse("[video]\n\
+                                  scale = 4\n\
+                                  scaling = \"fit\"\n\
+                                  palette = \"dmg\"\n").unwrap();
+
+        options.load_table(&config).unwrap();
+
+        assert!(options.scale == 4);
+        assert!(options.scaling == Scaling::Fit);
+        assert!(options.palette == Some("dmg".to_string()));
+        assert!(!options.fullscreen);
+
+        let config = toml::parse("[video]\nscale = 0\n").unwrap();
+
+        assert!(options.load_table(&config).is_err());
+    }
+}
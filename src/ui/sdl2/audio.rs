@@ -30,16 +30,17 @@ pub struct Audio {
 }
 
 impl Audio {
-    pub fn new(channel: Receiver<::spu::SampleBuffer>) -> Audio {
+    /// Open the audio device, playing at `sample_rate` Hz
+    pub fn new(channel: Receiver<::spu::SampleBuffer>, sample_rate: u32) -> Audio {
 
-        let resampler = Resampler::new(channel, SAMPLE_RATE);
+        let resampler = Resampler::new(channel, sample_rate);
 
         let async = resampler.async();
 
         let reader = Reader::new(resampler);
 
         let spec = AudioSpecDesired {
-            freq:     Some(SAMPLE_RATE as i32),
+            freq:     Some(sample_rate as i32),
             channels: Some(1),
             samples:  Some(::spu::SAMPLES_PER_BUFFER as u16),
         };
@@ -71,6 +72,3 @@ impl ::ui::Audio for Audio {
 
 // Use 8bit sound samples
 type Sample = u8;
-
-/// Audio output sample rate
-const SAMPLE_RATE: u32 = 44_100;
@@ -1,165 +1,545 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::mem;
 
 use sdl2::event::Event;
 use sdl2::keycode::KeyCode;
-use sdl2::{joystick, controller};
+use sdl2::keyboard::{self, LSHIFTMOD, RSHIFTMOD};
 use sdl2::controller::{GameController, Button, Axis};
+use sdl2::mouse::{Mouse, LEFTMOUSESTATE};
+use sdl2::rect::Rect;
 use sdl2::sdl::Sdl;
 
-use ui::ButtonState;
+use ui::{ButtonState, Tilt};
+use ui::bindings::{Bindings, Input, Action, Macro};
+use gpu::Layer;
 
 pub struct Controller {
-    buttons:      Cell<::ui::Buttons>,
-    #[allow(dead_code)]
-    controller:   Option<controller::GameController>,
-    x_axis_state: Cell<AxisState>,
-    y_axis_state: Cell<AxisState>,
+    buttons:     Cell<::ui::Buttons>,
+    /// Tilt of the console for the cartridge accelerometer
+    tilt:        Cell<Tilt>,
+    /// Tilt given by the mouse while its left button is held
+    mouse:       Cell<Option<(f32, f32)>>,
+    /// Window and position of the Game Boy screen, the mouse tilts
+    /// the console relative to its center
+    screen:      Cell<Option<(u32, Rect)>>,
+    /// Game controllers currently opened
+    controllers: RefCell<Vec<GameController>>,
+    /// Keyboard bindings: key, shift modifier and action
+    keys:        Vec<(KeyCode, bool, Action)>,
+    /// Controller button bindings
+    pad_buttons: Vec<(Button, Action)>,
+    /// Controller axis bindings: axis, direction and action
+    pad_axes:    Vec<(Axis, AxisState, Action)>,
+    /// Inputs currently held down along with the Game Boy button
+    /// they're bound to
+    held:        RefCell<Vec<(Source, Action)>>,
+    /// Last known position of the controller axes, by controller
+    /// instance
+    axes:        RefCell<Vec<(i32, Axis, i16)>>,
+    /// Input macros
+    macros:      Vec<Macro>,
+    /// Macro being played, if any, and the index of its current
+    /// frame
+    playing:     Cell<Option<(usize, usize)>>,
+    /// Number of frames the turbo buttons stay pressed, then released
+    turbo_rate:  u32,
+    /// Frame counter used to time the turbo buttons
+    frame:       Cell<u32>,
+    /// Hotkeys pressed since the last call to `take_hotkeys`
+    hotkeys:     RefCell<Vec<Hotkey>>,
 }
 
-impl Controller {
-    pub fn new() -> Controller {
-        // Attempt to add a game controller
-
-        let njoysticks =
-            match joystick::num_joysticks() {
-                Ok(n)  => n,
-                Err(e) => {
-                    error!("Can't enumarate joysticks: {:?}", e);
-                    0
-                }
-            };
+/// Emulator hotkeys, they're not forwarded to the emulated game
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Hotkey {
+    /// Switch to the next color palette
+    NextPalette,
+    /// Switch to the next upscaling filter
+    NextFilter,
+    /// Save the current frame as a PNG image
+    Screenshot,
+    /// Save the current frame as a PNG image at the display's
+    /// upscaled resolution
+    ScaledScreenshot,
+    /// Start or stop recording an animated GIF
+    ToggleGif,
+    /// Open or close the video memory viewer windows
+    ToggleViewer,
+    /// Save the video memory views as PNG images
+    DumpViews,
+    /// Switch between windowed and fullscreen mode
+    ToggleFullscreen,
+    /// Switch to the next scaling mode
+    NextScaling,
+}
 
-        let mut controller = None;
-
-        // For now we just take the first controller we manage to open
-        // (if any)
-        for id in 0..njoysticks {
-            if controller::is_game_controller(id) {
-                println!("Attempting to open controller {}", id);
-
-                match GameController::open(id) {
-                    Ok(c) => {
-                        // We managed to find and open a game controller,
-                        // exit the loop
-                        println!("Successfully opened \"{}\"", c.name());
-                        controller = Some(c);
-                        break;
+/// Physical input currently held down
+#[derive(Clone,Copy,PartialEq)]
+enum Source {
+    Key(KeyCode),
+    /// Controller button, along with the controller instance
+    Button(i32, Button),
+    /// Controller axis direction, along with the controller instance
+    Axis(i32, Axis, AxisState),
+}
+
+impl Controller {
+    /// Create a new controller using `bindings`. Invalid bindings
+    /// are ignored with a warning.
+    pub fn new(bindings: &Bindings) -> Controller {
+        let mut keys        = Vec::new();
+        let mut pad_buttons = Vec::new();
+        let mut pad_axes    = Vec::new();
+
+        for &(ref input, action) in bindings.bindings() {
+            match *input {
+                Input::Key { ref name, shift } =>
+                    match keyboard::get_key_from_name(name) {
+                        Ok(KeyCode::Unknown) | Err(_) =>
+                            println!("Unknown key \"{}\" for {}",
+                                     name, action.name()),
+                        Ok(key) => keys.push((key, shift, action)),
                     },
-                    Err(e) => println!("failed: {:?}", e),
+                Input::Button(name) =>
+                    pad_buttons.push((button_from_name(name), action)),
+                Input::Axis(name, positive) => {
+                    let dir =
+                        if positive {
+                            AxisState::Positive
+                        } else {
+                            AxisState::Negative
+                        };
+
+                    pad_axes.push((axis_from_name(name), dir, action));
                 }
             }
         }
 
-        match controller {
-            Some(_) => println!("Controller support enabled"),
-            None    => println!("No controller found"),
-        }
-
+        // We don't open the game controllers here: SDL sends a
+        // ControllerDeviceAdded event for each controller already
+        // plugged in when it starts so they'll be handled like the
+        // ones plugged in later.
         Controller {
-            buttons:      Cell::new(::ui::Buttons::new(ButtonState::Up)),
-            controller:   controller,
-            x_axis_state: Cell::new(AxisState::Neutral),
-            y_axis_state: Cell::new(AxisState::Neutral),
+            buttons:     Cell::new(::ui::Buttons::new(ButtonState::Up)),
+            tilt:        Cell::new(Tilt::level()),
+            mouse:       Cell::new(None),
+            screen:      Cell::new(None),
+            controllers: RefCell::new(Vec::new()),
+            keys:        keys,
+            pad_buttons: pad_buttons,
+            pad_axes:    pad_axes,
+            held:        RefCell::new(Vec::new()),
+            axes:        RefCell::new(Vec::new()),
+            macros:      bindings.macros().to_vec(),
+            playing:     Cell::new(None),
+            turbo_rate:  bindings.turbo_rate(),
+            frame:       Cell::new(0),
+            hotkeys:     RefCell::new(Vec::new()),
         }
     }
 
-    pub fn update(&self, sdl2: &Sdl) -> ::ui::Event {
-        let mut event = ::ui::Event::None;
+    /// Process the pending SDL events and return the ones the
+    /// emulator needs to handle
+    pub fn update(&self, sdl2: &Sdl) -> Vec<::ui::Event> {
+        let mut events = Vec::new();
 
         let mut event_pump = sdl2.event_pump();
 
         for e in event_pump.poll_iter() {
             match e {
-                Event::KeyDown { keycode: KeyCode::Escape, .. } =>
-                    event = ::ui::Event::PowerOff,
-                Event::KeyDown { keycode: key, .. } =>
-                    self.update_key(key, ButtonState::Down),
-                Event::KeyUp { keycode: key, .. } =>
-                    self.update_key(key, ButtonState::Up),
-                Event::ControllerButtonDown{ button, .. } =>
-                    self.update_button(button, ButtonState::Down),
-                Event::ControllerButtonUp{ button, .. } =>
-                    self.update_button(button, ButtonState::Up),
-                Event::ControllerAxisMotion{ axis, value: val, .. } =>
-                    self.update_axis(axis, val),
+                Event::KeyDown { keycode, keymod, repeat, .. } => {
+                    let shift = keymod.intersects(LSHIFTMOD | RSHIFTMOD);
+
+                    // If a binding requires shift and it's held it
+                    // takes precedence over the plain one
+                    let shifted = shift && self.keys.iter()
+                        .any(|&(k, s, _)| k == keycode && s);
+
+                    let actions: Vec<Action> =
+                        self.keys.iter()
+                        .filter(|&&(k, s, _)| k == keycode && s == shifted)
+                        .map(|&(_, _, a)| a)
+                        .collect();
+
+                    // Hotkeys don't auto-repeat
+                    if !repeat {
+                        self.press(Source::Key(keycode), &actions, &mut events);
+                    }
+                }
+                Event::KeyUp { keycode, .. } =>
+                    self.release(Source::Key(keycode)),
+                Event::ControllerButtonDown { which, button, .. } => {
+                    let actions: Vec<Action> =
+                        self.pad_buttons.iter()
+                        .filter(|&&(b, _)| b == button)
+                        .map(|&(_, a)| a)
+                        .collect();
+
+                    self.press(Source::Button(which, button), &actions, &mut events);
+                }
+                Event::ControllerButtonUp { which, button, .. } =>
+                    self.release(Source::Button(which, button)),
+                Event::ControllerAxisMotion { which, axis, value, .. } =>
+                    self.update_axis(which, axis, value, &mut events),
+                Event::MouseMotion { window_id, mousestate, x, y, .. } =>
+                    if mousestate.contains(LEFTMOUSESTATE) {
+                        self.mouse_tilt(window_id, x, y);
+                    },
+                Event::MouseButtonDown { window_id, mouse_btn: Mouse::Left, x, y, .. } =>
+                    self.mouse_tilt(window_id, x, y),
+                Event::MouseButtonUp { mouse_btn: Mouse::Left, .. } => {
+                    self.mouse.set(None);
+                    self.update_tilt();
+                }
+                Event::ControllerDeviceAdded { which, .. } =>
+                    self.add_controller(which),
+                Event::ControllerDeviceRemoved { which, .. } =>
+                    self.remove_controller(which),
                 Event::Quit { .. } =>
-                    event = ::ui::Event::PowerOff,
+                    events.push(::ui::Event::PowerOff),
                 _ => ()
             }
         }
 
-        event
+        events
     }
 
     pub fn buttons(&self) -> &Cell<::ui::Buttons> {
         &self.buttons
     }
 
-    /// Update key state. For now keybindings are hardcoded.
-    fn update_key(&self, key: KeyCode, state: ButtonState) {
-        let mut b = self.buttons.get();
-
-        match key {
-            KeyCode::Up        => b.up     = state,
-            KeyCode::Down      => b.down   = state,
-            KeyCode::Left      => b.left   = state,
-            KeyCode::Right     => b.right  = state,
-            KeyCode::LAlt      => b.a      = state,
-            KeyCode::LCtrl     => b.b      = state,
-            KeyCode::Return    => b.start  = state,
-            KeyCode::RShift    => b.select = state,
-            _                  => (),
+    pub fn tilt(&self) -> &Cell<Tilt> {
+        &self.tilt
+    }
+
+    /// Set the window displaying the Game Boy screen and the
+    /// position of the screen in it
+    pub fn set_screen(&self, window: u32, viewport: Rect) {
+        self.screen.set(Some((window, viewport)));
+    }
+
+    /// Return the hotkeys pressed since the last call
+    pub fn take_hotkeys(&self) -> Vec<Hotkey> {
+        mem::replace(&mut *self.hotkeys.borrow_mut(), Vec::new())
+    }
+
+    /// Must be called once per emulated frame to time the turbo
+    /// buttons and the macros
+    pub fn next_frame(&self) {
+        self.frame.set(self.frame.get().wrapping_add(1));
+
+        if let Some((m, f)) = self.playing.get() {
+            let next =
+                if f + 1 < self.macros[m].frames().len() {
+                    Some((m, f + 1))
+                } else {
+                    None
+                };
+
+            self.playing.set(next);
         }
 
-        self.buttons.set(b);
+        self.update_buttons();
+    }
+
+    /// Handle the press of an input bound to `actions`. The Game Boy
+    /// buttons are held until the input is released, the other
+    /// actions are triggered immediately.
+    fn press(&self, source: Source, actions: &[Action], events: &mut Vec<::ui::Event>) {
+        for &action in actions {
+            if action == Action::TurboA || action == Action::TurboB {
+                let turbo_held = self.held.borrow().iter()
+                    .any(|&(_, a)| a == Action::TurboA || a == Action::TurboB);
+
+                // Start with a press instead of waiting for the next
+                // turbo period
+                if !turbo_held {
+                    self.frame.set(0);
+                }
+            }
+
+            if action.is_button() || action.is_tilt() {
+                self.held.borrow_mut().push((source, action));
+            } else {
+                self.trigger(action, events);
+            }
+        }
+
+        self.update_buttons();
+    }
+
+    fn release(&self, source: Source) {
+        self.held.borrow_mut().retain(|&(s, _)| s != source);
+
+        self.update_buttons();
     }
 
-    /// Same as update_key but for controller buttons
-    fn update_button(&self, button: Button, state: ButtonState) {
-        let mut b = self.buttons.get();
-
-        match button {
-            Button::A         => b.a      = state,
-            Button::B         => b.b      = state,
-            Button::DPadLeft  => b.left   = state,
-            Button::DPadRight => b.right  = state,
-            Button::DPadUp    => b.up     = state,
-            Button::DPadDown  => b.down   = state,
-            Button::Start     => b.start  = state,
-            Button::Back      => b.select = state,
-            _                 => (),
+    /// Recompute the state of the Game Boy buttons from the inputs
+    /// currently held. A button stays down as long as any of the
+    /// inputs bound to it is held. While a macro plays it replaces
+    /// the user's input.
+    fn update_buttons(&self) {
+        self.update_tilt();
+
+        if let Some((m, f)) = self.playing.get() {
+            self.buttons.set(self.macros[m].frames()[f]);
+            return;
+        }
+
+        let mut b = ::ui::Buttons::new(ButtonState::Up);
+
+        let down = ButtonState::Down;
+
+        // The turbo buttons alternate between `turbo_rate` frames
+        // pressed and `turbo_rate` frames released
+        let turbo = (self.frame.get() / self.turbo_rate) % 2 == 0;
+
+        for &(_, action) in self.held.borrow().iter() {
+            match action {
+                Action::A      => b.a      = down,
+                Action::B      => b.b      = down,
+                Action::Start  => b.start  = down,
+                Action::Select => b.select = down,
+                Action::Up     => b.up     = down,
+                Action::Down   => b.down   = down,
+                Action::Left   => b.left   = down,
+                Action::Right  => b.right  = down,
+                Action::TurboA => if turbo { b.a = down },
+                Action::TurboB => if turbo { b.b = down },
+                _              => (),
+            }
         }
 
         self.buttons.set(b);
     }
 
-    /// Map left stick X/Y to directional buttons
-    fn update_axis(&self, axis: Axis, val: i16) {
-        let mut b = self.buttons.get();
+    /// Recompute the tilt of the console. The keys and buttons tilt
+    /// it all the way, the axes and the mouse proportionally. The
+    /// sources add up.
+    fn update_tilt(&self) {
+        let mut tilt =
+            match self.mouse.get() {
+                Some((x, y)) => Tilt { x: x, y: y },
+                None         => Tilt::level(),
+            };
 
-        let state = AxisState::from_value(val);
+        for &(source, action) in self.held.borrow().iter() {
+            // The axes are handled below
+            if let Source::Axis(..) = source {
+                continue;
+            }
 
-        match axis {
-            Axis::LeftX => {
-                if state != self.x_axis_state.get() {
-                    self.x_axis_state.set(state);
+            add_tilt(&mut tilt, action, 1.);
+        }
 
-                    b.left  = state.down_if_negative();
-                    b.right = state.down_if_positive();
+        for &(_, axis, val) in self.axes.borrow().iter() {
+            for &(a, dir, action) in self.pad_axes.iter() {
+                if a != axis {
+                    continue;
+                }
+
+                let amount =
+                    match dir {
+                        AxisState::Negative => -(val as f32) / 32768.,
+                        _                   => val as f32 / 32767.,
+                    };
+
+                if amount > 0. {
+                    add_tilt(&mut tilt, action, amount);
                 }
             }
-            Axis::LeftY => {
-                if state != self.y_axis_state.get() {
-                    self.y_axis_state.set(state);
+        }
+
+        tilt.x = tilt.x.max(-1.).min(1.);
+        tilt.y = tilt.y.max(-1.).min(1.);
+
+        self.tilt.set(tilt);
+    }
+
+    /// The mouse has been dragged to (`x`, `y`) in `window`
+    fn mouse_tilt(&self, window: u32, x: i32, y: i32) {
+        let viewport =
+            match self.screen.get() {
+                Some((w, viewport)) if w == window => viewport,
+                _ => return,
+            };
+
+        let half_w = ::std::cmp::max(viewport.w / 2, 1) as f32;
+        let half_h = ::std::cmp::max(viewport.h / 2, 1) as f32;
+
+        let x = (x - viewport.x) as f32 / half_w - 1.;
+        let y = (y - viewport.y) as f32 / half_h - 1.;
 
-                    b.up   = state.down_if_negative();
-                    b.down = state.down_if_positive();
+        self.mouse.set(Some((x.max(-1.).min(1.), y.max(-1.).min(1.))));
+
+        self.update_tilt();
+    }
+
+    /// Start playing the `index`th macro from the beginning
+    fn play_macro(&self, index: usize) {
+        if self.macros[index].frames().is_empty() {
+            return;
+        }
+
+        println!("Macro: {}", self.macros[index].name());
+
+        self.playing.set(Some((index, 0)));
+
+        self.update_buttons();
+    }
+
+    /// Trigger a hotkey action
+    fn trigger(&self, action: Action, events: &mut Vec<::ui::Event>) {
+        let hotkey =
+            match action {
+                Action::Macro(m) =>
+                    return self.play_macro(m),
+                Action::Quit =>
+                    return events.push(::ui::Event::PowerOff),
+                Action::ToggleBackground =>
+                    return events.push(::ui::Event::ToggleLayer(Layer::Background)),
+                Action::ToggleWindow =>
+                    return events.push(::ui::Event::ToggleLayer(Layer::Window)),
+                Action::ToggleSprites =>
+                    return events.push(::ui::Event::ToggleLayer(Layer::Sprites)),
+                Action::SpriteLimit =>
+                    return events.push(::ui::Event::ToggleLayer(Layer::SpriteLimit)),
+                Action::NextPalette      => Hotkey::NextPalette,
+                Action::NextFilter       => Hotkey::NextFilter,
+                Action::ToggleViewer     => Hotkey::ToggleViewer,
+                Action::DumpViews        => Hotkey::DumpViews,
+                Action::ToggleGif        => Hotkey::ToggleGif,
+                Action::ToggleFullscreen => Hotkey::ToggleFullscreen,
+                Action::NextScaling      => Hotkey::NextScaling,
+                Action::Screenshot       => Hotkey::Screenshot,
+                Action::ScaledScreenshot => Hotkey::ScaledScreenshot,
+                // Game Boy buttons are handled by `press`
+                _ => return,
+            };
+
+        self.hotkeys.borrow_mut().push(hotkey);
+    }
+
+    /// Axes are treated like a pair of buttons, one for each
+    /// direction. The tilt actions also use their position.
+    fn update_axis(&self, which: i32, axis: Axis, val: i16, events: &mut Vec<::ui::Event>) {
+        let state = AxisState::from_value(val);
+
+        let previous = {
+            let mut axes = self.axes.borrow_mut();
+
+            match axes.iter().position(|&(w, a, _)| w == which && a == axis) {
+                Some(i) => AxisState::from_value(mem::replace(&mut axes[i].2, val)),
+                None => {
+                    axes.push((which, axis, val));
+                    AxisState::Neutral
                 }
             }
-            _ => (),
+        };
+
+        if state == previous {
+            self.update_tilt();
+            return;
         }
 
-        self.buttons.set(b);
+        if previous != AxisState::Neutral {
+            self.release(Source::Axis(which, axis, previous));
+        }
+
+        if state != AxisState::Neutral {
+            let actions: Vec<Action> =
+                self.pad_axes.iter()
+                .filter(|&&(a, d, _)| a == axis && d == state)
+                .map(|&(_, _, action)| action)
+                .collect();
+
+            self.press(Source::Axis(which, axis, state), &actions, events);
+        }
+    }
+
+    /// A controller has been plugged in, `index` is its device
+    /// index
+    fn add_controller(&self, index: i32) {
+        match GameController::open(index) {
+            Ok(c) => {
+                println!("Controller connected: \"{}\"", c.name());
+                self.controllers.borrow_mut().push(c);
+            }
+            Err(e) => println!("Couldn't open controller {}: {:?}", index, e),
+        }
+    }
+
+    /// A controller has been unplugged, `instance` is its instance
+    /// ID
+    fn remove_controller(&self, instance: i32) {
+        self.controllers.borrow_mut().retain(|c| {
+            let attached = c.get_attached();
+
+            if !attached {
+                println!("Controller disconnected: \"{}\"", c.name());
+            }
+
+            attached
+        });
+
+        // Release everything that was held on this controller
+        self.held.borrow_mut().retain(|&(s, _)| {
+            match s {
+                Source::Button(w, _) | Source::Axis(w, _, _) => w != instance,
+                Source::Key(_) => true,
+            }
+        });
+
+        self.axes.borrow_mut().retain(|&(w, _, _)| w != instance);
+
+        self.update_buttons();
+    }
+}
+
+/// Tilt the console by `amount` in the direction of `action`, if
+/// it's a tilt action
+fn add_tilt(tilt: &mut Tilt, action: Action, amount: f32) {
+    match action {
+        Action::TiltUp    => tilt.y -= amount,
+        Action::TiltDown  => tilt.y += amount,
+        Action::TiltLeft  => tilt.x -= amount,
+        Action::TiltRight => tilt.x += amount,
+        _                 => (),
+    }
+}
+
+/// Convert a button name validated by `ui::bindings`
+fn button_from_name(name: &str) -> Button {
+    match name {
+        "a"             => Button::A,
+        "b"             => Button::B,
+        "x"             => Button::X,
+        "y"             => Button::Y,
+        "back"          => Button::Back,
+        "guide"         => Button::Guide,
+        "start"         => Button::Start,
+        "leftstick"     => Button::LeftStick,
+        "rightstick"    => Button::RightStick,
+        "leftshoulder"  => Button::LeftShoulder,
+        "rightshoulder" => Button::RightShoulder,
+        "dpup"          => Button::DPadUp,
+        "dpdown"        => Button::DPadDown,
+        "dpleft"        => Button::DPadLeft,
+        "dpright"       => Button::DPadRight,
+        _               => Button::Invalid,
+    }
+}
+
+/// Convert an axis name validated by `ui::bindings`
+fn axis_from_name(name: &str) -> Axis {
+    match name {
+        "leftx"        => Axis::LeftX,
+        "lefty"        => Axis::LeftY,
+        "rightx"       => Axis::RightX,
+        "righty"       => Axis::RightY,
+        "lefttrigger"  => Axis::TriggerLeft,
+        "righttrigger" => Axis::TriggerRight,
+        _              => Axis::Invalid,
     }
 }
 
@@ -180,22 +560,6 @@ impl AxisState {
             AxisState::Neutral
         }
     }
-
-    fn down_if_negative(self) -> ButtonState {
-        if self == AxisState::Negative {
-            ButtonState::Down
-        } else {
-            ButtonState::Up
-        }
-    }
-
-    fn down_if_positive(self) -> ButtonState {
-        if self == AxisState::Positive {
-            ButtonState::Down
-        } else {
-            ButtonState::Up
-        }
-    }
 }
 
 /// The controller axis moves in a range from -32768 to +32767. To
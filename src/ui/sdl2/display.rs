@@ -1,32 +1,91 @@
 use sdl2::video::Window;
-use sdl2::render::Renderer;
-use sdl2::pixels::Color::RGB;
-use sdl2::rect::{Point, Rect};
+use sdl2::render::{Renderer, Texture};
+use sdl2::pixels::{PixelFormatEnum, Color};
+use sdl2::rect::Rect;
+use sdl2::video::FullscreenType;
 use sdl2::sdl::Sdl;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::BufWriter;
+use std::cell::RefCell;
 
-use gpu::Color;
+use gpu::{Frame, VideoMemory, SCREEN_WIDTH, SCREEN_HEIGHT};
+use ui::palette::{Palettes, Rgb};
+use ui::filter::{self, Filter};
+use ui::ghosting::Ghosting;
+use ui::scaling::{Scaling, VideoOptions};
+use ui::sdl2::controller::{Controller, Hotkey};
+use ui::sdl2::viewer::Viewer;
+use capture::gif::GifWriter;
 
-pub struct Display {
-    renderer: Renderer<'static>,
-    /// Upscaling factor, log2.
-    upscale:  u8,
+pub struct Display<'a> {
+    sdl2:       &'a Sdl,
+    renderer:   Renderer<'static>,
+    /// Streaming texture the frames are uploaded to before being
+    /// scaled to the viewport by SDL. Its size depends on the
+    /// upscaling filter.
+    texture:    Texture,
+    /// Available upscaling filters
+    filters:    Vec<Box<Filter>>,
+    /// Index of the current filter in `filters`
+    filter:     usize,
+    /// LCD ghosting emulation, applied once per frame before the
+    /// image is displayed and captured
+    ghosting:   Ghosting,
+    /// Last image displayed, shared with the recorder
+    screen:     &'a RefCell<Vec<Rgb>>,
+    /// Palettes used to convert the frame's shades of grey into RGB
+    palettes:   Palettes,
+    /// Controller used to receive the display hotkeys
+    controller: &'a Controller,
+    /// How the screen is scaled to fit the window
+    scaling:    Scaling,
+    /// `true` if the window is fullscreen
+    fullscreen: bool,
+    /// Name of the game, used to name the screenshots
+    game:       String,
+    /// Directory where the screenshots, GIFs and video memory views
+    /// are saved
+    output_dir: PathBuf,
+    /// Animated GIF being recorded, if any, along with its path
+    gif:        Option<(GifWriter<BufWriter<File>>, PathBuf)>,
+    /// `true` if the video memory viewer windows should be displayed
+    show_viewer: bool,
+    /// Video memory viewer windows, opened at the next VBlank after
+    /// `show_viewer` is set
+    viewer:     Option<Viewer>,
+    /// `true` if the video memory views should be saved at the next
+    /// VBlank
+    dump_views: bool,
 }
 
-impl Display {
-    pub fn new(sdl2: &Sdl, upscale: u8) -> Display {
-        let up = 1 << (upscale as usize);
+impl<'a> Display<'a> {
+    pub fn new<'n>(sdl2: &'n Sdl,
+                   controller: &'n Controller,
+                   screen: &'n RefCell<Vec<Rgb>>,
+                   options: &VideoOptions,
+                   palettes: Palettes,
+                   game: String) -> Display<'n> {
+        let xres = (SCREEN_WIDTH  * options.scale) as i32;
+        let yres = (SCREEN_HEIGHT * options.scale) as i32;
 
-        let xres = 160 * up;
-        let yres = 144 * up;
+        let mut flags = ::sdl2::video::OPENGL | ::sdl2::video::RESIZABLE;
 
-        let window = match Window::new(sdl2, "gb-rs",
-                                       ::sdl2::video::WindowPos::PosCentered,
-                                       ::sdl2::video::WindowPos::PosCentered,
-                                       xres, yres, ::sdl2::video::OPENGL) {
+        if options.fullscreen {
+            flags = flags | ::sdl2::video::FULLSCREEN_DESKTOP;
+        }
+
+        let mut window = match Window::new(sdl2, "gb-rs",
+                                           ::sdl2::video::WindowPos::PosCentered,
+                                           ::sdl2::video::WindowPos::PosCentered,
+                                           xres, yres, flags) {
             Ok(window) => window,
             Err(err)   => panic!("failed to create SDL2 window: {}", err)
         };
 
+        window.properties(&sdl2.event_pump())
+            .set_minimum_size(SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32);
+
         let renderer =
             match Renderer::from_window(window,
                                         ::sdl2::render::RenderDriverIndex::Auto,
@@ -35,45 +94,302 @@ impl Display {
             Err(err) => panic!("failed to create SDL2 renderer: {}", err)
         };
 
-        Display { renderer: renderer, upscale: upscale }
+        let texture =
+            match create_texture(&renderer, 1) {
+                Ok(texture) => texture,
+                Err(err) => panic!("failed to create SDL2 texture: {}", err)
+            };
+
+        // Blank screen until the first frame
+        *screen.borrow_mut() = palettes.current().render(&Frame::new());
+
+        Display {
+            sdl2:       sdl2,
+            renderer:   renderer,
+            texture:    texture,
+            filters:    filter::filters(),
+            filter:     0,
+            ghosting:   Ghosting::off(),
+            screen:     screen,
+            palettes:   palettes,
+            controller: controller,
+            scaling:    options.scaling,
+            fullscreen: options.fullscreen,
+            game:       game,
+            output_dir: PathBuf::from("."),
+            gif:        None,
+            show_viewer: false,
+            viewer:     None,
+            dump_views: false,
+        }
     }
-}
 
-impl ::ui::Display for Display {
-    fn clear(&mut self) {
-        let mut drawer = self.renderer.drawer();
+    /// Handle the hotkeys, `pixels` is the current image used for
+    /// the screenshots
+    fn handle_hotkeys(&mut self, pixels: &[Rgb]) {
+        for hotkey in self.controller.take_hotkeys() {
+            match hotkey {
+                Hotkey::NextPalette => {
+                    self.palettes.next();
+
+                    println!("Palette: {}", self.palettes.current().name());
+                }
+                Hotkey::NextFilter => {
+                    let next = (self.filter + 1) % self.filters.len();
+
+                    self.set_filter(next);
 
-        let _ = drawer.set_draw_color(RGB(0xff, 0x00, 0x00));
-        let _ = drawer.clear();
+                    println!("Filter: {}", self.filters[self.filter].name());
+                }
+                Hotkey::Screenshot =>
+                    self.screenshot(pixels, 1),
+                Hotkey::ScaledScreenshot => {
+                    let scale = self.screen_scale();
+
+                    self.screenshot(pixels, scale);
+                }
+                Hotkey::ToggleGif =>
+                    self.toggle_gif(),
+                Hotkey::ToggleViewer => {
+                    self.show_viewer = !self.show_viewer;
+
+                    if !self.show_viewer {
+                        self.viewer = None;
+                    }
+                }
+                Hotkey::DumpViews =>
+                    self.dump_views = true,
+                Hotkey::ToggleFullscreen =>
+                    self.toggle_fullscreen(),
+                Hotkey::NextScaling => {
+                    self.scaling = self.scaling.next();
+
+                    println!("Scaling: {}", self.scaling.name());
+                }
+            }
+        }
     }
 
-    fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
-        let color = match color {
-            Color::Black     => RGB(0x00, 0x00, 0x00),
-            Color::DarkGrey  => RGB(0x55, 0x55, 0x55),
-            Color::LightGrey => RGB(0xab, 0xab, 0xab),
-            Color::White     => RGB(0xff, 0xff, 0xff),
-        };
+    /// Switch to the `index`th filter, the texture is resized to the
+    /// filter's output
+    pub fn set_filter(&mut self, index: usize) {
+        let scale = self.filters[index].scale();
 
-        let mut drawer = self.renderer.drawer();
+        match create_texture(&self.renderer, scale) {
+            Ok(texture) => {
+                self.texture = texture;
+                self.filter  = index;
+            }
+            Err(e) => println!("Couldn't resize SDL2 texture: {}", e),
+        }
+    }
 
-        let _ = drawer.set_draw_color(color);
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = !self.fullscreen;
 
-        if self.upscale == 0 {
-            let _ = drawer.draw_point(Point::new(x as i32, y as i32));
-        } else {
-            let up = 1 << (self.upscale as usize);
+        let mode =
+            if fullscreen {
+                FullscreenType::FTDesktop
+            } else {
+                FullscreenType::FTOff
+            };
 
-            // Translate coordinates
-            let x = x as i32 * up;
-            let y = y as i32 * up;
+        let pump = self.sdl2.event_pump();
 
-            let _ = drawer.fill_rect(Rect::new(x, y, up, up));
+        let res =
+            match self.renderer.window_properties(&pump) {
+                Some(mut window) => window.set_fullscreen(mode),
+                None => return,
+            };
+
+        match res {
+            Ok(_)  => self.fullscreen = fullscreen,
+            Err(e) => println!("Couldn't toggle fullscreen: {}", e),
         }
     }
 
-    fn flip(&mut self) {
-        self.renderer.drawer().present();
-        self.clear();
+    /// Return the position and size of the screen in the window
+    fn viewport(&mut self) -> Rect {
+        let (w, h) =
+            match self.renderer.drawer().get_output_size() {
+                Ok(s)  => s,
+                Err(e) => {
+                    error!("Couldn't get SDL2 output size: {}", e);
+                    (SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32)
+                }
+            };
+
+        let (x, y, w, h) = self.scaling.viewport(w as u32, h as u32);
+
+        Rect::new(x as i32, y as i32, w as i32, h as i32)
+    }
+
+    /// Integer factor closest to the current size of the screen in
+    /// the window, at least 1
+    fn screen_scale(&mut self) -> u32 {
+        let viewport = self.viewport();
+
+        ::std::cmp::max(viewport.w as u32 / SCREEN_WIDTH, 1)
+    }
+
+    pub fn set_output_dir(&mut self, dir: &Path) {
+        if let Err(e) = ::std::fs::create_dir_all(dir) {
+            println!("Couldn't create {}: {}", dir.display(), e);
+        }
+
+        self.output_dir = dir.to_path_buf();
+    }
+
+    pub fn set_ghosting(&mut self, ghosting: Ghosting) {
+        self.ghosting = ghosting;
     }
+
+    fn screenshot(&self, pixels: &[Rgb], scale: u32) {
+        match ::capture::save_screenshot(&self.output_dir,
+                                         &self.game,
+                                         pixels,
+                                         scale) {
+            Ok(path) => println!("Screenshot saved to {}", path.display()),
+            Err(e)   => println!("Couldn't save screenshot: {}", e),
+        }
+    }
+
+    fn toggle_gif(&mut self) {
+        match self.gif.take() {
+            Some((gif, path)) =>
+                match gif.finish() {
+                    Ok(n)  => println!("GIF saved to {} ({} frames)",
+                                       path.display(), n),
+                    Err(e) => println!("Couldn't save GIF: {}", e),
+                },
+            None => {
+                let path = ::capture::unique_path(&self.output_dir,
+                                                  &self.game,
+                                                  "gif");
+
+                let gif = File::create(&path).and_then(|f| {
+                    GifWriter::new(BufWriter::new(f))
+                });
+
+                match gif {
+                    Ok(gif) => {
+                        println!("Recording GIF to {}", path.display());
+                        self.gif = Some((gif, path));
+                    }
+                    Err(e) => println!("Couldn't create GIF: {}", e),
+                }
+            }
+        }
+    }
+
+    fn record_gif(&mut self, pixels: &[Rgb], time: u64) {
+        let res =
+            match self.gif {
+                Some((ref mut gif, _)) => gif.push_frame(pixels, time),
+                None                   => return,
+            };
+
+        if let Err(e) = res {
+            println!("GIF recording failed: {}", e);
+            self.gif = None;
+        }
+    }
+}
+
+impl<'a> Drop for Display<'a> {
+    fn drop(&mut self) {
+        // Don't lose a GIF still being recorded when the emulator
+        // exits
+        if self.gif.is_some() {
+            self.toggle_gif();
+        }
+    }
+}
+
+impl<'a> ::ui::Display for Display<'a> {
+    fn flip(&mut self, frame: &Frame) {
+        self.controller.next_frame();
+
+        // The ghosting is applied once, the blended image is the one
+        // displayed, captured and recorded
+        let mut pixels = self.palettes.current().render(frame);
+
+        self.ghosting.apply(&mut pixels);
+
+        self.handle_hotkeys(&pixels);
+        self.record_gif(&pixels, frame.time());
+
+        let filter = &self.filters[self.filter];
+
+        let scaled = filter.apply(&pixels, SCREEN_WIDTH, SCREEN_HEIGHT);
+
+        let width = (SCREEN_WIDTH * filter.scale()) as usize;
+
+        *self.screen.borrow_mut() = pixels;
+
+        let res = self.texture.with_lock(None, |buf, pitch| {
+            for (y, line) in scaled.chunks(width).enumerate() {
+                for (x, rgb) in line.iter().enumerate() {
+                    let off = y * pitch + x * 3;
+
+                    buf[off..off + 3].clone_from_slice(rgb);
+                }
+            }
+        });
+
+        if let Err(e) = res {
+            error!("Couldn't update SDL2 texture: {}", e);
+            return;
+        }
+
+        let viewport = self.viewport();
+
+        // The mouse tilts the console relative to the screen
+        if let Some(window) = self.renderer.get_parent_as_window() {
+            self.controller.set_screen(window.get_id(), viewport);
+        }
+
+        let mut drawer = self.renderer.drawer();
+
+        // Letterbox the screen in black if the window doesn't have
+        // the right aspect ratio
+        drawer.set_draw_color(Color::RGB(0, 0, 0));
+        drawer.clear();
+        drawer.copy(&self.texture, None, Some(viewport));
+        drawer.present();
+    }
+
+    fn wants_video_memory(&self) -> bool {
+        self.show_viewer || self.dump_views
+    }
+
+    fn video_memory(&mut self, mem: &VideoMemory) {
+        let palette = self.palettes.current();
+
+        if self.show_viewer {
+            match self.viewer {
+                Some(ref mut v) => v.update(mem, palette),
+                None => self.viewer = Some(Viewer::new(self.sdl2, mem, palette)),
+            }
+        }
+
+        if self.dump_views {
+            self.dump_views = false;
+
+            match ::ui::viewer::save_views(&self.output_dir, &self.game, mem, palette) {
+                Ok(paths) => for p in paths {
+                    println!("Saved {}", p.display());
+                },
+                Err(e) => println!("Couldn't save video memory views: {}", e),
+            }
+        }
+    }
+}
+
+/// Create a streaming texture large enough for a frame upscaled by
+/// `scale`
+fn create_texture(renderer: &Renderer, scale: u32) -> Result<Texture, String> {
+    renderer.create_texture_streaming(PixelFormatEnum::RGB24,
+                                      ((SCREEN_WIDTH  * scale) as i32,
+                                       (SCREEN_HEIGHT * scale) as i32))
 }
@@ -1,5 +1,7 @@
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+
+use ui::palette::Rgb;
 
 // Re-export the public interface defined in sub-modules
 pub use ui::sdl2::display::Display;
@@ -9,14 +11,18 @@ pub use ui::sdl2::audio::Audio;
 mod display;
 mod audio;
 mod controller;
+mod viewer;
 
 pub struct Context {
     sdl2: ::sdl2::sdl::Sdl,
     controller: controller::Controller,
+    /// Image currently displayed, after the palette conversion and
+    /// the LCD ghosting
+    screen: RefCell<Vec<Rgb>>,
 }
 
 impl Context {
-    pub fn new() -> Context {
+    pub fn new(bindings: &::ui::bindings::Bindings) -> Context {
         let sdl2 =
             ::sdl2::init(::sdl2::INIT_VIDEO |
                          ::sdl2::INIT_GAME_CONTROLLER |
@@ -24,19 +30,39 @@ impl Context {
 
         Context {
             sdl2: sdl2,
-            controller: controller::Controller::new(),
+            controller: controller::Controller::new(bindings),
+            screen: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn new_display(&self, upscale: u8) -> display::Display {
-        display::Display::new(&self.sdl2, upscale)
+    pub fn new_display<'a>(&'a self,
+                           options: &::ui::scaling::VideoOptions,
+                           palettes: ::ui::palette::Palettes,
+                           game: String)
+                           -> display::Display<'a> {
+        display::Display::new(&self.sdl2,
+                              &self.controller,
+                              &self.screen,
+                              options,
+                              palettes,
+                              game)
     }
 
     pub fn buttons(&self) -> &Cell<::ui::Buttons> {
         self.controller.buttons()
     }
 
-    pub fn update_buttons(&self) -> ::ui::Event {
+    pub fn tilt(&self) -> &Cell<::ui::Tilt> {
+        self.controller.tilt()
+    }
+
+    /// Return the image currently displayed, the one the recordings
+    /// capture
+    pub fn screen(&self) -> &RefCell<Vec<Rgb>> {
+        &self.screen
+    }
+
+    pub fn update_buttons(&self) -> Vec<::ui::Event> {
         self.controller.update(&self.sdl2)
     }
 }
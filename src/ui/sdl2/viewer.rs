@@ -0,0 +1,116 @@
+//! SDL2 windows displaying the video memory debugging views
+
+use sdl2::video::Window;
+use sdl2::render::{Renderer, Texture};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::sdl::Sdl;
+
+use gpu::VideoMemory;
+use ui::palette::Palette;
+use ui::viewer::View;
+
+/// One window per view
+pub struct Viewer {
+    windows: Vec<ViewWindow>,
+}
+
+struct ViewWindow {
+    view:     View,
+    renderer: Renderer<'static>,
+    texture:  Texture,
+}
+
+impl Viewer {
+    /// Open the debugging windows. They're closed when the `Viewer`
+    /// is dropped.
+    pub fn new(sdl2: &Sdl, mem: &VideoMemory, palette: &Palette) -> Viewer {
+        let windows =
+            View::all().iter()
+            .filter_map(|&view| {
+                match ViewWindow::new(sdl2, view, mem, palette) {
+                    Ok(w)  => Some(w),
+                    Err(e) => {
+                        println!("Couldn't open {} viewer: {}", view.name(), e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Viewer {
+            windows: windows,
+        }
+    }
+
+    /// Redraw all the views
+    pub fn update(&mut self, mem: &VideoMemory, palette: &Palette) {
+        for w in self.windows.iter_mut() {
+            w.update(mem, palette);
+        }
+    }
+}
+
+impl ViewWindow {
+    fn new(sdl2: &Sdl,
+           view: View,
+           mem: &VideoMemory,
+           palette: &Palette) -> Result<ViewWindow, String> {
+        // Render the view once to get its dimensions
+        let img = view.render(mem, palette);
+
+        let (w, h) = (img.width() as i32, img.height() as i32);
+
+        let window = try!(Window::new(sdl2,
+                                      &format!("gb-rs: {}", view.name()),
+                                      ::sdl2::video::WindowPos::PosUndefined,
+                                      ::sdl2::video::WindowPos::PosUndefined,
+                                      w * VIEWER_SCALE,
+                                      h * VIEWER_SCALE,
+                                      ::sdl2::video::OPENGL));
+
+        let renderer =
+            try!(Renderer::from_window(window,
+                                       ::sdl2::render::RenderDriverIndex::Auto,
+                                       ::sdl2::render::SOFTWARE));
+
+        let texture = try!(renderer.create_texture_streaming(PixelFormatEnum::RGB24,
+                                                             (w, h)));
+
+        let mut vw = ViewWindow {
+            view:     view,
+            renderer: renderer,
+            texture:  texture,
+        };
+
+        vw.update(mem, palette);
+
+        Ok(vw)
+    }
+
+    fn update(&mut self, mem: &VideoMemory, palette: &Palette) {
+        let img = self.view.render(mem, palette);
+
+        let stride = img.width() as usize * 3;
+
+        let res = self.texture.with_lock(None, |buf, pitch| {
+            for (y, line) in img.rgb().chunks(stride).enumerate() {
+                let off = y * pitch;
+
+                buf[off..off + stride].clone_from_slice(line);
+            }
+        });
+
+        if let Err(e) = res {
+            error!("Couldn't update SDL2 texture: {}", e);
+            return;
+        }
+
+        let mut drawer = self.renderer.drawer();
+
+        drawer.copy(&self.texture, None, None);
+        drawer.present();
+    }
+}
+
+/// Upscaling factor of the viewer windows
+const VIEWER_SCALE: i32 = 2;
@@ -0,0 +1,321 @@
+//! Debugging views of the video memory: tile data, background maps
+//! and sprite attributes. They're rendered into RGB images that can
+//! be displayed by the frontend or saved as PNG.
+
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{BufWriter, Write, Result};
+
+use gpu::{VideoMemory, Source, SCREEN_WIDTH, SCREEN_HEIGHT};
+use gpu::sprite::Sprite;
+use ui::palette::{Palette, Rgb};
+
+/// RGB image, 3 bytes per pixel in row-major order
+pub struct Image {
+    width:  u32,
+    height: u32,
+    rgb:    Vec<u8>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32, fill: Rgb) -> Image {
+        let npixels = (width * height) as usize;
+
+        let mut rgb = Vec::with_capacity(npixels * 3);
+
+        for _ in 0..npixels {
+            rgb.extend(fill.iter().cloned());
+        }
+
+        Image {
+            width:  width,
+            height: height,
+            rgb:    rgb,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn rgb(&self) -> &[u8] {
+        &self.rgb
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, c: Rgb) {
+        let off = ((y * self.width + x) * 3) as usize;
+
+        self.rgb[off..off + 3].clone_from_slice(&c);
+    }
+
+    /// Draw the outline of a `w`x`h` rectangle whose top left corner
+    /// is at (`x`, `y`). The rectangle wraps around the edges of the
+    /// image like the background does on the screen.
+    fn wrapped_rect(&mut self, x: u32, y: u32, w: u32, h: u32, c: Rgb) {
+        for i in 0..w {
+            let px = (x + i) % self.width;
+
+            self.set_pixel(px, y % self.height, c);
+            self.set_pixel(px, (y + h - 1) % self.height, c);
+        }
+
+        for i in 0..h {
+            let py = (y + i) % self.height;
+
+            self.set_pixel(x % self.width, py, c);
+            self.set_pixel((x + w - 1) % self.width, py, c);
+        }
+    }
+}
+
+/// Which view to render
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum View {
+    /// The 384 tiles in VRAM
+    Tiles,
+    /// Tile map at 0x9800
+    LowMap,
+    /// Tile map at 0x9c00
+    HighMap,
+    /// The 40 sprites in OAM
+    Sprites,
+}
+
+impl View {
+    pub fn all() -> [View; 4] {
+        [View::Tiles, View::LowMap, View::HighMap, View::Sprites]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            View::Tiles   => "tiles",
+            View::LowMap  => "map-9800",
+            View::HighMap => "map-9c00",
+            View::Sprites => "sprites",
+        }
+    }
+
+    pub fn render(self, mem: &VideoMemory, palette: &Palette) -> Image {
+        match self {
+            View::Tiles   => tiles(mem, palette),
+            View::LowMap  => tile_map(mem, false, palette),
+            View::HighMap => tile_map(mem, true, palette),
+            View::Sprites => sprites(mem, palette),
+        }
+    }
+}
+
+/// Render the 384 tiles in VRAM, 16 tiles per row
+pub fn tiles(mem: &VideoMemory, palette: &Palette) -> Image {
+    let mut img = Image::new(16 * 8, 24 * 8, [0; 3]);
+
+    for tile in 0..384 {
+        let tx = (tile % 16) as u32 * 8;
+        let ty = (tile / 16) as u32 * 8;
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let c = mem.tile_pixel(tile, x, y);
+
+                img.set_pixel(tx + x as u32,
+                              ty + y as u32,
+                              palette.rgb(c, Source::Bgp));
+            }
+        }
+    }
+
+    img
+}
+
+/// Render the full 256x256 tile map at 0x9c00 if `high` is true,
+/// 0x9800 otherwise. The part of the map currently visible on screen
+/// is outlined.
+pub fn tile_map(mem: &VideoMemory, high: bool, palette: &Palette) -> Image {
+    let mut img = Image::new(256, 256, [0; 3]);
+
+    for y in 0..256 {
+        for x in 0..256 {
+            let c = mem.map_pixel(high, x as u8, y as u8);
+
+            img.set_pixel(x, y, palette.rgb(c, Source::Bgp));
+        }
+    }
+
+    if mem.bg_map_high() == high {
+        img.wrapped_rect(mem.scx() as u32,
+                         mem.scy() as u32,
+                         SCREEN_WIDTH,
+                         SCREEN_HEIGHT,
+                         VIEWPORT_COLOR);
+    }
+
+    if mem.window_map_high() == high {
+        // The top left corner of the window map is displayed at
+        // (WX - 7, WY)
+        let left = ::std::cmp::max(mem.wx() as i32 - 7, 0) as u32;
+        let top  = mem.wy() as u32;
+
+        if left < SCREEN_WIDTH && top < SCREEN_HEIGHT {
+            img.wrapped_rect(0, 0,
+                             SCREEN_WIDTH - left,
+                             SCREEN_HEIGHT - top,
+                             WINDOW_COLOR);
+        }
+    }
+
+    img
+}
+
+/// Render the 40 sprites in OAM on a grid of 8 columns
+pub fn sprites(mem: &VideoMemory, palette: &Palette) -> Image {
+    let mut img = Image::new(8 * SPRITE_CELL_W, 5 * SPRITE_CELL_H, [0; 3]);
+
+    let height = mem.sprite_height();
+
+    for (i, sprite) in mem.sprites().iter().enumerate() {
+        let cx = (i % 8) as u32 * SPRITE_CELL_W;
+        let cy = (i / 8) as u32 * SPRITE_CELL_H;
+
+        // Background of the cell, the transparent pixels are shown
+        // in this color
+        for y in 1..SPRITE_CELL_H - 1 {
+            for x in 1..SPRITE_CELL_W - 1 {
+                img.set_pixel(cx + x, cy + y, TRANSPARENT_COLOR);
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..8 {
+                if let Some((c, source)) = mem.sprite_pixel(sprite, x, y) {
+                    img.set_pixel(cx + 2 + x as u32,
+                                  cy + 2 + y as u32,
+                                  palette.rgb(c, source));
+                }
+            }
+        }
+    }
+
+    img
+}
+
+/// Return a textual description of the 40 sprites in OAM
+pub fn sprite_table(mem: &VideoMemory) -> String {
+    let mut s = String::from("  #    X    Y  TILE  PAL  FLIP  PRIO\n");
+
+    for (i, sprite) in mem.sprites().iter().enumerate() {
+        s.push_str(&format!("{:3} {:4} {:4}  0x{:02x}  {}  {}{}    {}\n",
+                            i,
+                            sprite.left_column(),
+                            sprite.top_line(),
+                            sprite.tile(),
+                            palette_name(sprite),
+                            if sprite.x_flip() { 'X' } else { '-' },
+                            if sprite.y_flip() { 'Y' } else { '-' },
+                            if sprite.background() { "BG" } else { "OBJ" }));
+    }
+
+    s
+}
+
+fn palette_name(sprite: &Sprite) -> &'static str {
+    match sprite.palette() {
+        ::gpu::sprite::Palette::Obp0 => "OBP0",
+        ::gpu::sprite::Palette::Obp1 => "OBP1",
+    }
+}
+
+/// Save all the views as PNG images in `dir` along with the sprite
+/// table as a text file. Returns the paths of the new files.
+pub fn save_views(dir: &Path,
+                  game: &str,
+                  mem: &VideoMemory,
+                  palette: &Palette) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for &view in View::all().iter() {
+        let img = view.render(mem, palette);
+
+        let path = ::capture::unique_path(dir,
+                                          game,
+                                          &format!("{}.png", view.name()));
+
+        let mut file = BufWriter::new(try!(File::create(&path)));
+
+        try!(::capture::png::write_png(&mut file,
+                                       img.width(),
+                                       img.height(),
+                                       img.rgb()));
+
+        paths.push(path);
+    }
+
+    let path = ::capture::unique_path(dir, game, "sprites.txt");
+
+    let mut file = try!(File::create(&path));
+
+    try!(file.write_all(sprite_table(mem).as_bytes()));
+
+    paths.push(path);
+
+    Ok(paths)
+}
+
+/// Each sprite is drawn in a cell large enough for 8x16 sprites with
+/// a border
+const SPRITE_CELL_W: u32 = 8 + 4;
+const SPRITE_CELL_H: u32 = 16 + 4;
+
+/// Outline of the background viewport in the tile map views
+const VIEWPORT_COLOR:    Rgb = [0xff, 0x00, 0x00];
+/// Outline of the visible part of the window in the tile map views
+const WINDOW_COLOR:      Rgb = [0x00, 0x00, 0xff];
+/// Color of the transparent sprite pixels
+const TRANSPARENT_COLOR: Rgb = [0xff, 0x00, 0xff];
+
+#[cfg(test)]
+mod tests {
+    use gpu::{Gpu, Color, Source};
+    use ui::palette::Palettes;
+
+    #[test]
+    fn views() {
+        let mut d = ::ui::dummy::DummyDisplay;
+        let mut gpu = Gpu::new(&mut d);
+
+        gpu.set_bgp(0xe4);
+        gpu.set_scx(250);
+        gpu.set_scy(10);
+
+        // First line of tile 1 is light grey
+        gpu.set_vram(0x10, 0xff);
+        gpu.set_vram(0x11, 0x00);
+
+        let mem     = gpu.video_memory();
+        let palette = Palettes::builtin();
+        let palette = palette.current();
+
+        let light = palette.rgb(Color::LightGrey, Source::Bgp);
+
+        let tiles = super::tiles(&mem, palette);
+
+        assert!(tiles.width() == 128 && tiles.height() == 192);
+        assert!(&tiles.rgb()[8 * 3..9 * 3] == &light);
+        assert!(&tiles.rgb()[7 * 3..8 * 3] != &light);
+
+        // The viewport wraps around the right edge of the map
+        let map = super::tile_map(&mem, false, palette);
+        let off = |x: usize, y: usize| (y * 256 + x) * 3;
+
+        assert!(&map.rgb()[off(250, 10)..off(251, 10)] == &super::VIEWPORT_COLOR);
+        assert!(&map.rgb()[off(153, 153)..off(154, 153)] == &super::VIEWPORT_COLOR);
+        assert!(&map.rgb()[off(154, 153)..off(155, 153)] != &super::VIEWPORT_COLOR);
+
+        let table = super::sprite_table(&mem);
+
+        assert!(table.lines().count() == 41);
+    }
+}